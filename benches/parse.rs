@@ -0,0 +1,96 @@
+// Benchmarks for the parser on large inputs.
+//
+// Run with `cargo bench`. Each benchmark parses a generated source a few
+// times and reports the best time and throughput, so runs can be compared
+// before and after a change to the parser.
+
+extern crate smoltok;
+
+use std::time::{Duration, Instant};
+
+use smoltok::parser::{parse_chunks, parse_statements};
+
+const METHODS: &[&str] = &[
+    "x: xValue y: yValue
+    \"Set the coordinates.\"
+    x <- xValue.
+    y <- yValue",
+    "+ delta
+    ^(x + delta x) @ (y + delta y)",
+    "dist: aPoint
+    | dx dy |
+    dx <- aPoint x - x.
+    dy <- aPoint y - y.
+    ^((dx * dx) + (dy * dy)) sqrt",
+    "printOn: aStream
+    aStream print: x; nextPutAll: ' @ '; print: y",
+    "isZero
+    ^x = 0 and: [y = 0]",
+    "rounded
+    ^(x rounded @ y rounded) ifTrue: [#(1 2.5 $a 'str' #sym (nested -3))] ifFalse: [16r1F]",
+    "do: aBlock
+    1 to: 10 do: [:i | | t | t <- i * 2. aBlock value: t]",
+];
+
+// A file of `classes` classes, each with a few sections of methods.
+fn chunk_file(classes: usize) -> String {
+    let mut src = String::new();
+    for i in 0..classes {
+        src.push_str(&format!(
+            "Object subclass: #Point{}
+    instanceVariableNames: 'x y'
+    classVariableNames: ''
+    category: 'Graphics-Primitives'!
+
+Point{} comment: 'A point in the plane.'!
+",
+            i, i
+        ));
+        for category in &["accessing", "arithmetic", "printing"] {
+            src.push_str(&format!("\n!Point{} methodsFor: '{}'!\n", i, category));
+            src.push_str(&METHODS.join("!\n"));
+            src.push_str("! !\n");
+        }
+    }
+    src
+}
+
+// A long sequence of statements.
+fn statements(count: usize) -> String {
+    let mut src = String::new();
+    for i in 0..count {
+        src.push_str(&format!(
+            "x{} <- (a foo: {} bar: #(1 $b 'c') + 3) baz; qux: [:y | y * 2.5e3]. ",
+            i, i
+        ));
+    }
+    src.push_str("^x0");
+    src
+}
+
+fn bench<T, F: Fn(&str) -> T>(name: &str, src: &str, parse: F) {
+    let mut best = Duration::MAX;
+    for _ in 0..5 {
+        let start = Instant::now();
+        let _ = parse(src);
+        best = best.min(start.elapsed());
+    }
+    let mb = src.len() as f64 / 1_000_000.0;
+    println!(
+        "{:<20} {:>8.1} KB {:>10.2} ms {:>8.2} MB/s",
+        name,
+        src.len() as f64 / 1000.0,
+        best.as_secs_f64() * 1000.0,
+        mb / best.as_secs_f64()
+    );
+}
+
+fn main() {
+    let chunks = chunk_file(200);
+    if let Err(e) = parse_chunks(&chunks) { panic!("{}", e) }
+    bench("parse_chunks", &chunks, parse_chunks);
+
+    let stmts = statements(5000);
+    assert!(parse_statements(&stmts).is_ok());
+    bench("parse_statements", &stmts, parse_statements);
+}
@@ -0,0 +1,513 @@
+// Random well-formed syntax trees, for property testing the parser against
+// the pretty printer.
+//
+// The generators only build trees that the parser could have produced:
+// identifiers are never reserved words, digits are valid in their radix, a
+// return only ever ends a statement list, and so on. Shrinking a tree keeps
+// it well-formed, so a failing property is reported with a minimal program.
+// Trees use every extension of the blue book syntax that the printer knows,
+// so they are parsed in a dialect which accepts them all.
+
+use quickcheck::{Arbitrary, Gen, QuickCheck};
+
+use parser::{Dialect, ParserConfig};
+use pretty::{print_expr, print_literal, print_method, Config};
+use syntax::*;
+
+// How deeply expressions nest.
+const MAX_DEPTH: usize = 3;
+
+const NAMES: &[&str] = &["a", "b", "x", "foo", "bar", "value", "Object", "aString", "x1"];
+const RESERVED: &[&str] = &["self", "super", "nil", "true", "false", "thisContext"];
+const BINARY_SELECTORS: &[&str] = &[
+    "+", "-", "*", "/", "//", "\\\\", "<", ">", "<=", ">=", "=", "~=", "==", "@", "%", "&", "|",
+    ",", "->", "==>", "~~", "<=>",
+];
+const LETTERS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const TEXT: &str = "abc XYZ 019 '\"!$#.:;()[]^|\n";
+
+fn below(g: &mut Gen, n: usize) -> usize {
+    usize::arbitrary(g) % n
+}
+
+fn one_in(g: &mut Gen, n: usize) -> bool {
+    below(g, n) == 0
+}
+
+fn pick(g: &mut Gen, chars: &str) -> char {
+    let chars: Vec<char> = chars.chars().collect();
+    *g.choose(&chars).unwrap()
+}
+
+fn name(g: &mut Gen) -> String {
+    if !one_in(g, 3) {
+        return String::from(*g.choose(NAMES).unwrap());
+    }
+    loop {
+        let mut name = pick(g, LETTERS).to_string();
+        for _ in 0..below(g, 6) {
+            name.push(if one_in(g, 4) { pick(g, &DIGITS[..10]) } else { pick(g, LETTERS) });
+        }
+        if !RESERVED.contains(&&name[..]) {
+            return name;
+        }
+    }
+}
+
+fn ident(g: &mut Gen) -> Ident {
+    mk_ident(&name(g))
+}
+
+fn keyword(g: &mut Gen) -> Ident {
+    mk_ident(&format!("{}:", name(g)))
+}
+
+fn binary_selector(g: &mut Gen) -> String {
+    String::from(*g.choose(BINARY_SELECTORS).unwrap())
+}
+
+fn text(g: &mut Gen, chars: &str) -> String {
+    (0..below(g, 8)).map(|_| pick(g, chars)).collect()
+}
+
+fn digits(g: &mut Gen, radix: u8) -> String {
+    let valid = &DIGITS[..radix as usize];
+    (0..1 + below(g, 4)).map(|_| pick(g, valid)).collect()
+}
+
+impl Arbitrary for Num {
+    fn arbitrary(g: &mut Gen) -> Num {
+        let radix = if one_in(g, 3) { Some(2 + below(g, 35) as u8) } else { None };
+        let base = radix.unwrap_or(10);
+        Num {
+            negative: bool::arbitrary(g),
+            radix,
+            integer: digits(g, base),
+            mantissa: if one_in(g, 3) { Some(digits(g, base)) } else { None },
+            exponent: if one_in(g, 4) { Some(below(g, 41) as i32 - 20) } else { None },
+            scale: if one_in(g, 6) { Some(below(g, 5) as u32) } else { None },
+        }
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Num>> {
+        let mut smaller = Vec::new();
+        if self.negative {
+            smaller.push(Num { negative: false, ..self.clone() });
+        }
+        if self.scale.is_some() {
+            smaller.push(Num { scale: None, ..self.clone() });
+        }
+        if self.exponent.is_some() {
+            smaller.push(Num { exponent: None, ..self.clone() });
+        }
+        if self.mantissa.is_some() {
+            smaller.push(Num { mantissa: None, ..self.clone() });
+        }
+        let decimal = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+        if self.radix.is_some()
+            && decimal(&self.integer)
+            && self.mantissa.as_ref().is_none_or(|m| decimal(m)) {
+            smaller.push(Num { radix: None, ..self.clone() });
+        }
+        if self.integer.len() > 1 {
+            smaller.push(Num { integer: self.integer[1..].to_string(), ..self.clone() });
+        }
+        Box::new(smaller.into_iter())
+    }
+}
+
+// A literal nesting arrays at most `depth` deep.
+fn literal(g: &mut Gen, depth: usize) -> Literal {
+    let span = Span::default();
+    match below(g, if depth == 0 { 5 } else { 6 }) {
+        0 => Literal::Number(Num::arbitrary(g), span),
+        1 => Literal::Char(pick(g, TEXT), span),
+        2 => Literal::Str(text(g, TEXT), span),
+        3 => Literal::Symbol(match below(g, 3) {
+            0 => Selector(name(g)),
+            1 => Selector(binary_selector(g)),
+            _ => Selector((0..1 + below(g, 3)).map(|_| keyword(g).0).collect()),
+        }, span),
+        4 => Literal::ByteArray((0..below(g, 4)).map(|_| u8::arbitrary(g)).collect(), span),
+        _ => {
+            let elems = (0..below(g, 4)).map(|_| element(g, depth - 1)).collect();
+            Literal::Array(elems, span)
+        }
+    }
+}
+
+// An element of a literal array, which may also be a constant.
+fn element(g: &mut Gen, depth: usize) -> Literal {
+    let span = Span::default();
+    match below(g, 8) {
+        0 => Literal::Bool(bool::arbitrary(g), span),
+        1 => Literal::Nil(span),
+        _ => literal(g, depth),
+    }
+}
+
+impl Arbitrary for Literal {
+    fn arbitrary(g: &mut Gen) -> Literal {
+        literal(g, MAX_DEPTH)
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Literal>> {
+        let span = Span::default();
+        match *self {
+            Literal::Number(ref n, _) =>
+                Box::new(n.shrink().map(move |n| Literal::Number(n, span))),
+            Literal::Str(ref s, _) =>
+                Box::new(s.shrink().map(move |s| Literal::Str(s, span))),
+            Literal::Array(ref elems, _) => {
+                let mut smaller: Vec<_> = elems.iter()
+                    .filter(|e| !matches!(**e, Literal::Bool(..) | Literal::Nil(_)))
+                    .cloned()
+                    .collect();
+                smaller.extend(elems.shrink().map(|elems| Literal::Array(elems, span)));
+                Box::new(smaller.into_iter())
+            }
+            Literal::ByteArray(ref bytes, _) =>
+                Box::new(bytes.shrink().map(move |b| Literal::ByteArray(b, span))),
+            Literal::Char(..) | Literal::Symbol(..) | Literal::Bool(..) | Literal::Nil(_) =>
+                Box::new(None.into_iter()),
+        }
+    }
+}
+
+fn expr(g: &mut Gen, depth: usize) -> Expr {
+    if depth == 0 {
+        return match below(g, 2) {
+            0 => Expr::Id(ident(g)),
+            _ => Expr::Lit(literal(g, 1)),
+        };
+    }
+    let span = Span::default();
+    let inner = depth - 1;
+    match below(g, 9) {
+        0 => Expr::Id(ident(g)),
+        1 => Expr::Lit(literal(g, 2)),
+        2 => Expr::Assign(ident(g), Box::new(expr(g, inner))),
+        3 | 4 => Expr::Message {
+            receiver: Box::new(expr(g, inner)),
+            selector: message(g, inner),
+            span,
+        },
+        5 => Expr::Cascade {
+            receiver: Box::new(expr(g, inner)),
+            messages: (0..2 + below(g, 2)).map(|_| message(g, inner)).collect(),
+            span,
+        },
+        6 => Expr::Block {
+            args: (0..below(g, 3)).map(|_| ident(g)).collect(),
+            temps: if one_in(g, 3) {
+                (0..1 + below(g, 2)).map(|_| ident(g)).collect()
+            } else {
+                Vec::new()
+            },
+            statements: statements(g, inner),
+            span,
+        },
+        7 => Expr::Brace { elements: (0..below(g, 4)).map(|_| expr(g, inner)).collect(), span },
+        _ => Expr::Pseudo(*g.choose(&PseudoVar::ALL).unwrap(), span),
+    }
+}
+
+fn message(g: &mut Gen, depth: usize) -> Msg {
+    match below(g, 3) {
+        0 => Msg::Unary(ident(g)),
+        1 => Msg::Binary(Selector(binary_selector(g)), Box::new(expr(g, depth))),
+        _ => Msg::Kwargs((0..1 + below(g, 3))
+            .map(|_| Keyword { id: keyword(g), val: expr(g, depth) })
+            .collect()),
+    }
+}
+
+// The arguments of a message.
+fn arguments(msg: &Msg) -> Vec<Expr> {
+    match *msg {
+        Msg::Unary(_) => vec![],
+        Msg::Binary(_, ref arg) => vec![(**arg).clone()],
+        Msg::Kwargs(ref kws) => kws.iter().map(|kw| kw.val.clone()).collect(),
+    }
+}
+
+// Smaller versions of a message: with fewer keywords, or a shrunk argument.
+fn shrink_message(msg: &Msg) -> Vec<Msg> {
+    let mut smaller = Vec::new();
+    match *msg {
+        Msg::Unary(_) => (),
+        Msg::Binary(ref sel, ref arg) => {
+            smaller.extend((**arg).shrink().map(|a| Msg::Binary(sel.clone(), Box::new(a))));
+        }
+        Msg::Kwargs(ref kws) => {
+            for i in 0..kws.len() {
+                if kws.len() > 1 {
+                    let mut fewer = kws.clone();
+                    fewer.remove(i);
+                    smaller.push(Msg::Kwargs(fewer));
+                }
+                for val in kws[i].val.shrink() {
+                    let mut shrunk = kws.clone();
+                    shrunk[i].val = val;
+                    smaller.push(Msg::Kwargs(shrunk));
+                }
+            }
+        }
+    }
+    smaller
+}
+
+fn statements(g: &mut Gen, depth: usize) -> Vec<Statement> {
+    let mut stmts: Vec<_> = (0..below(g, 4)).map(|_| Statement::E(expr(g, depth))).collect();
+    if one_in(g, 3) {
+        stmts.push(Statement::Ret(expr(g, depth), Span::default()));
+    }
+    stmts
+}
+
+// Smaller versions of a statement list: without one of the statements, or
+// with one of them shrunk.
+fn shrink_statements(stmts: &[Statement]) -> Vec<Vec<Statement>> {
+    let mut smaller = Vec::new();
+    for i in 0..stmts.len() {
+        let mut fewer = stmts.to_vec();
+        fewer.remove(i);
+        smaller.push(fewer);
+    }
+    for (i, stmt) in stmts.iter().enumerate() {
+        let (e, ret) = match *stmt {
+            Statement::E(ref e) => (e, false),
+            Statement::Ret(ref e, _) => (e, true),
+        };
+        for e in e.shrink() {
+            let mut shrunk = stmts.to_vec();
+            shrunk[i] = if ret { Statement::Ret(e, Span::default()) } else { Statement::E(e) };
+            smaller.push(shrunk);
+        }
+    }
+    smaller
+}
+
+impl Arbitrary for Expr {
+    fn arbitrary(g: &mut Gen) -> Expr {
+        expr(g, MAX_DEPTH)
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Expr>> {
+        let span = Span::default();
+        let mut smaller = Vec::new();
+        match *self {
+            Expr::Id(_) | Expr::Pseudo(..) | Expr::Method(_) | Expr::Error(_) => (),
+            Expr::Lit(ref lit) => smaller.extend(lit.shrink().map(Expr::Lit)),
+            Expr::Assign(ref id, ref val) => {
+                smaller.push((**val).clone());
+                smaller.extend((**val).shrink().map(|v| Expr::Assign(id.clone(), Box::new(v))));
+            }
+            Expr::Message { ref receiver, ref selector, .. } => {
+                let send = |receiver: Expr, selector: Msg| Expr::Message {
+                    receiver: Box::new(receiver),
+                    selector,
+                    span,
+                };
+                smaller.push((**receiver).clone());
+                smaller.extend(arguments(selector));
+                smaller.extend(shrink_message(selector).into_iter().map(|m| {
+                    send((**receiver).clone(), m)
+                }));
+                smaller.extend((**receiver).shrink().map(|r| send(r, selector.clone())));
+            }
+            Expr::Cascade { ref receiver, ref messages, .. } => {
+                let cascade = |receiver: Expr, messages: Vec<Msg>| Expr::Cascade {
+                    receiver: Box::new(receiver),
+                    messages,
+                    span,
+                };
+                smaller.push((**receiver).clone());
+                for (i, msg) in messages.iter().enumerate() {
+                    smaller.push(Expr::Message {
+                        receiver: receiver.clone(),
+                        selector: msg.clone(),
+                        span,
+                    });
+                    if messages.len() > 2 {
+                        let mut fewer = messages.clone();
+                        fewer.remove(i);
+                        smaller.push(cascade((**receiver).clone(), fewer));
+                    }
+                    for m in shrink_message(msg) {
+                        let mut shrunk = messages.clone();
+                        shrunk[i] = m;
+                        smaller.push(cascade((**receiver).clone(), shrunk));
+                    }
+                }
+                smaller.extend((**receiver).shrink().map(|r| cascade(r, messages.clone())));
+            }
+            Expr::Block { ref args, ref temps, ref statements, .. } => {
+                let block = |args: &[Ident], temps: &[Ident], statements: Vec<Statement>| {
+                    Expr::Block { args: args.to_vec(), temps: temps.to_vec(), statements, span }
+                };
+                for stmt in statements {
+                    match *stmt {
+                        Statement::E(ref e) | Statement::Ret(ref e, _) => smaller.push(e.clone()),
+                    }
+                }
+                if !args.is_empty() {
+                    smaller.push(block(&[], temps, statements.clone()));
+                }
+                if !temps.is_empty() {
+                    smaller.push(block(args, &[], statements.clone()));
+                }
+                smaller.extend(shrink_statements(statements).into_iter().map(|statements| {
+                    block(args, temps, statements)
+                }));
+            }
+            Expr::Brace { ref elements, .. } => {
+                smaller.extend(elements.iter().cloned());
+                smaller.extend(elements.shrink().map(|elements| Expr::Brace { elements, span }));
+            }
+        }
+        Box::new(smaller.into_iter())
+    }
+}
+
+impl Arbitrary for Method {
+    fn arbitrary(g: &mut Gen) -> Method {
+        let sig = match below(g, 3) {
+            0 => MsgPat::Unary(ident(g)),
+            1 => MsgPat::Bin(mk_ident(&binary_selector(g)), ident(g)),
+            _ => MsgPat::Kwargs((0..1 + below(g, 3))
+                .map(|_| KeyPat { keyword: keyword(g), var: ident(g) })
+                .collect()),
+        };
+        let comment = if one_in(g, 3) {
+            Some(text(g, TEXT).replace('"', ""))
+        } else {
+            None
+        };
+        let primitive = if one_in(g, 4) { Some(u32::arbitrary(g) % 1000) } else { None };
+        let pragmas = if one_in(g, 3) {
+            (0..1 + below(g, 2)).map(|_| pragma(g)).collect()
+        } else {
+            Vec::new()
+        };
+        let temps = if one_in(g, 2) {
+            Some((0..1 + below(g, 3)).map(|_| ident(g)).collect())
+        } else {
+            None
+        };
+        Method {
+            sig,
+            comment,
+            primitive,
+            pragmas,
+            temps,
+            stmts: Some(statements(g, MAX_DEPTH - 1)),
+            span: Span::default(),
+        }
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Method>> {
+        let mut smaller = Vec::new();
+        if self.comment.is_some() {
+            smaller.push(Method { comment: None, ..self.clone() });
+        }
+        if self.primitive.is_some() {
+            smaller.push(Method { primitive: None, ..self.clone() });
+        }
+        if !self.pragmas.is_empty() {
+            smaller.push(Method { pragmas: Vec::new(), ..self.clone() });
+        }
+        if self.temps.is_some() {
+            smaller.push(Method { temps: None, ..self.clone() });
+        }
+        if let Some(ref stmts) = self.stmts {
+            smaller.extend(shrink_statements(stmts).into_iter().map(|stmts| {
+                Method { stmts: Some(stmts), ..self.clone() }
+            }));
+        }
+        Box::new(smaller.into_iter())
+    }
+}
+
+fn pragma(g: &mut Gen) -> Pragma {
+    if one_in(g, 3) {
+        return Pragma { selector: Selector(name(g)), args: Vec::new(), span: Span::default() };
+    }
+    let keywords: Vec<_> = (0..1 + below(g, 2)).map(|_| keyword(g).0).collect();
+    Pragma {
+        selector: Selector(keywords.concat()),
+        args: keywords.iter().map(|_| literal(g, 1)).collect(),
+        span: Span::default(),
+    }
+}
+
+// The parser configuration for the generated trees, which the printer
+// spells with `<-` for assignment.
+fn dialect() -> ParserConfig {
+    ParserConfig { left_arrow_assignment: true, ..Dialect::Pharo.config() }
+}
+
+// Line width and indentation for printing, from very narrow to roomy.
+fn config(width: u8) -> Config {
+    Config { indent: 1 + width as usize % 4, width: width as usize }
+}
+
+fn check<A: Arbitrary + ::std::fmt::Debug>(prop: fn(A) -> bool) {
+    QuickCheck::new().tests(2000).quickcheck(prop)
+}
+
+#[test]
+fn prop_literal_round_trip() {
+    fn prop(lit: Literal) -> bool {
+        dialect().parse_literal(&print_literal(&lit)).spanless_eq(&Ok(lit))
+    }
+    check(prop as fn(Literal) -> bool);
+}
+
+#[test]
+fn prop_num_round_trip() {
+    fn prop(n: Num) -> bool {
+        let lit = Literal::Number(n, Span::default());
+        dialect().parse_literal(&print_literal(&lit)).spanless_eq(&Ok(lit))
+    }
+    check(prop as fn(Num) -> bool);
+}
+
+#[test]
+fn prop_expr_round_trip() {
+    fn prop(e: Expr) -> bool {
+        dialect().parse_expr(&print_expr(&e, &Config::default())).spanless_eq(&Ok(e))
+    }
+    check(prop as fn(Expr) -> bool);
+}
+
+#[test]
+fn prop_expr_round_trip_any_width() {
+    fn prop(args: (Expr, u8)) -> bool {
+        let (e, width) = args;
+        dialect().parse_expr(&print_expr(&e, &config(width))).spanless_eq(&Ok(e))
+    }
+    check(prop as fn((Expr, u8)) -> bool);
+}
+
+#[test]
+fn prop_method_round_trip() {
+    fn prop(args: (Method, u8)) -> bool {
+        let (m, width) = args;
+        let printed = print_method(&m, &config(width));
+        dialect().parse_method(&printed).spanless_eq(&Ok(m))
+    }
+    check(prop as fn((Method, u8)) -> bool);
+}
+
+#[test]
+fn prop_printing_is_stable() {
+    fn prop(args: (Method, u8)) -> bool {
+        let (m, width) = args;
+        let printed = print_method(&m, &config(width));
+        dialect().parse_method(&printed).map(|m| print_method(&m, &config(width))) == Ok(printed)
+    }
+    check(prop as fn((Method, u8)) -> bool);
+}
+
@@ -0,0 +1,1126 @@
+// The bytecode compiler.
+//
+// Lowers a resolved method to a CompiledMethod laid out the way chapter 27 of
+// the Blue Book describes it: a header, a literal frame and the bytecodes of
+// chapter 28.
+
+use std::fmt;
+
+use compiler::resolve::{self, resolve_do_it, resolve_method, Binding, ClassLayout, Diagnostic, Expr, Msg, Statement, Var};
+use number::NumberValue;
+use syntax::{self, Literal, PseudoVar, Selector, Span, SpanlessEq, Syntax};
+
+/// The number of temporaries and stack entries a small context has room for.
+/// Methods which need more have the large context flag set in their header.
+pub const SMALL_FRAME: usize = 12;
+
+/// The number of temporaries and stack entries a large context has room for.
+pub const LARGE_FRAME: usize = 32;
+
+/// The selectors sent by bytecodes 176 to 207, in order, with the number of
+/// arguments each takes. They need no literal.
+pub const SPECIAL_SELECTORS: [(&str, usize); 32] = [
+    ("+", 1),
+    ("-", 1),
+    ("<", 1),
+    (">", 1),
+    ("<=", 1),
+    (">=", 1),
+    ("=", 1),
+    ("~=", 1),
+    ("*", 1),
+    ("/", 1),
+    ("\\\\", 1),
+    ("@", 1),
+    ("bitShift:", 1),
+    ("//", 1),
+    ("bitAnd:", 1),
+    ("bitOr:", 1),
+    ("at:", 1),
+    ("at:put:", 2),
+    ("size", 0),
+    ("next", 0),
+    ("nextPut:", 1),
+    ("atEnd", 0),
+    ("==", 1),
+    ("class", 0),
+    ("blockCopy:", 1),
+    ("value", 0),
+    ("value:", 1),
+    ("do:", 1),
+    ("new", 0),
+    ("new:", 1),
+    ("x", 0),
+    ("y", 0),
+];
+
+/// Options for the compiler.
+///
+/// ```
+/// use smoltok::compiler::bytecode::CompilerConfig;
+/// use smoltok::compiler::resolve::ClassLayout;
+/// use smoltok::parser::parse_method;
+///
+/// let m = parse_method("max: n ^self > n ifTrue: [self] ifFalse: [n]").unwrap();
+/// let sends = CompilerConfig { inline_control_flow: false };
+/// let (inlined, _) = CompilerConfig::default().compile_method(&m, &ClassLayout::default()).unwrap();
+/// let (sent, _) = sends.compile_method(&m, &ClassLayout::default()).unwrap();
+/// assert_eq!(inlined.literals.len(), 0);
+/// assert_eq!(sent.literals.len(), 1);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompilerConfig {
+    /// Compile `ifTrue:ifFalse:`, `ifTrue:`, `ifFalse:`, `ifFalse:ifTrue:`,
+    /// `and:`, `or:`, `whileTrue:`, `whileFalse:` and `to:do:` as jumps when
+    /// their blocks are written out in place, rather than sending them.
+    pub inline_control_flow: bool,
+}
+
+impl CompilerConfig {
+    /// The compiler of the blue book, which inlines control flow.
+    pub const BLUE_BOOK: CompilerConfig = CompilerConfig { inline_control_flow: true };
+
+    /// Compile a method defined in a class laid out as `layout`.
+    pub fn compile_method(
+        &self,
+        method: &syntax::Method,
+        layout: &ClassLayout,
+    ) -> Result<(CompiledMethod, Vec<Diagnostic>), CompileError> {
+        let (m, diagnostics) = resolve_method(method, layout);
+        Ok((self.compile(&m)?, diagnostics))
+    }
+
+    /// Compile a doIt as the method `DoIt`.
+    pub fn compile_do_it(
+        &self,
+        do_it: &Syntax,
+        layout: &ClassLayout,
+    ) -> Result<(CompiledMethod, Vec<Diagnostic>), CompileError> {
+        let (mut m, diagnostics) = resolve_do_it(do_it, layout);
+        if let Some(last) = m.statements.pop() {
+            m.statements.push(match last {
+                Statement::E(e) => Statement::Ret(e, m.span),
+                ret => ret,
+            });
+        }
+        Ok((self.compile(&m)?, diagnostics))
+    }
+
+    /// Compile a method whose variables have been resolved.
+    pub fn compile(&self, method: &resolve::Method) -> Result<CompiledMethod, CompileError> {
+        let error = |kind| CompileError { kind, span: method.span };
+        let num_args = method.args.len();
+        if num_args > 31 {
+            return Err(error(CompileErrorKind::TooManyArguments(num_args)));
+        }
+        let primitive = match method.primitive {
+            Some(n) if n > 255 => return Err(error(CompileErrorKind::PrimitiveOutOfRange(n))),
+            Some(n) => Some(n as u8),
+            None => None,
+        };
+
+        let mut gen = Codegen {
+            inline_control_flow: self.inline_control_flow,
+            num_temps: method.num_temps,
+            ..Codegen::default()
+        };
+        gen.method_body(&method.statements)?;
+        // Inlined loops may have added temporaries of their own.
+        let num_temps = gen.num_temps;
+        if num_temps > 31 {
+            return Err(error(CompileErrorKind::TooManyTemporaries(num_temps)));
+        }
+
+        let quick = match gen.code[..] {
+            _ if primitive.is_some() || num_args > 0 || num_temps > 0 || !gen.literals.is_empty() => None,
+            [120] => Some(Quick::ReturnSelf),
+            [field, 124] if field < 16 => Some(Quick::ReturnInstVar(field as usize)),
+            _ => None,
+        };
+        let frame = num_temps + gen.max_depth;
+        if frame > LARGE_FRAME {
+            return Err(error(CompileErrorKind::FrameTooLarge(frame)));
+        }
+        let mut header = Header {
+            num_args,
+            num_temps,
+            primitive,
+            large_context: frame > SMALL_FRAME,
+            literal_count: 0,
+            quick,
+        };
+        if let Some(extension) = header.extension() {
+            gen.literals.push(MethodLiteral::Extension(extension));
+            gen.literals.push(MethodLiteral::MethodClass);
+        } else if gen.sends_super {
+            gen.literals.push(MethodLiteral::MethodClass);
+        }
+        if gen.literals.len() > 63 {
+            return Err(error(CompileErrorKind::TooManyLiterals(gen.literals.len())));
+        }
+        header.literal_count = gen.literals.len();
+        Ok(CompiledMethod { header, literals: gen.literals, bytecodes: gen.code })
+    }
+}
+
+impl Default for CompilerConfig {
+    fn default() -> Self {
+        CompilerConfig::BLUE_BOOK
+    }
+}
+
+/// A method ready for the interpreter.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CompiledMethod {
+    pub header: Header,
+    pub literals: Vec<MethodLiteral>,
+    pub bytecodes: Vec<u8>,
+}
+
+/// An entry in the literal frame of a method.
+#[derive(Debug, PartialEq, Clone)]
+pub enum MethodLiteral {
+    /// A literal object, such as a number, a string, or the symbol which is
+    /// the selector of a send.
+    Constant(Literal),
+    /// The association holding the value of a global, class or pool variable.
+    Variable(String),
+    /// The header extension of a method whose flag is 7. It comes second to
+    /// last in the frame.
+    Extension(u16),
+    /// The association whose value is the class the method is installed in,
+    /// for sends to `super`. It comes last in the frame.
+    MethodClass,
+}
+
+impl SpanlessEq for MethodLiteral {
+    fn spanless_eq(&self, other: &MethodLiteral) -> bool {
+        match (self, other) {
+            (MethodLiteral::Constant(a), MethodLiteral::Constant(b)) => a.spanless_eq(b),
+            _ => self == other,
+        }
+    }
+}
+
+/// The header of a method, which the interpreter reads to set up a context.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Header {
+    pub num_args: usize,
+    /// The size of the temporary frame, including the arguments.
+    pub num_temps: usize,
+    pub primitive: Option<u8>,
+    pub large_context: bool,
+    pub literal_count: usize,
+    pub quick: Option<Quick>,
+}
+
+/// A method simple enough that the interpreter runs it without making a
+/// context at all.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Quick {
+    /// `^self`.
+    ReturnSelf,
+    /// `^` an instance variable, by its field index.
+    ReturnInstVar(usize),
+}
+
+impl Header {
+    /// The top three bits of the header: the number of arguments from 0 to
+    /// 4, 5 for a quick return of self, 6 for a quick return of an instance
+    /// variable, and 7 when the arguments and primitive are in the header
+    /// extension.
+    pub fn flag(&self) -> u16 {
+        match self.quick {
+            Some(Quick::ReturnSelf) => 5,
+            Some(Quick::ReturnInstVar(_)) => 6,
+            None if self.primitive.is_none() && self.num_args <= 4 => self.num_args as u16,
+            None => 7,
+        }
+    }
+
+    /// The header as the SmallInteger object pointer stored in the method.
+    /// From the high bit: the flag, the temporary count (the field index for
+    /// a quick return of an instance variable), the large context flag, the
+    /// literal count, and the SmallInteger tag.
+    pub fn word(&self) -> u16 {
+        let temps = match self.quick {
+            Some(Quick::ReturnInstVar(field)) => field,
+            _ => self.num_temps,
+        };
+        self.flag() << 13
+            | (temps as u16) << 8
+            | u16::from(self.large_context) << 7
+            | (self.literal_count as u16) << 1
+            | 1
+    }
+
+    /// The header extension, when the flag is 7: the number of arguments
+    /// above the primitive index, and the SmallInteger tag.
+    pub fn extension(&self) -> Option<u16> {
+        if self.flag() != 7 {
+            return None;
+        }
+        Some((self.num_args as u16) << 9 | u16::from(self.primitive.unwrap_or(0)) << 1 | 1)
+    }
+}
+
+/// A method which cannot be encoded in the Blue Book's bytecode set.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CompileError {
+    pub kind: CompileErrorKind,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum CompileErrorKind {
+    /// An assignment to a method or block argument.
+    StoreIntoArgument(String),
+    TooManyArguments(usize),
+    TooManyTemporaries(usize),
+    TooManyLiterals(usize),
+    /// A variable whose index is too large for any bytecode which reaches it.
+    VariableOutOfRange(String),
+    /// The temporaries and stack need more room than a large context has.
+    FrameTooLarge(usize),
+    PrimitiveOutOfRange(u32),
+    JumpTooFar(usize),
+    /// Syntax the Blue Book has no bytecodes for.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let start = self.span.start;
+        write!(f, "line {}, column {}: ", start.line, start.column)?;
+        match self.kind {
+            CompileErrorKind::StoreIntoArgument(ref name) => write!(f, "cannot store into argument `{}`", name),
+            CompileErrorKind::TooManyArguments(n) => write!(f, "{} arguments is more than the limit of 31", n),
+            CompileErrorKind::TooManyTemporaries(n) => write!(f, "{} temporaries is more than the limit of 31", n),
+            CompileErrorKind::TooManyLiterals(n) => write!(f, "{} literals is more than the limit of 63", n),
+            CompileErrorKind::VariableOutOfRange(ref name) => write!(f, "`{}` is out of range of the bytecodes", name),
+            CompileErrorKind::FrameTooLarge(n) => {
+                write!(f, "the method needs {} fields, more than a large context has", n)
+            }
+            CompileErrorKind::PrimitiveOutOfRange(n) => write!(f, "primitive {} is more than the limit of 255", n),
+            CompileErrorKind::JumpTooFar(n) => write!(f, "a jump of {} bytes is too far", n),
+            CompileErrorKind::Unsupported(what) => write!(f, "{} cannot be compiled", what),
+        }
+    }
+}
+
+/// Compile a method defined in a class laid out as `layout`. The
+/// diagnostics from resolving its variables come back with it.
+///
+/// ```
+/// use smoltok::compiler::bytecode::{compile_method, MethodLiteral};
+/// use smoltok::compiler::resolve::ClassLayout;
+/// use smoltok::parser::parse_method;
+///
+/// let m = parse_method("isNil ^false").unwrap();
+/// let (method, diagnostics) = compile_method(&m, &ClassLayout::default()).unwrap();
+/// assert_eq!(method.bytecodes, vec![122]);
+/// assert_eq!(method.literals, Vec::<MethodLiteral>::new());
+/// assert!(diagnostics.is_empty());
+/// ```
+pub fn compile_method(
+    method: &syntax::Method,
+    layout: &ClassLayout,
+) -> Result<(CompiledMethod, Vec<Diagnostic>), CompileError> {
+    CompilerConfig::default().compile_method(method, layout)
+}
+
+/// Compile a doIt as the method `DoIt`, which answers the value of its last
+/// statement.
+pub fn compile_do_it(do_it: &Syntax, layout: &ClassLayout) -> Result<(CompiledMethod, Vec<Diagnostic>), CompileError> {
+    CompilerConfig::default().compile_do_it(do_it, layout)
+}
+
+/// Compile a method whose variables have been resolved.
+pub fn compile(method: &resolve::Method) -> Result<CompiledMethod, CompileError> {
+    CompilerConfig::default().compile(method)
+}
+
+// Where a variable lives, as the push and store bytecodes see it.
+enum Location {
+    Receiver(usize),
+    Temp(usize),
+    LiteralVariable(usize),
+}
+
+// One branch of an inlined conditional.
+enum Arm<'a> {
+    Block(&'a [Statement]),
+    // A constant, only pushed when the value is wanted.
+    Push(u8),
+}
+
+#[derive(Default)]
+struct Codegen {
+    inline_control_flow: bool,
+    // The size of the temporary frame so far.
+    num_temps: usize,
+    literals: Vec<MethodLiteral>,
+    code: Vec<u8>,
+    depth: usize,
+    max_depth: usize,
+    sends_super: bool,
+}
+
+impl Codegen {
+    // Emit `bytes`, which push `pushed` and then pop `popped` entries.
+    fn emit(&mut self, bytes: &[u8], pushed: usize, popped: usize) {
+        self.code.extend_from_slice(bytes);
+        self.depth += pushed;
+        self.max_depth = self.max_depth.max(self.depth);
+        self.depth -= popped;
+    }
+
+    fn literal(&mut self, lit: MethodLiteral) -> usize {
+        // Literals are shared wherever they appear in the method, whichever
+        // occurrence their spans came from.
+        match self.literals.iter().position(|l| l.spanless_eq(&lit)) {
+            Some(i) => i,
+            None => {
+                self.literals.push(lit);
+                self.literals.len() - 1
+            }
+        }
+    }
+
+    // Compile into a fresh buffer, and answer the bytecodes.
+    fn apart<F>(&mut self, f: F) -> Result<Vec<u8>, CompileError>
+    where
+        F: FnOnce(&mut Self) -> Result<(), CompileError>,
+    {
+        let outer = ::std::mem::take(&mut self.code);
+        let result = f(self);
+        let code = ::std::mem::replace(&mut self.code, outer);
+        result.map(|()| code)
+    }
+
+    fn method_body(&mut self, stmts: &[Statement]) -> Result<(), CompileError> {
+        for s in stmts {
+            match *s {
+                Statement::Ret(ref e, _) => {
+                    self.ret(e)?;
+                    return Ok(());
+                }
+                Statement::E(ref e) => self.effect(e)?,
+            }
+        }
+        self.emit(&[120], 0, 0);
+        Ok(())
+    }
+
+    // Return the value of `e` from the method.
+    fn ret(&mut self, e: &Expr) -> Result<(), CompileError> {
+        match *e {
+            Expr::Pseudo(PseudoVar::Self_, _) => self.emit(&[120], 0, 0),
+            Expr::Pseudo(PseudoVar::True, _) => self.emit(&[121], 0, 0),
+            Expr::Pseudo(PseudoVar::False, _) => self.emit(&[122], 0, 0),
+            Expr::Pseudo(PseudoVar::Nil, _) => self.emit(&[123], 0, 0),
+            _ => {
+                self.expr(e)?;
+                self.emit(&[124], 0, 1);
+            }
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, s: &Statement) -> Result<(), CompileError> {
+        match *s {
+            Statement::E(ref e) => self.effect(e),
+            Statement::Ret(ref e, _) => self.ret(e),
+        }
+    }
+
+    // Evaluate `e` for its effect, leaving the stack as it was.
+    fn effect(&mut self, e: &Expr) -> Result<(), CompileError> {
+        if let Expr::Message { ref receiver, ref msg, span } = *e {
+            if self.inline(receiver, msg, false, span)? {
+                return Ok(());
+            }
+        }
+        match *e {
+            Expr::Assign(ref var, ref val) => {
+                self.expr(val)?;
+                self.store(var, true)
+            }
+            _ => {
+                self.expr(e)?;
+                self.emit(&[135], 0, 1);
+                Ok(())
+            }
+        }
+    }
+
+    // Evaluate `e`, leaving its value on the stack.
+    fn expr(&mut self, e: &Expr) -> Result<(), CompileError> {
+        match *e {
+            Expr::Var(ref var) => self.push_var(var),
+            Expr::Assign(ref var, ref val) => {
+                self.expr(val)?;
+                self.store(var, false)
+            }
+            Expr::Lit(ref lit) => {
+                self.push_literal(lit);
+                Ok(())
+            }
+            Expr::Pseudo(var, _) => {
+                self.push_pseudo(var);
+                Ok(())
+            }
+            Expr::Message { ref receiver, ref msg, span } => {
+                if self.inline(receiver, msg, true, span)? {
+                    return Ok(());
+                }
+                self.expr(receiver)?;
+                self.send(msg, is_super(receiver), span)
+            }
+            Expr::Cascade { ref receiver, ref messages, span } => {
+                self.expr(receiver)?;
+                let to_super = is_super(receiver);
+                if let Some((last, rest)) = messages.split_last() {
+                    for msg in rest {
+                        self.emit(&[136], 1, 0);
+                        self.send(msg, to_super, span)?;
+                        self.emit(&[135], 0, 1);
+                    }
+                    self.send(last, to_super, span)?;
+                }
+                Ok(())
+            }
+            Expr::Block { ref args, ref statements, span, .. } => self.block(args, statements, span),
+            Expr::Brace { span, .. } => Err(CompileError { kind: CompileErrorKind::Unsupported("a brace array"), span }),
+            Expr::Method(ref m) => Err(CompileError { kind: CompileErrorKind::Unsupported("a nested method"), span: m.span }),
+            Expr::Error(span) => Err(CompileError { kind: CompileErrorKind::Unsupported("a syntax error"), span }),
+        }
+    }
+
+    // A block is made by sending `blockCopy:` to the active context, and its
+    // bytecodes follow inline, behind a jump which skips over them. They
+    // start by popping the arguments into their temporaries. The jump is
+    // always a long one, because `blockCopy:` finds the start of the block
+    // three bytes past its own send.
+    fn block(&mut self, args: &[Var], stmts: &[Statement], span: Span) -> Result<(), CompileError> {
+        self.emit(&[137], 1, 0);
+        self.push_integer(args.len() as i64);
+        self.send_selector(&Selector::new("blockCopy:"), 1, false, span)?;
+
+        let outer_code = ::std::mem::take(&mut self.code);
+        let outer_depth = ::std::mem::replace(&mut self.depth, args.len());
+        for arg in args.iter().rev() {
+            self.store_slot(arg, true)?;
+        }
+        match stmts.split_last() {
+            None => self.emit(&[115, 125], 1, 1),
+            Some((last, rest)) => {
+                for s in rest {
+                    self.statement(s)?;
+                }
+                match *last {
+                    Statement::E(ref e) => {
+                        self.expr(e)?;
+                        self.emit(&[125], 0, 1);
+                    }
+                    Statement::Ret(ref e, _) => self.ret(e)?,
+                }
+            }
+        }
+        let body = ::std::mem::replace(&mut self.code, outer_code);
+        self.depth = outer_depth;
+
+        self.code.extend(long_jump(body.len(), span)?);
+        self.code.extend(body);
+        Ok(())
+    }
+
+    // Compile `receiver msg` as jumps, when it is a control structure whose
+    // blocks are written out in place. Answers false, having emitted
+    // nothing, when it has to be sent after all.
+    fn inline(&mut self, receiver: &Expr, msg: &Msg, for_value: bool, span: Span) -> Result<bool, CompileError> {
+        if !self.inline_control_flow {
+            return Ok(false);
+        }
+        let blocks: Option<Vec<_>> = msg.args.iter().map(|a| literal_block(a, 0).map(|b| b.1)).collect();
+        match (msg.selector.as_str(), blocks) {
+            ("ifTrue:", Some(b)) => self.conditional(receiver, false, Arm::Block(b[0]), Arm::Push(115), for_value, span)?,
+            ("ifFalse:", Some(b)) => self.conditional(receiver, true, Arm::Block(b[0]), Arm::Push(115), for_value, span)?,
+            ("ifTrue:ifFalse:", Some(b)) => {
+                self.conditional(receiver, false, Arm::Block(b[0]), Arm::Block(b[1]), for_value, span)?
+            }
+            ("ifFalse:ifTrue:", Some(b)) => {
+                self.conditional(receiver, false, Arm::Block(b[1]), Arm::Block(b[0]), for_value, span)?
+            }
+            ("and:", Some(b)) => self.conditional(receiver, false, Arm::Block(b[0]), Arm::Push(114), for_value, span)?,
+            ("or:", Some(b)) => self.conditional(receiver, true, Arm::Block(b[0]), Arm::Push(113), for_value, span)?,
+            (sel @ "whileTrue:", Some(b)) | (sel @ "whileFalse:", Some(b)) => match literal_block(receiver, 0) {
+                Some((_, test)) => self.while_loop(test, b[0], sel == "whileTrue:", for_value, span)?,
+                None => return Ok(false),
+            },
+            ("to:do:", _) => match literal_block(&msg.args[1], 1) {
+                Some((args, body)) => self.counting_loop(receiver, &msg.args[0], &args[0], body, for_value, span)?,
+                None => return Ok(false),
+            },
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // Evaluate `receiver`, then `first` unless it is `skip_when`, and
+    // `second` if it is.
+    fn conditional(
+        &mut self,
+        receiver: &Expr,
+        skip_when: bool,
+        first: Arm,
+        second: Arm,
+        for_value: bool,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        self.expr(receiver)?;
+        // The conditional jump pops the receiver.
+        self.emit(&[], 0, 1);
+        let depth = self.depth;
+        let mut first = self.apart(|gen| gen.arm(first, for_value))?;
+        self.depth = depth;
+        let second = self.apart(|gen| gen.arm(second, for_value))?;
+        if !second.is_empty() {
+            first.extend(jump(second.len(), span)?);
+        }
+        self.code.extend(jump_if(skip_when, first.len(), span)?);
+        self.code.extend(first);
+        self.code.extend(second);
+        Ok(())
+    }
+
+    fn arm(&mut self, arm: Arm, for_value: bool) -> Result<(), CompileError> {
+        match arm {
+            Arm::Block(stmts) => self.inline_block(stmts, for_value)?,
+            Arm::Push(byte) if for_value => self.emit(&[byte], 1, 0),
+            Arm::Push(_) => (),
+        }
+        Ok(())
+    }
+
+    // The statements of a block written out in place. Its value is that of
+    // its last statement, or nil when it has none.
+    fn inline_block(&mut self, stmts: &[Statement], for_value: bool) -> Result<(), CompileError> {
+        let (last, rest) = match stmts.split_last() {
+            Some(split) => split,
+            None if for_value => {
+                self.emit(&[115], 1, 0);
+                return Ok(());
+            }
+            None => return Ok(()),
+        };
+        for s in rest {
+            self.statement(s)?;
+        }
+        match *last {
+            Statement::E(ref e) if for_value => self.expr(e),
+            Statement::E(ref e) => self.effect(e),
+            Statement::Ret(ref e, _) => {
+                self.ret(e)?;
+                // Nothing after the return runs, but the branches around
+                // it still expect a value.
+                if for_value {
+                    self.depth += 1;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn while_loop(
+        &mut self,
+        test: &[Statement],
+        body: &[Statement],
+        while_true: bool,
+        for_value: bool,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        let test = self.apart(|gen| {
+            gen.inline_block(test, true)?;
+            gen.emit(&[], 0, 1);
+            Ok(())
+        })?;
+        let body = self.apart(|gen| gen.inline_block(body, false))?;
+        let exit = jump_if(!while_true, body.len() + 2, span)?;
+        let back = jump_back(test.len() + exit.len() + body.len() + 2, span)?;
+        self.code.extend(test);
+        self.code.extend(exit);
+        self.code.extend(body);
+        self.code.extend(back);
+        if for_value {
+            self.emit(&[115], 1, 0);
+        }
+        Ok(())
+    }
+
+    // Count `counter` up from `start` to `stop`. Like a sent `to:do:`, the
+    // loop answers `start`.
+    fn counting_loop(
+        &mut self,
+        start: &Expr,
+        stop: &Expr,
+        counter: &Var,
+        body: &[Statement],
+        for_value: bool,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        self.expr(start)?;
+        if for_value {
+            self.emit(&[136], 1, 0);
+        }
+        self.store_slot(counter, true)?;
+        // The limit is only evaluated once, so unless it is a literal it is
+        // kept in a temporary of its own.
+        let limit = match *stop {
+            Expr::Lit(_) => None,
+            _ => {
+                self.expr(stop)?;
+                let slot = self.num_temps;
+                self.num_temps += 1;
+                self.emit(&temp_bytes(104, 130, slot), 0, 1);
+                Some(slot)
+            }
+        };
+        let test = self.apart(|gen| {
+            gen.push_var(counter)?;
+            match limit {
+                Some(slot) => gen.emit(&temp_bytes(16, 128, slot), 1, 0),
+                None => gen.expr(stop)?,
+            }
+            gen.send_selector(&Selector::new("<="), 1, false, span)?;
+            gen.emit(&[], 0, 1);
+            Ok(())
+        })?;
+        let body = self.apart(|gen| {
+            gen.inline_block(body, false)?;
+            gen.push_var(counter)?;
+            gen.push_integer(1);
+            gen.send_selector(&Selector::new("+"), 1, false, span)?;
+            gen.store_slot(counter, true)
+        })?;
+        let exit = jump_if(false, body.len() + 2, span)?;
+        let back = jump_back(test.len() + exit.len() + body.len() + 2, span)?;
+        self.code.extend(test);
+        self.code.extend(exit);
+        self.code.extend(body);
+        self.code.extend(back);
+        Ok(())
+    }
+
+    fn send(&mut self, msg: &Msg, to_super: bool, span: Span) -> Result<(), CompileError> {
+        for arg in &msg.args {
+            self.expr(arg)?;
+        }
+        self.send_selector(&msg.selector, msg.args.len(), to_super, span)
+    }
+
+    fn send_selector(&mut self, selector: &Selector, nargs: usize, to_super: bool, span: Span) -> Result<(), CompileError> {
+        if !to_super {
+            if let Some(i) = SPECIAL_SELECTORS.iter().position(|&(sel, _)| sel == selector.as_str()) {
+                self.emit(&[176 + i as u8], 0, nargs);
+                return Ok(());
+            }
+        }
+        let k = self.literal(MethodLiteral::Constant(Literal::Symbol(selector.clone(), Span::default())));
+        if k > 255 || nargs > 255 {
+            return Err(CompileError { kind: CompileErrorKind::TooManyLiterals(k + 1), span });
+        }
+        let (k, n) = (k as u8, nargs as u8);
+        let bytes = if to_super {
+            self.sends_super = true;
+            if k < 32 && n < 8 { vec![133, n << 5 | k] } else { vec![134, n, k] }
+        } else if n <= 2 && k < 16 {
+            vec![208 + 16 * n + k]
+        } else if k < 32 && n < 8 {
+            vec![131, n << 5 | k]
+        } else {
+            vec![132, n, k]
+        };
+        // The receiver and arguments are replaced by the result.
+        self.emit(&bytes, 0, nargs);
+        Ok(())
+    }
+
+    fn push_pseudo(&mut self, var: PseudoVar) {
+        let byte = match var {
+            PseudoVar::Self_ | PseudoVar::Super => 112,
+            PseudoVar::True => 113,
+            PseudoVar::False => 114,
+            PseudoVar::Nil => 115,
+            PseudoVar::ThisContext => 137,
+        };
+        self.emit(&[byte], 1, 0);
+    }
+
+    fn push_integer(&mut self, n: i64) {
+        match n {
+            -1..=2 => self.emit(&[(117 + n) as u8], 1, 0),
+            _ => {
+                let num = syntax::Num { negative: n < 0, ..syntax::Num::int_from_str(&n.abs().to_string()) };
+                self.push_literal(&Literal::Number(num, Span::default()));
+            }
+        }
+    }
+
+    fn push_literal(&mut self, lit: &Literal) {
+        if let Literal::Number(ref num, _) = *lit {
+            if let Ok(NumberValue::SmallInteger(n @ -1..=2)) = num.evaluate() {
+                self.emit(&[(117 + n) as u8], 1, 0);
+                return;
+            }
+        }
+        let k = self.literal(MethodLiteral::Constant(lit.clone()));
+        // Past 63 literals the method is rejected anyway, so the index
+        // only has to fit the extended push.
+        let bytes = if k < 32 { vec![32 + k as u8] } else { vec![128, 0x80 | (k & 0x3F) as u8] };
+        self.emit(&bytes, 1, 0);
+    }
+
+    fn location(&mut self, var: &Var) -> Location {
+        match var.binding {
+            Binding::Arg(i) | Binding::Temp(i) | Binding::BlockArg(i) => Location::Temp(i),
+            Binding::InstVar(i) => Location::Receiver(i),
+            Binding::ClassVar(_) | Binding::Pool(..) | Binding::Global | Binding::Undeclared => {
+                Location::LiteralVariable(self.literal(MethodLiteral::Variable(var.name.0.clone())))
+            }
+        }
+    }
+
+    fn push_var(&mut self, var: &Var) -> Result<(), CompileError> {
+        let bytes = match self.location(var) {
+            Location::Receiver(i) if i < 16 => vec![i as u8],
+            Location::Temp(i) if i < 16 => vec![16 + i as u8],
+            Location::LiteralVariable(k) if k < 32 => vec![64 + k as u8],
+            Location::Receiver(i) if i < 64 => vec![128, i as u8],
+            Location::Temp(i) if i < 64 => vec![128, 0x40 | i as u8],
+            Location::LiteralVariable(k) if k < 64 => vec![128, 0xC0 | k as u8],
+            _ => return Err(out_of_range(var)),
+        };
+        self.emit(&bytes, 1, 0);
+        Ok(())
+    }
+
+    fn store(&mut self, var: &Var, pop: bool) -> Result<(), CompileError> {
+        match var.binding {
+            Binding::Arg(_) | Binding::BlockArg(_) => Err(CompileError {
+                kind: CompileErrorKind::StoreIntoArgument(var.name.0.clone()),
+                span: var.name.1,
+            }),
+            _ => self.store_slot(var, pop),
+        }
+    }
+
+    // Store the top of the stack into `var`, popping it if `pop` is set.
+    fn store_slot(&mut self, var: &Var, pop: bool) -> Result<(), CompileError> {
+        let extended = if pop { 130 } else { 129 };
+        let bytes = match self.location(var) {
+            Location::Receiver(i) if pop && i < 8 => vec![96 + i as u8],
+            Location::Temp(i) if pop && i < 8 => vec![104 + i as u8],
+            Location::Receiver(i) if i < 64 => vec![extended, i as u8],
+            Location::Temp(i) if i < 64 => vec![extended, 0x40 | i as u8],
+            Location::LiteralVariable(k) if k < 64 => vec![extended, 0xC0 | k as u8],
+            _ => return Err(out_of_range(var)),
+        };
+        self.emit(&bytes, 0, usize::from(pop));
+        Ok(())
+    }
+}
+
+// The push or pop-and-store of temporary `i`, in its short form from
+// `short` when there is one and otherwise after the extended bytecode.
+fn temp_bytes(short: u8, extended: u8, i: usize) -> Vec<u8> {
+    let limit = if short == 16 { 16 } else { 8 };
+    if i < limit {
+        vec![short + i as u8]
+    } else {
+        vec![extended, 0x40 | i as u8]
+    }
+}
+
+// A jump forward over `distance` bytes, in as few bytes as possible.
+fn jump(distance: usize, span: Span) -> Result<Vec<u8>, CompileError> {
+    match distance {
+        1..=8 => Ok(vec![143 + distance as u8]),
+        _ => long_jump(distance, span),
+    }
+}
+
+fn long_jump(distance: usize, span: Span) -> Result<Vec<u8>, CompileError> {
+    if distance > 1023 {
+        return Err(CompileError { kind: CompileErrorKind::JumpTooFar(distance), span });
+    }
+    Ok(vec![164 + (distance >> 8) as u8, distance as u8])
+}
+
+// A jump back to `distance` bytes before the end of the jump itself.
+fn jump_back(distance: usize, span: Span) -> Result<Vec<u8>, CompileError> {
+    if distance > 1024 {
+        return Err(CompileError { kind: CompileErrorKind::JumpTooFar(distance), span });
+    }
+    let offset = -(distance as isize);
+    Ok(vec![(164 + offset.div_euclid(256)) as u8, offset.rem_euclid(256) as u8])
+}
+
+// Pop the top of the stack, and jump forward over `distance` bytes if it is
+// `when`. Only jumps on false have a short form.
+fn jump_if(when: bool, distance: usize, span: Span) -> Result<Vec<u8>, CompileError> {
+    match distance {
+        1..=8 if !when => Ok(vec![151 + distance as u8]),
+        0..=1023 => {
+            let base = if when { 168 } else { 172 };
+            Ok(vec![base + (distance >> 8) as u8, distance as u8])
+        }
+        _ => Err(CompileError { kind: CompileErrorKind::JumpTooFar(distance), span }),
+    }
+}
+
+// The arguments and statements of `e`, if it is a block taking `nargs`
+// arguments.
+fn literal_block(e: &Expr, nargs: usize) -> Option<(&[Var], &[Statement])> {
+    match *e {
+        Expr::Block { ref args, ref statements, .. } if args.len() == nargs => Some((args, statements)),
+        _ => None,
+    }
+}
+
+fn is_super(e: &Expr) -> bool {
+    matches!(*e, Expr::Pseudo(PseudoVar::Super, _))
+}
+
+fn out_of_range(var: &Var) -> CompileError {
+    CompileError { kind: CompileErrorKind::VariableOutOfRange(var.name.0.clone()), span: var.name.1 }
+}
+
+// Headers are written with their fields grouped, rather than by nibble.
+#[cfg(test)]
+#[allow(clippy::unusual_byte_groupings)]
+mod tests {
+    use super::*;
+    use parser::{parse_do_it, parse_method};
+
+    fn compile_in(layout: &ClassLayout, src: &str) -> CompiledMethod {
+        let m = parse_method(src).unwrap_or_else(|e| panic!("{}", e));
+        compile_method(&m, layout).unwrap_or_else(|e| panic!("{}", e)).0
+    }
+
+    fn rectangle() -> ClassLayout {
+        ClassLayout {
+            instance_vars: vec![String::from("origin"), String::from("corner")],
+            globals: vec![String::from("Transcript")],
+            ..ClassLayout::default()
+        }
+    }
+
+    fn symbol(s: &str) -> MethodLiteral {
+        MethodLiteral::Constant(Literal::Symbol(Selector::new(s), Span::default()))
+    }
+
+    // `center` and `intersects:` are the Blue Book's own examples, and these
+    // are the bytecodes the book lists for them, special sends included.
+    #[test]
+    fn test_rectangle_center() {
+        let m = compile_in(&rectangle(), "center ^origin + corner / 2");
+        // push origin, push corner, send +, push 2, send /, return.
+        assert_eq!(m.bytecodes, vec![0, 1, 176, 119, 185, 124]);
+        assert!(m.literals.is_empty());
+        assert_eq!(m.header.word(), 0b000_00000_0_000000_1);
+        assert_eq!(m.header.extension(), None);
+    }
+
+    #[test]
+    fn test_rectangle_intersects() {
+        let m = compile_in(
+            &rectangle(),
+            "intersects: aRectangle
+                | rOrigin rCorner |
+                rOrigin <- aRectangle origin.
+                rCorner <- aRectangle corner.
+                rCorner x < origin x ifTrue: [^false].
+                rCorner y < origin y ifTrue: [^false].
+                rOrigin x > corner x ifTrue: [^false].
+                rOrigin y > corner y ifTrue: [^false].
+                ^true",
+        );
+        assert_eq!(
+            m.bytecodes,
+            vec![
+                16, 208, 105, // rOrigin <- aRectangle origin
+                16, 209, 106, // rCorner <- aRectangle corner
+                18, 206, 0, 206, 178, 152, 122, // rCorner x < origin x ifTrue: [^false]
+                18, 207, 0, 207, 178, 152, 122, // rCorner y < origin y ifTrue: [^false]
+                17, 206, 1, 206, 179, 152, 122, // rOrigin x > corner x ifTrue: [^false]
+                17, 207, 1, 207, 179, 152, 122, // rOrigin y > corner y ifTrue: [^false]
+                121,
+            ]
+        );
+        assert_eq!(m.literals, vec![symbol("origin"), symbol("corner")]);
+        assert_eq!(m.header.word(), 0b001_00011_0_000010_1);
+    }
+
+    // The rest are not in the book, so they check this compiler's output
+    // against bytecodes worked out by hand from the book's encoding.
+    #[test]
+    fn test_small_integer_plus() {
+        let m = compile_in(&ClassLayout::default(), "+ aNumber <primitive: 1> ^super + aNumber");
+        // push self, push aNumber, send + to super, return.
+        assert_eq!(m.bytecodes, vec![112, 16, 133, 0b001_00000, 124]);
+        assert_eq!(m.literals, vec![symbol("+"), MethodLiteral::Extension(0b00_00001_00000001_1), MethodLiteral::MethodClass]);
+        assert_eq!(m.header.flag(), 7);
+        assert_eq!(m.header.word(), 0b111_00001_0_000011_1);
+    }
+
+    #[test]
+    fn test_quick_methods() {
+        let m = compile_in(&rectangle(), "corner ^corner");
+        assert_eq!(m.bytecodes, vec![1, 124]);
+        assert_eq!(m.header.quick, Some(Quick::ReturnInstVar(1)));
+        assert_eq!(m.header.word(), 0b110_00001_0_000000_1);
+
+        let m = compile_in(&rectangle(), "yourself");
+        assert_eq!(m.bytecodes, vec![120]);
+        assert_eq!(m.header.word(), 0b101_00000_0_000000_1);
+
+        let m = compile_in(&rectangle(), "origin: aPoint origin <- aPoint");
+        assert_eq!(m.bytecodes, vec![16, 96, 120]);
+        assert_eq!(m.header.quick, None);
+        assert_eq!(m.header.word(), 0b001_00001_0_000000_1);
+    }
+
+    #[test]
+    fn test_statements_and_cascades() {
+        let m = compile_in(&rectangle(), "show | t | corner <- nil. t <- Transcript show: 'hi'; cr. ^t");
+        assert_eq!(
+            m.bytecodes,
+            vec![
+                115, 97, // corner <- nil
+                64, 136, 33, 226, 135, 211, // Transcript show: 'hi'; cr
+                104, // t <- ...
+                16, 124, // ^t
+            ]
+        );
+        assert_eq_ignoring_spans!(
+            m.literals,
+            vec![
+                MethodLiteral::Variable(String::from("Transcript")),
+                MethodLiteral::Constant(Literal::Str(String::from("hi"), Span::default())),
+                symbol("show:"),
+                symbol("cr"),
+            ]
+        );
+        assert_eq!(m.header.num_temps, 1);
+    }
+
+    #[test]
+    fn test_blocks() {
+        let m = compile_in(&rectangle(), "collect: aBlock ^[:x :y | | t | t <- x. y] value: 3 value: []");
+        assert_eq!(
+            m.bytecodes,
+            vec![
+                137, 119, 200, // thisContext blockCopy: 2
+                164, 6, // jump over the block
+                106, 105, // pop y, pop x
+                17, 107, // t <- x
+                18, 125, // y
+                32, // push 3
+                137, 117, 200, 164, 2, 115, 125, // []
+                240 + 1, // send value:value:
+                124,
+            ]
+        );
+        assert_eq!(m.header.num_temps, 4);
+        assert_eq!(m.literals[1], symbol("value:value:"));
+
+        let m = compile_in(&rectangle(), "foo ^[origin. corner. origin. corner. origin]");
+        assert_eq!(m.bytecodes[..5], [137, 117, 200, 164, 10]);
+    }
+
+    #[test]
+    fn test_inlined_control_flow() {
+        let m = compile_in(&rectangle(), "max: a ^self > a ifTrue: [self] ifFalse: [a]");
+        assert_eq!(
+            m.bytecodes,
+            vec![
+                112, 16, 179, // self > a
+                153, // jump on false over the true arm
+                112, 144, // self, jump over the false arm
+                16, 124,
+            ]
+        );
+        assert!(m.literals.is_empty());
+
+        let m = compile_in(&rectangle(), "count | i | i <- 0. [i < 3] whileTrue: [i <- i + 1]");
+        assert_eq!(
+            m.bytecodes,
+            vec![
+                117, 104, // i <- 0
+                16, 32, 178, // i < 3
+                157, // jump on false out of the loop
+                16, 118, 176, 104, // i <- i + 1
+                163, 246, // jump back to the test
+                120,
+            ]
+        );
+
+        let m = compile_in(&rectangle(), "isEmpty ^origin isNil or: [corner isNil]");
+        assert_eq!(m.bytecodes, vec![0, 208, 168, 3, 1, 208, 144, 113, 124]);
+
+        let m = compile_in(&rectangle(), "sum | s | s <- 0. 1 to: 10 do: [:i | s <- s + i]. ^s");
+        assert_eq!(
+            m.bytecodes,
+            vec![
+                117, 104, // s <- 0
+                118, 105, // i <- 1
+                17, 32, 180, // i <= 10
+                172, 10, // jump on false out of the loop, too far for the short form
+                16, 17, 176, 104, // s <- s + i
+                17, 118, 176, 105, // i <- i + 1
+                163, 241, // jump back to the test
+                16, 124,
+            ]
+        );
+
+        // A block in a variable is sent the message.
+        let m = compile_in(&rectangle(), "when: aBlock ^origin isNil ifTrue: aBlock");
+        assert_eq!(m.bytecodes, vec![0, 208, 16, 225, 124]);
+    }
+
+    #[test]
+    fn test_extended_forms_and_large_contexts() {
+        let layout = ClassLayout {
+            instance_vars: (0..21).map(|i| format!("v{}", i)).collect(),
+            ..ClassLayout::default()
+        };
+        let m = compile_in(&layout, "foo | a b c d e f g h i j k | i <- v20 <- v9. ^k");
+        assert_eq!(m.bytecodes, vec![9, 129, 20, 130, 0x48, 16 + 10, 124]);
+        assert_eq!(m.header.num_temps, 11);
+        assert!(!m.header.large_context);
+
+        let m = compile_in(&layout, "foo | a b c d e f g h i j k | ^k + k");
+        assert!(m.header.large_context);
+        assert_eq!(m.header.word() >> 7 & 1, 1);
+    }
+
+    #[test]
+    fn test_do_it() {
+        let do_it = parse_do_it("| a | a <- 3. a + 4").unwrap();
+        let (m, _) = compile_do_it(&do_it, &ClassLayout::default()).unwrap();
+        assert_eq!(m.bytecodes, vec![32, 104, 16, 33, 176, 124]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let m = parse_method("foo: x x <- 3").unwrap();
+        let err = compile_method(&m, &ClassLayout::default()).unwrap_err();
+        assert_eq!(err.kind, CompileErrorKind::StoreIntoArgument(String::from("x")));
+        assert_eq!(err.to_string(), "line 1, column 8: cannot store into argument `x`");
+
+        let m = parse_method("foo <primitive: 300>").unwrap();
+        let err = compile_method(&m, &ClassLayout::default()).unwrap_err();
+        assert_eq!(err.kind, CompileErrorKind::PrimitiveOutOfRange(300));
+    }
+}
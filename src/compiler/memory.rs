@@ -23,6 +23,12 @@ pub struct Heap {
     pub curr_idx: Pointer,
 }
 
+impl Default for Heap {
+    fn default() -> Self {
+        Heap::new()
+    }
+}
+
 impl Heap {
     pub fn new() -> Self {
         Heap {
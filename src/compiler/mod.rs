@@ -1,2 +1,4 @@
 pub mod naive;
 pub mod memory;
+pub mod resolve;
+pub mod bytecode;
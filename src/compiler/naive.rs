@@ -1,5 +1,1118 @@
-// a naive translation of the smalltalk compiler
+// A naive interpreter for compiled methods.
+//
+// Objects are Rust values and contexts are Rust structs, rather than words in
+// an object memory, but the bytecodes run the way chapter 28 of the Blue Book
+// describes. The classes and methods it starts with are filed in from
+// `kernel.st`.
 
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use compiler::bytecode::{CompileError, CompiledMethod, CompilerConfig, MethodLiteral, Quick, SPECIAL_SELECTORS};
+use compiler::resolve::ClassLayout;
+use number::{NumberValue, SMALL_INTEGER_MAX, SMALL_INTEGER_MIN};
+use parser::{parse_chunks, parse_do_it, SyntaxError};
+use syntax::{self, Chunk, ClassDef, ClassKind, Literal, Syntax};
+
+const KERNEL: &str = include_str!("kernel.st");
+
+/// An object.
+///
+/// Strings and arrays compare equal when their contents are; everything else
+/// compares by identity, as `==` does.
+#[derive(Clone)]
+pub enum Value {
+    Nil,
+    True,
+    False,
+    Int(i16),
+    Char(char),
+    Str(Rc<str>),
+    Symbol(Rc<str>),
+    Array(Rc<RefCell<Vec<Value>>>),
+    Object(Rc<Instance>),
+    Class(Rc<Class>),
+    Block(Rc<Block>),
+    Context(Rc<RefCell<Context>>),
+}
+
+impl Value {
+    fn bool(b: bool) -> Value {
+        if b {
+            Value::True
+        } else {
+            Value::False
+        }
+    }
+
+    // Whether the two are the same object.
+    fn identical(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) | (Value::True, Value::True) | (Value::False, Value::False) => true,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Char(a), Value::Char(b)) => a == b,
+            // Symbols are unique.
+            (Value::Symbol(a), Value::Symbol(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => Rc::ptr_eq(a, b),
+            (Value::Array(a), Value::Array(b)) => Rc::ptr_eq(a, b),
+            (Value::Object(a), Value::Object(b)) => Rc::ptr_eq(a, b),
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            (Value::Block(a), Value::Block(b)) => Rc::ptr_eq(a, b),
+            (Value::Context(a), Value::Context(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Array(a), Value::Array(b)) => *a.borrow() == *b.borrow(),
+            _ => self.identical(other),
+        }
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Nil => write!(f, "nil"),
+            Value::True => write!(f, "true"),
+            Value::False => write!(f, "false"),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Char(c) => write!(f, "${}", c),
+            Value::Str(ref s) => write!(f, "'{}'", s.replace('\'', "''")),
+            Value::Symbol(ref s) => write!(f, "#{}", s),
+            Value::Array(ref elems) => {
+                write!(f, "#(")?;
+                for (i, e) in elems.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{:?}", e)?;
+                }
+                write!(f, ")")
+            }
+            Value::Object(ref o) => write!(f, "a {}", o.class.name),
+            Value::Class(ref c) => write!(f, "{}", c.name),
+            Value::Block(_) => write!(f, "a BlockContext"),
+            Value::Context(ref c) if c.borrow().home.is_some() => write!(f, "a BlockContext"),
+            Value::Context(_) => write!(f, "a MethodContext"),
+        }
+    }
+}
+
+/// An instance of a class defined in Smalltalk, with its named fields.
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: RefCell<Vec<Value>>,
+}
+
+pub struct Class {
+    pub name: String,
+    pub superclass: Option<Rc<Class>>,
+    pub kind: ClassKind,
+    /// Every instance variable, inherited ones first.
+    pub instance_vars: Vec<String>,
+    /// Every class variable, inherited ones first.
+    pub class_vars: Vec<String>,
+    // The values of the class variables this class declares. Subclasses
+    // share them.
+    class_var_values: RefCell<HashMap<String, Value>>,
+    methods: RefCell<HashMap<String, Rc<Method>>>,
+    class_methods: RefCell<HashMap<String, Rc<Method>>>,
+}
+
+impl Class {
+    // The class, from this one up its superclasses, which declares the
+    // class variable `name`.
+    fn class_var_holder(&self, name: &str) -> Option<&Class> {
+        let mut next = Some(self);
+        while let Some(class) = next {
+            if class.class_var_values.borrow().contains_key(name) {
+                return Some(class);
+            }
+            next = class.superclass.as_deref();
+        }
+        None
+    }
+}
+
+// A compiled method, with the class it is installed in for sends to super.
+struct Method {
+    compiled: CompiledMethod,
+    class: Rc<Class>,
+    meta: bool,
+}
+
+/// A block, made by `blockCopy:`. Each evaluation runs in a context of its
+/// own, which shares the temporary frame of the block's home context.
+pub struct Block {
+    home: Rc<RefCell<Context>>,
+    num_args: usize,
+    initial_ip: usize,
+}
+
+/// An activation of a method or block.
+pub struct Context {
+    method: Rc<Method>,
+    receiver: Value,
+    temps: Rc<RefCell<Vec<Value>>>,
+    stack: Vec<Value>,
+    ip: usize,
+    sender: Option<Rc<RefCell<Context>>>,
+    // The method context a block context was made in.
+    home: Option<Rc<RefCell<Context>>>,
+    // Set when a method context returns, after which its blocks cannot
+    // return from it again.
+    returned: bool,
+}
+
+/// Why a program stopped.
+#[derive(Debug)]
+pub enum RunError {
+    Syntax(SyntaxError),
+    Compile(CompileError),
+    DoesNotUnderstand { class: String, selector: String },
+    /// A global variable which has not been defined.
+    Undeclared(String),
+    UnknownClass(String),
+    /// A `^` in a block whose method has already returned.
+    CannotReturn,
+    /// A conditional jump found an object other than true or false.
+    NonBoolean(String),
+    /// Sent by `Object>>error:`.
+    Error(String),
+    /// A literal the interpreter has no objects for, such as a Float.
+    Unsupported(String),
+    InvalidBytecode(u8),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RunError::Syntax(ref e) => write!(f, "{}", e),
+            RunError::Compile(ref e) => write!(f, "{}", e),
+            RunError::DoesNotUnderstand { ref class, ref selector } => {
+                write!(f, "{} does not understand #{}", class, selector)
+            }
+            RunError::Undeclared(ref name) => write!(f, "`{}` is not defined", name),
+            RunError::UnknownClass(ref name) => write!(f, "there is no class named {}", name),
+            RunError::CannotReturn => write!(f, "cannot return from a method which has already returned"),
+            RunError::NonBoolean(ref class) => write!(f, "expected true or false, not a {}", class),
+            RunError::Error(ref message) => write!(f, "error: {}", message),
+            RunError::Unsupported(ref what) => write!(f, "{} is not supported", what),
+            RunError::InvalidBytecode(byte) => write!(f, "invalid bytecode {}", byte),
+        }
+    }
+}
+
+impl From<SyntaxError> for RunError {
+    fn from(e: SyntaxError) -> Self {
+        RunError::Syntax(e)
+    }
+}
+
+impl From<CompileError> for RunError {
+    fn from(e: CompileError) -> Self {
+        RunError::Compile(e)
+    }
+}
+
+/// The result of a primitive.
+enum Outcome {
+    Answer(Value),
+    // The primitive made a new context active.
+    Activated,
+    Failed,
+}
+
+/// Runs Smalltalk code compiled to bytecodes.
+///
+/// ```
+/// use smoltok::compiler::naive::{Interpreter, Value};
+///
+/// let mut interpreter = Interpreter::new();
+/// let sum = interpreter.evaluate("| s | s <- 0. 1 to: 10 do: [:i | s <- s + i]. s").unwrap();
+/// assert_eq!(sum, Value::Int(55));
+/// ```
 pub struct Interpreter {
+    config: CompilerConfig,
+    classes: HashMap<String, Rc<Class>>,
+    globals: HashMap<String, Value>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter::with_config(CompilerConfig::default())
+    }
+
+    /// An interpreter which compiles what it is given with `config`. The
+    /// kernel is always compiled as the Blue Book's is, with control flow
+    /// inlined, because `BlockContext>>whileTrue:` is written in terms of
+    /// itself.
+    pub fn with_config(config: CompilerConfig) -> Self {
+        let mut interpreter = Interpreter {
+            config: CompilerConfig::BLUE_BOOK,
+            classes: HashMap::new(),
+            globals: HashMap::new(),
+        };
+        if let Err(e) = interpreter.file_in(KERNEL) {
+            panic!("the kernel does not file in: {}", e);
+        }
+        interpreter.config = config;
+        interpreter
+    }
+
+    /// File in source in the chunk format: define its classes, install its
+    /// methods and run its doIts.
+    pub fn file_in(&mut self, src: &str) -> Result<(), RunError> {
+        for chunk in parse_chunks(src)? {
+            match chunk {
+                Chunk::Class(def) => self.define_class(&def)?,
+                Chunk::Methods(category) => {
+                    let class = self.class(&category.class.0)?;
+                    for m in &category.methods {
+                        self.install(&class, category.meta, m)?;
+                    }
+                }
+                Chunk::DoIt { temps, statements, .. } => {
+                    self.run_do_it(&Syntax::DoIt { temps, statements })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluate a doIt, with nil as the receiver, and answer the value of its
+    /// last statement.
+    pub fn evaluate(&mut self, src: &str) -> Result<Value, RunError> {
+        let do_it = parse_do_it(src)?;
+        self.run_do_it(&do_it)
+    }
+
+    fn run_do_it(&mut self, do_it: &Syntax) -> Result<Value, RunError> {
+        let class = self.class("UndefinedObject")?;
+        let (compiled, _) = self.config.compile_do_it(do_it, &self.layout(&class, false))?;
+        let method = Rc::new(Method { compiled, class, meta: false });
+        self.run(method, Value::Nil, Vec::new())
+    }
+
+    fn class(&self, name: &str) -> Result<Rc<Class>, RunError> {
+        self.classes.get(name).cloned().ok_or_else(|| RunError::UnknownClass(String::from(name)))
+    }
+
+    fn define_class(&mut self, def: &ClassDef) -> Result<(), RunError> {
+        let superclass = match def.superclass {
+            Some(ref name) => Some(self.class(&name.0)?),
+            None => None,
+        };
+        let mut instance_vars = superclass.as_ref().map_or_else(Vec::new, |s| s.instance_vars.clone());
+        instance_vars.extend(def.instance_vars.iter().cloned());
+        let mut class_vars = superclass.as_ref().map_or_else(Vec::new, |s| s.class_vars.clone());
+        class_vars.extend(def.class_vars.iter().cloned());
+        let class_var_values = def.class_vars.iter().map(|v| (v.clone(), Value::Nil)).collect();
+        let class = Rc::new(Class {
+            name: def.name.0.clone(),
+            superclass,
+            kind: def.kind,
+            instance_vars,
+            class_vars,
+            class_var_values: RefCell::new(class_var_values),
+            methods: RefCell::new(HashMap::new()),
+            class_methods: RefCell::new(HashMap::new()),
+        });
+        self.classes.insert(class.name.clone(), class.clone());
+        self.globals.insert(class.name.clone(), Value::Class(class.clone()));
+        for category in &def.categories {
+            for m in &category.methods {
+                self.install(&class, category.meta, m)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn layout(&self, class: &Class, meta: bool) -> ClassLayout {
+        ClassLayout {
+            instance_vars: if meta { Vec::new() } else { class.instance_vars.clone() },
+            class_vars: class.class_vars.clone(),
+            globals: self.globals.keys().cloned().collect(),
+            ..ClassLayout::default()
+        }
+    }
+
+    fn install(&mut self, class: &Rc<Class>, meta: bool, m: &syntax::Method) -> Result<(), RunError> {
+        let (compiled, _) = self.config.compile_method(m, &self.layout(class, meta))?;
+        let methods = if meta { &class.class_methods } else { &class.methods };
+        let method = Method { compiled, class: class.clone(), meta };
+        methods.borrow_mut().insert(m.sig.selector().0, Rc::new(method));
+        Ok(())
+    }
+
+    // The class a message to `v` is looked up in, and whether the lookup is
+    // on the class side.
+    fn class_of(&self, v: &Value) -> Result<(Rc<Class>, bool), RunError> {
+        let name = match *v {
+            Value::Nil => "UndefinedObject",
+            Value::True => "True",
+            Value::False => "False",
+            Value::Int(_) => "SmallInteger",
+            Value::Char(_) => "Character",
+            Value::Str(_) => "String",
+            Value::Symbol(_) => "Symbol",
+            Value::Array(_) => "Array",
+            Value::Object(ref o) => return Ok((o.class.clone(), false)),
+            Value::Class(ref c) => return Ok((c.clone(), true)),
+            Value::Block(_) => "BlockContext",
+            Value::Context(ref c) if c.borrow().home.is_some() => "BlockContext",
+            Value::Context(_) => "MethodContext",
+        };
+        Ok((self.class(name)?, false))
+    }
+
+    // Find the method for `selector`, starting at `class`. Class side
+    // lookups end in the instance methods of `Class`.
+    fn lookup(&self, class: Option<Rc<Class>>, meta: bool, selector: &str) -> Option<Rc<Method>> {
+        let mut next = class;
+        while let Some(class) = next {
+            let methods = if meta { &class.class_methods } else { &class.methods };
+            if let Some(m) = methods.borrow().get(selector) {
+                return Some(m.clone());
+            }
+            next = class.superclass.clone();
+        }
+        if meta {
+            self.lookup(self.classes.get("Class").cloned(), false, selector)
+        } else {
+            None
+        }
+    }
+
+    fn run(&mut self, method: Rc<Method>, receiver: Value, args: Vec<Value>) -> Result<Value, RunError> {
+        let mut active = Rc::new(RefCell::new(activation(method, receiver, args, None)));
+        loop {
+            if let Some(result) = self.step(&mut active)? {
+                return Ok(result);
+            }
+        }
+    }
+
+    // Run one bytecode of the active context. Answers the result when the
+    // context the run started with returns.
+    fn step(&mut self, active: &mut Rc<RefCell<Context>>) -> Result<Option<Value>, RunError> {
+        let byte = self.fetch(active)?;
+        match byte {
+            0..=15 => self.push_receiver_variable(active, byte as usize)?,
+            16..=31 => self.push_temp(active, byte as usize - 16),
+            32..=63 => self.push_literal_constant(active, byte as usize - 32)?,
+            64..=95 => self.push_literal_variable(active, byte as usize - 64)?,
+            96..=103 => {
+                let v = pop(active);
+                self.store_receiver_variable(active, byte as usize - 96, v)?;
+            }
+            104..=111 => {
+                let v = pop(active);
+                store_temp(active, byte as usize - 104, v);
+            }
+            112 => {
+                let receiver = active.borrow().receiver.clone();
+                push(active, receiver);
+            }
+            113 => push(active, Value::True),
+            114 => push(active, Value::False),
+            115 => push(active, Value::Nil),
+            116..=119 => push(active, Value::Int(i16::from(byte) - 117)),
+            120 => {
+                let receiver = active.borrow().receiver.clone();
+                return self.return_from_method(active, receiver);
+            }
+            121 => return self.return_from_method(active, Value::True),
+            122 => return self.return_from_method(active, Value::False),
+            123 => return self.return_from_method(active, Value::Nil),
+            124 => {
+                let v = pop(active);
+                return self.return_from_method(active, v);
+            }
+            125 => {
+                let v = pop(active);
+                let sender = active.borrow().sender.clone();
+                return Ok(resume(active, sender, v));
+            }
+            128 => {
+                let next = self.fetch(active)? as usize;
+                let i = next & 0x3F;
+                match next >> 6 {
+                    0 => self.push_receiver_variable(active, i)?,
+                    1 => self.push_temp(active, i),
+                    2 => self.push_literal_constant(active, i)?,
+                    _ => self.push_literal_variable(active, i)?,
+                }
+            }
+            129 | 130 => {
+                let next = self.fetch(active)? as usize;
+                let v = if byte == 130 { pop(active) } else { top(active) };
+                let i = next & 0x3F;
+                match next >> 6 {
+                    0 => self.store_receiver_variable(active, i, v)?,
+                    1 => store_temp(active, i, v),
+                    2 => return Err(RunError::InvalidBytecode(byte)),
+                    _ => self.store_literal_variable(active, i, v)?,
+                }
+            }
+            131 | 133 => {
+                let next = self.fetch(active)?;
+                self.send_literal(active, next as usize & 0x1F, next as usize >> 5, byte == 133)?;
+            }
+            132 | 134 => {
+                let nargs = self.fetch(active)? as usize;
+                let k = self.fetch(active)? as usize;
+                self.send_literal(active, k, nargs, byte == 134)?;
+            }
+            135 => {
+                pop(active);
+            }
+            136 => {
+                let v = top(active);
+                push(active, v);
+            }
+            137 => {
+                let this = Value::Context(active.clone());
+                push(active, this);
+            }
+            144..=151 => jump(active, byte as isize - 143),
+            152..=159 => self.jump_if(active, false, byte as isize - 151)?,
+            160..=167 => {
+                let next = self.fetch(active)? as isize;
+                jump(active, (byte as isize - 164) * 256 + next);
+            }
+            168..=175 => {
+                let next = self.fetch(active)? as isize;
+                let when = byte < 172;
+                let high = if when { byte - 168 } else { byte - 172 };
+                self.jump_if(active, when, high as isize * 256 + next)?;
+            }
+            176..=207 => {
+                if !self.special_arithmetic(active, byte) {
+                    let (selector, nargs) = SPECIAL_SELECTORS[byte as usize - 176];
+                    self.send(active, selector, nargs, false)?;
+                }
+            }
+            208..=255 => {
+                let nargs = (byte as usize - 208) / 16;
+                self.send_literal(active, byte as usize & 0x0F, nargs, false)?;
+            }
+            _ => return Err(RunError::InvalidBytecode(byte)),
+        }
+        Ok(None)
+    }
+
+    fn fetch(&self, active: &Rc<RefCell<Context>>) -> Result<u8, RunError> {
+        let mut ctx = active.borrow_mut();
+        let byte = ctx.method.compiled.bytecodes.get(ctx.ip).cloned();
+        ctx.ip += 1;
+        // Every method and block ends in a return.
+        byte.ok_or(RunError::InvalidBytecode(0))
+    }
+
+    fn push_receiver_variable(&self, active: &Rc<RefCell<Context>>, i: usize) -> Result<(), RunError> {
+        let v = match active.borrow().receiver {
+            Value::Object(ref o) => o.fields.borrow().get(i).cloned(),
+            _ => None,
+        };
+        let v = v.ok_or(RunError::InvalidBytecode(i as u8))?;
+        push(active, v);
+        Ok(())
+    }
+
+    fn store_receiver_variable(&self, active: &Rc<RefCell<Context>>, i: usize, v: Value) -> Result<(), RunError> {
+        match active.borrow().receiver {
+            Value::Object(ref o) if i < o.fields.borrow().len() => {
+                o.fields.borrow_mut()[i] = v;
+                Ok(())
+            }
+            _ => Err(RunError::InvalidBytecode(96 + i as u8)),
+        }
+    }
+
+    fn push_temp(&self, active: &Rc<RefCell<Context>>, i: usize) {
+        let v = active.borrow().temps.borrow()[i].clone();
+        push(active, v);
+    }
+
+    fn literal(&self, active: &Rc<RefCell<Context>>, k: usize) -> Result<MethodLiteral, RunError> {
+        let ctx = active.borrow();
+        let lit = ctx.method.compiled.literals.get(k).cloned();
+        lit.ok_or(RunError::InvalidBytecode(k as u8))
+    }
+
+    fn push_literal_constant(&self, active: &Rc<RefCell<Context>>, k: usize) -> Result<(), RunError> {
+        let v = match self.literal(active, k)? {
+            MethodLiteral::Constant(ref lit) => literal_value(lit)?,
+            _ => return Err(RunError::InvalidBytecode(32 + k as u8)),
+        };
+        push(active, v);
+        Ok(())
+    }
+
+    fn literal_variable_name(&self, active: &Rc<RefCell<Context>>, k: usize) -> Result<String, RunError> {
+        match self.literal(active, k)? {
+            MethodLiteral::Variable(name) => Ok(name),
+            _ => Err(RunError::InvalidBytecode(64 + k as u8)),
+        }
+    }
+
+    // Literal variables are the class variables of the method's class, as
+    // the compiler found them, and otherwise globals.
+    fn push_literal_variable(&self, active: &Rc<RefCell<Context>>, k: usize) -> Result<(), RunError> {
+        let name = self.literal_variable_name(active, k)?;
+        let class = active.borrow().method.class.clone();
+        let v = match class.class_var_holder(&name) {
+            Some(holder) => holder.class_var_values.borrow()[&name].clone(),
+            None => self.globals.get(&name).cloned().ok_or(RunError::Undeclared(name))?,
+        };
+        push(active, v);
+        Ok(())
+    }
+
+    fn store_literal_variable(&mut self, active: &Rc<RefCell<Context>>, k: usize, v: Value) -> Result<(), RunError> {
+        let name = self.literal_variable_name(active, k)?;
+        let class = active.borrow().method.class.clone();
+        match class.class_var_holder(&name) {
+            Some(holder) => holder.class_var_values.borrow_mut().insert(name, v),
+            None => self.globals.insert(name, v),
+        };
+        Ok(())
+    }
+
+    fn jump_if(&self, active: &Rc<RefCell<Context>>, when: bool, offset: isize) -> Result<(), RunError> {
+        match pop(active) {
+            Value::True if when => jump(active, offset),
+            Value::False if !when => jump(active, offset),
+            Value::True | Value::False => (),
+            v => return Err(RunError::NonBoolean(self.class_of(&v)?.0.name.clone())),
+        }
+        Ok(())
+    }
+
+    // Do the arithmetic of bytecodes 176 to 191 in place when the receiver
+    // and argument are SmallIntegers and the result is one too, rather than
+    // looking up the method. Answers false when the message has to be sent.
+    fn special_arithmetic(&self, active: &Rc<RefCell<Context>>, byte: u8) -> bool {
+        let primitive = match byte {
+            176..=186 => byte - 175,
+            188 => 17,
+            189 => 12,
+            190 => 14,
+            191 => 15,
+            // `@` makes a Point, and the rest are not arithmetic.
+            _ => return false,
+        };
+        let mut ctx = active.borrow_mut();
+        let n = ctx.stack.len();
+        let result = match ctx.stack[n.saturating_sub(2)..] {
+            [Value::Int(a), Value::Int(b)] => small_integer_primitive(primitive, a, b),
+            _ => None,
+        };
+        match result {
+            Some(v) => {
+                ctx.stack.truncate(n - 2);
+                ctx.stack.push(v);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn send_literal(
+        &mut self,
+        active: &mut Rc<RefCell<Context>>,
+        k: usize,
+        nargs: usize,
+        to_super: bool,
+    ) -> Result<(), RunError> {
+        let selector = match self.literal(active, k)? {
+            MethodLiteral::Constant(Literal::Symbol(sel, _)) => sel.0,
+            _ => return Err(RunError::InvalidBytecode(k as u8)),
+        };
+        self.send(active, &selector, nargs, to_super)
+    }
+
+    fn send(
+        &mut self,
+        active: &mut Rc<RefCell<Context>>,
+        selector: &str,
+        nargs: usize,
+        to_super: bool,
+    ) -> Result<(), RunError> {
+        let (receiver, args) = {
+            let mut ctx = active.borrow_mut();
+            let at = ctx.stack.len() - nargs;
+            let args = ctx.stack.split_off(at);
+            (ctx.stack.pop().unwrap_or(Value::Nil), args)
+        };
+        let (class, meta) = if to_super {
+            let ctx = active.borrow();
+            (ctx.method.class.superclass.clone(), ctx.method.meta)
+        } else {
+            let (class, meta) = self.class_of(&receiver)?;
+            (Some(class), meta)
+        };
+        let method = match self.lookup(class, meta, selector) {
+            Some(m) => m,
+            None => {
+                return Err(RunError::DoesNotUnderstand {
+                    class: self.class_of(&receiver)?.0.name.clone(),
+                    selector: String::from(selector),
+                })
+            }
+        };
+        self.activate(active, method, receiver, args)
+    }
+
+    fn activate(
+        &mut self,
+        active: &mut Rc<RefCell<Context>>,
+        method: Rc<Method>,
+        receiver: Value,
+        args: Vec<Value>,
+    ) -> Result<(), RunError> {
+        match method.compiled.header.quick {
+            Some(Quick::ReturnSelf) => {
+                push(active, receiver);
+                return Ok(());
+            }
+            Some(Quick::ReturnInstVar(i)) => {
+                if let Value::Object(ref o) = receiver {
+                    let v = o.fields.borrow()[i].clone();
+                    push(active, v);
+                    return Ok(());
+                }
+            }
+            None => (),
+        }
+        if let Some(index) = method.compiled.header.primitive {
+            match self.primitive(index, active, &receiver, &args)? {
+                Outcome::Answer(v) => {
+                    push(active, v);
+                    return Ok(());
+                }
+                Outcome::Activated => return Ok(()),
+                Outcome::Failed => (),
+            }
+        }
+        let ctx = activation(method, receiver, args, Some(active.clone()));
+        *active = Rc::new(RefCell::new(ctx));
+        Ok(())
+    }
+
+    fn return_from_method(&mut self, active: &mut Rc<RefCell<Context>>, v: Value) -> Result<Option<Value>, RunError> {
+        let home = active.borrow().home.clone().unwrap_or_else(|| active.clone());
+        if home.borrow().returned {
+            return Err(RunError::CannotReturn);
+        }
+        home.borrow_mut().returned = true;
+        let sender = home.borrow().sender.clone();
+        Ok(resume(active, sender, v))
+    }
+
+    fn primitive(
+        &mut self,
+        index: u8,
+        active: &mut Rc<RefCell<Context>>,
+        receiver: &Value,
+        args: &[Value],
+    ) -> Result<Outcome, RunError> {
+        let answer = match (index, receiver, args) {
+            (1..=17, Value::Int(a), [Value::Int(b)]) => small_integer_primitive(index, *a, *b),
+            (60, _, [Value::Int(i)]) => at(receiver, *i),
+            (61, Value::Array(elems), [Value::Int(i), v]) => {
+                let mut elems = elems.borrow_mut();
+                match elems.get_mut((*i as usize).wrapping_sub(1)) {
+                    Some(slot) if *i > 0 => {
+                        *slot = v.clone();
+                        Some(v.clone())
+                    }
+                    _ => None,
+                }
+            }
+            (62, Value::Array(elems), []) => Some(Value::Int(elems.borrow().len() as i16)),
+            (62, Value::Str(s), []) | (62, Value::Symbol(s), []) => {
+                Some(Value::Int(s.chars().count() as i16))
+            }
+            (70, Value::Class(class), []) if class.kind == ClassKind::Fixed => {
+                let fields = vec![Value::Nil; class.instance_vars.len()];
+                Some(Value::Object(Rc::new(Instance { class: class.clone(), fields: RefCell::new(fields) })))
+            }
+            (71, Value::Class(class), [Value::Int(n)]) if class.name == "Array" && *n >= 0 => {
+                Some(Value::Array(Rc::new(RefCell::new(vec![Value::Nil; *n as usize]))))
+            }
+            (80, Value::Context(ctx), [Value::Int(n)]) if *n >= 0 => {
+                let ctx = ctx.borrow();
+                let home = ctx.home.clone().unwrap_or_else(|| active.clone());
+                // The block starts after the long jump which follows this
+                // send.
+                Some(Value::Block(Rc::new(Block { home, num_args: *n as usize, initial_ip: ctx.ip + 2 })))
+            }
+            (81, Value::Block(block), _) if block.num_args == args.len() => {
+                let home = block.home.borrow();
+                let ctx = Context {
+                    method: home.method.clone(),
+                    receiver: home.receiver.clone(),
+                    temps: home.temps.clone(),
+                    stack: args.to_vec(),
+                    ip: block.initial_ip,
+                    sender: Some(active.clone()),
+                    home: Some(block.home.clone()),
+                    returned: false,
+                };
+                *active = Rc::new(RefCell::new(ctx));
+                return Ok(Outcome::Activated);
+            }
+            (110, _, [other]) => Some(Value::bool(receiver.identical(other))),
+            (111, _, []) => {
+                let (class, meta) = self.class_of(receiver)?;
+                if meta {
+                    // Metaclasses are not objects here.
+                    None
+                } else {
+                    Some(Value::Class(class))
+                }
+            }
+            (255, _, [Value::Str(message)]) => return Err(RunError::Error(message.to_string())),
+            _ => None,
+        };
+        Ok(answer.map_or(Outcome::Failed, Outcome::Answer))
+    }
+}
+
+/// Run one of the SmallInteger primitives, 1 to 17, whose numbers follow
+/// the Blue Book. Answers `None` when the primitive fails, as it does when
+/// the result is not a SmallInteger.
+pub fn small_integer_primitive(index: u8, a: i16, b: i16) -> Option<Value> {
+    let (a, b) = (i32::from(a), i32::from(b));
+    let n = match index {
+        1 => a + b,
+        2 => a - b,
+        3 => return Some(Value::bool(a < b)),
+        4 => return Some(Value::bool(a > b)),
+        5 => return Some(Value::bool(a <= b)),
+        6 => return Some(Value::bool(a >= b)),
+        7 => return Some(Value::bool(a == b)),
+        8 => return Some(Value::bool(a != b)),
+        9 => a * b,
+        // Only exact division answers a SmallInteger.
+        10 if b != 0 && a % b == 0 => a / b,
+        11 if b != 0 => a.rem_euclid(b) + if b < 0 && a.rem_euclid(b) != 0 { b } else { 0 },
+        12 if b != 0 => a.div_euclid(b) - if b < 0 && a.rem_euclid(b) != 0 { 1 } else { 0 },
+        13 if b != 0 => a / b,
+        14 => a & b,
+        15 => a | b,
+        16 => a ^ b,
+        17 if b >= 0 => a.checked_shl(b as u32).filter(|n| n >> b == a)?,
+        17 => a >> (-b).min(31),
+        _ => return None,
+    };
+    if n < i32::from(SMALL_INTEGER_MIN) || n > i32::from(SMALL_INTEGER_MAX) {
+        None
+    } else {
+        Some(Value::Int(n as i16))
+    }
+}
+
+fn activation(method: Rc<Method>, receiver: Value, mut args: Vec<Value>, sender: Option<Rc<RefCell<Context>>>) -> Context {
+    args.resize(method.compiled.header.num_temps, Value::Nil);
+    Context {
+        method,
+        receiver,
+        temps: Rc::new(RefCell::new(args)),
+        stack: Vec::new(),
+        ip: 0,
+        sender,
+        home: None,
+        returned: false,
+    }
+}
+
+// Make `sender` active, with `v` pushed on its stack. Answers `v` when
+// there is no sender to return to.
+fn resume(active: &mut Rc<RefCell<Context>>, sender: Option<Rc<RefCell<Context>>>, v: Value) -> Option<Value> {
+    match sender {
+        Some(sender) => {
+            sender.borrow_mut().stack.push(v);
+            *active = sender;
+            None
+        }
+        None => Some(v),
+    }
+}
+
+fn push(active: &Rc<RefCell<Context>>, v: Value) {
+    active.borrow_mut().stack.push(v);
+}
+
+fn pop(active: &Rc<RefCell<Context>>) -> Value {
+    active.borrow_mut().stack.pop().unwrap_or(Value::Nil)
+}
+
+fn top(active: &Rc<RefCell<Context>>) -> Value {
+    active.borrow().stack.last().cloned().unwrap_or(Value::Nil)
+}
+
+fn store_temp(active: &Rc<RefCell<Context>>, i: usize, v: Value) {
+    active.borrow().temps.borrow_mut()[i] = v;
+}
+
+fn jump(active: &Rc<RefCell<Context>>, offset: isize) {
+    let mut ctx = active.borrow_mut();
+    ctx.ip = (ctx.ip as isize + offset) as usize;
+}
+
+fn at(receiver: &Value, i: i16) -> Option<Value> {
+    let i = (i as usize).checked_sub(1)?;
+    match *receiver {
+        Value::Array(ref elems) => elems.borrow().get(i).cloned(),
+        Value::Str(ref s) | Value::Symbol(ref s) => s.chars().nth(i).map(Value::Char),
+        _ => None,
+    }
+}
+
+fn literal_value(lit: &Literal) -> Result<Value, RunError> {
+    Ok(match *lit {
+        Literal::Number(ref n, _) => match n.evaluate() {
+            Ok(NumberValue::SmallInteger(n)) => Value::Int(n),
+            _ => return Err(RunError::Unsupported(format!("the number {:?}", n))),
+        },
+        Literal::Char(c, _) => Value::Char(c),
+        Literal::Str(ref s, _) => Value::Str(Rc::from(s.as_str())),
+        Literal::Symbol(ref s, _) => Value::Symbol(Rc::from(s.as_str())),
+        Literal::Array(ref elems, _) => {
+            let elems = elems.iter().map(literal_value).collect::<Result<_, _>>()?;
+            Value::Array(Rc::new(RefCell::new(elems)))
+        }
+        Literal::ByteArray(ref bytes, _) => {
+            let elems = bytes.iter().map(|&b| Value::Int(i16::from(b))).collect();
+            Value::Array(Rc::new(RefCell::new(elems)))
+        }
+        Literal::Bool(b, _) => Value::bool(b),
+        Literal::Nil(_) => Value::Nil,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(interpreter: &mut Interpreter, src: &str) -> Value {
+        interpreter.evaluate(src).unwrap_or_else(|e| panic!("{}: {}", src, e))
+    }
+
+    // Run `src` compiled with and without inlining, and check that both
+    // answer `expected`.
+    fn same_both_ways(src: &str, expected: Value) {
+        let mut inlined = Interpreter::new();
+        let mut sent = Interpreter::with_config(CompilerConfig { inline_control_flow: false });
+        assert_eq!(run(&mut inlined, src), expected, "inlined: {}", src);
+        assert_eq!(run(&mut sent, src), expected, "sent: {}", src);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(run(&mut interpreter, "3 + 4 * 2"), Value::Int(14));
+        assert_eq!(run(&mut interpreter, "-7 \\\\ 2"), Value::Int(1));
+        assert_eq!(run(&mut interpreter, "-7 // 2"), Value::Int(-4));
+        assert_eq!(run(&mut interpreter, "-7 quo: 2"), Value::Int(-3));
+        assert_eq!(run(&mut interpreter, "1 bitShift: 4"), Value::Int(16));
+        assert_eq!(run(&mut interpreter, "3 = 3"), Value::True);
+        assert_eq!(run(&mut interpreter, "3 = #a"), Value::False);
+        match interpreter.evaluate("16383 + 1") {
+            Err(RunError::Error(ref message)) => assert_eq!(message, "a primitive has failed"),
+            r => panic!("expected overflow to fail, got {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_conditionals() {
+        same_both_ways("3 > 2 ifTrue: ['yes'] ifFalse: ['no']", Value::Str(Rc::from("yes")));
+        same_both_ways("3 < 2 ifTrue: ['yes'] ifFalse: ['no']", Value::Str(Rc::from("no")));
+        same_both_ways("3 < 2 ifFalse: ['yes'] ifTrue: ['no']", Value::Str(Rc::from("yes")));
+        same_both_ways("3 < 2 ifTrue: ['yes']", Value::Nil);
+        same_both_ways("3 < 2 ifFalse: ['no']", Value::Str(Rc::from("no")));
+        same_both_ways("| x | x <- 0. 3 > 2 ifTrue: [x <- 1]. x", Value::Int(1));
+        same_both_ways("| x | x <- 0. 3 > 2 ifFalse: [x <- 1]. x", Value::Int(0));
+        same_both_ways("3 > 2 ifTrue: []", Value::Nil);
+        // Not literal blocks, so these are sent even when inlining.
+        same_both_ways("| b | b <- ['yes']. 3 > 2 ifTrue: b ifFalse: ['no']", Value::Str(Rc::from("yes")));
+    }
+
+    #[test]
+    fn test_and_or() {
+        same_both_ways("3 > 2 and: [2 > 1]", Value::True);
+        same_both_ways("3 > 2 and: [2 > 3]", Value::False);
+        same_both_ways("3 < 2 and: [nil foo]", Value::False);
+        same_both_ways("3 > 2 or: [nil foo]", Value::True);
+        same_both_ways("3 < 2 or: [2 < 3]", Value::True);
+        same_both_ways("| n | n <- 0. (3 < 2 or: [n <- 5. false]) ifFalse: [n]", Value::Int(5));
+    }
+
+    #[test]
+    fn test_loops() {
+        same_both_ways("| i | i <- 0. [i < 10] whileTrue: [i <- i + 1]. i", Value::Int(10));
+        same_both_ways("| i | i <- 0. [i >= 10] whileFalse: [i <- i + 3]. i", Value::Int(12));
+        same_both_ways("| i | i <- 0. [i < 10] whileTrue: [i <- i + 1]", Value::Nil);
+        same_both_ways("| s | s <- 0. 1 to: 10 do: [:i | s <- s + i]. s", Value::Int(55));
+        same_both_ways("| s | s <- 0. 5 to: 1 do: [:i | s <- s + i]. s", Value::Int(0));
+        same_both_ways("| n | n <- 3. 1 to: n + 1 do: [:i | n <- n + i]. n", Value::Int(13));
+        same_both_ways("1 to: 3 do: [:i | i]", Value::Int(1));
+        same_both_ways("| s b | s <- 0. b <- [:i | s <- s + i]. 1 to: 4 do: b. s", Value::Int(10));
+        same_both_ways("| i c | i <- 0. c <- [i < 5]. c whileTrue: [i <- i + 1]. i", Value::Int(5));
+        same_both_ways(
+            "| a | a <- Array new: 4. 1 to: 4 do: [:i | a at: i put: i * i]. a",
+            Value::Array(Rc::new(RefCell::new(vec![Value::Int(1), Value::Int(4), Value::Int(9), Value::Int(16)]))),
+        );
+    }
+
+    #[test]
+    fn test_nested_blocks_and_returns() {
+        let src = "Object subclass: #Finder
+    instanceVariableNames: 'limit'
+    classVariableNames: ''
+    category: 'Tests'!
+
+!Finder methodsFor: 'searching'!
+limit: n
+    limit <- n!
+firstOver: n in: anArray
+    anArray do: [:each | each > n ifTrue: [^each]].
+    ^nil!
+countTo: n
+    | count |
+    count <- 0.
+    1 to: n do: [:i | (i \\\\ 2 = 0 and: [i < limit]) ifTrue: [count <- count + 1]].
+    ^count! !
+";
+        for &inline in &[true, false] {
+            let mut interpreter = Interpreter::with_config(CompilerConfig { inline_control_flow: inline });
+            interpreter.file_in(src).unwrap();
+            let finder = "(Finder new limit: 7; yourself)";
+            assert_eq!(run(&mut interpreter, &format!("{} firstOver: 3 in: #(1 5 2 8)", finder)), Value::Int(5));
+            assert_eq!(run(&mut interpreter, &format!("{} firstOver: 9 in: #(1 5 2 8)", finder)), Value::Nil);
+            assert_eq!(run(&mut interpreter, &format!("{} countTo: 20", finder)), Value::Int(3));
+        }
+    }
+
+    #[test]
+    fn test_special_selectors() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .file_in(
+                "Object subclass: #Vec
+    instanceVariableNames: 'x y'
+    classVariableNames: ''
+    category: 'Tests'!
+
+!Vec methodsFor: 'arithmetic'!
+x
+    ^x!
+y
+    ^y!
+x: newX y: newY
+    x <- newX.
+    y <- newY!
++ aVec
+    ^Vec new x: x + aVec x y: y + aVec y! !
+",
+            )
+            .unwrap();
+        // `+`, `x` and `y` are special sends which miss the fast path.
+        let src = "((Vec new x: 1 y: 2) + (Vec new x: 30 y: 40)) y";
+        assert_eq!(run(&mut interpreter, src), Value::Int(42));
+        assert_eq!(run(&mut interpreter, "(3 bitAnd: 6) + (1 bitShift: 3) - (7 // 2)"), Value::Int(7));
+        assert_eq!(run(&mut interpreter, "#(1 2 3) at: 2"), Value::Int(2));
+        match interpreter.evaluate("3 @ 4") {
+            Err(RunError::DoesNotUnderstand { ref selector, .. }) => assert_eq!(selector, "@"),
+            r => panic!("expected doesNotUnderstand, got {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_class_variables() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .file_in(
+                "Object subclass: #Counter
+    instanceVariableNames: ''
+    classVariableNames: 'Count'
+    category: 'Tests'!
+
+Counter subclass: #SubCounter
+    instanceVariableNames: ''
+    classVariableNames: ''
+    category: 'Tests'!
+
+Object subclass: #Tally
+    instanceVariableNames: ''
+    classVariableNames: 'Count'
+    category: 'Tests'!
+
+!Counter class methodsFor: 'counting'!
+count
+    ^Count!
+increment
+    Count isNil ifTrue: [Count <- 0].
+    Count <- Count + 1! !
+
+!SubCounter methodsFor: 'counting'!
+count
+    ^Count! !
+
+!Tally class methodsFor: 'counting'!
+count
+    ^Count!
+count: n
+    Count <- n! !
+",
+            )
+            .unwrap();
+        assert_eq!(run(&mut interpreter, "Counter count"), Value::Nil);
+        assert_eq!(run(&mut interpreter, "Counter increment; increment. Counter count"), Value::Int(2));
+        // Subclasses share the variable, but other classes' of the same name
+        // are their own, and none of them are globals.
+        assert_eq!(run(&mut interpreter, "SubCounter new count"), Value::Int(2));
+        assert_eq!(run(&mut interpreter, "Tally count"), Value::Nil);
+        assert_eq!(run(&mut interpreter, "Tally count: 7. Counter count"), Value::Int(2));
+        assert_eq!(run(&mut interpreter, "Tally count"), Value::Int(7));
+        match interpreter.evaluate("Count") {
+            Err(RunError::Undeclared(ref name)) => assert_eq!(name, "Count"),
+            r => panic!("expected an undeclared error, got {:?}", r.map(|_| ())),
+        }
+    }
 
+    #[test]
+    fn test_errors() {
+        let mut interpreter = Interpreter::new();
+        match interpreter.evaluate("3 foo") {
+            Err(RunError::DoesNotUnderstand { ref class, ref selector }) => {
+                assert_eq!((class.as_str(), selector.as_str()), ("SmallInteger", "foo"));
+            }
+            r => panic!("expected doesNotUnderstand, got {:?}", r.map(|_| ())),
+        }
+        match interpreter.evaluate("3 ifTrue: [4]") {
+            Err(RunError::NonBoolean(ref class)) => assert_eq!(class, "SmallInteger"),
+            r => panic!("expected a non-boolean error, got {:?}", r.map(|_| ())),
+        }
+        match interpreter.evaluate("Missing new") {
+            Err(RunError::Undeclared(ref name)) => assert_eq!(name, "Missing"),
+            r => panic!("expected an undeclared error, got {:?}", r.map(|_| ())),
+        }
+    }
 }
@@ -0,0 +1,571 @@
+// Scope resolution: works out which variable each identifier names.
+//
+// The parser leaves every variable as a bare `Ident`. Before a method can be
+// compiled, each one has to be found in the scopes around it: the arguments
+// and temporaries of the method and its blocks, then the instance, class and
+// pool variables of the class, then the globals.
+
+use std::fmt;
+
+use syntax::{self, Ident, Literal, MsgPat, PseudoVar, Selector, Span, Syntax};
+
+/// Where the value of a variable lives.
+///
+/// In the Blue Book, the arguments and temporaries of blocks are kept in the
+/// temporary frame of the method's context, after the method's own arguments
+/// and temporaries. Every `Arg`, `Temp` and `BlockArg` index is a slot in that
+/// frame.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Binding {
+    /// An argument of the method.
+    Arg(usize),
+    /// A temporary of the method or of a block.
+    Temp(usize),
+    /// An argument of a block.
+    BlockArg(usize),
+    /// A field of the receiver.
+    InstVar(usize),
+    /// An index into `ClassLayout::class_vars`.
+    ClassVar(usize),
+    /// An index into `ClassLayout::pools`, then into the variables of the
+    /// pool.
+    Pool(usize, usize),
+    /// A name in `ClassLayout::globals`.
+    Global,
+    /// A name which no scope declares. It is reported, and left for the
+    /// compiler to treat as a global which does not exist yet.
+    Undeclared,
+}
+
+impl Binding {
+    fn describe(self) -> &'static str {
+        match self {
+            Binding::Arg(_) => "an argument",
+            Binding::Temp(_) => "a temporary",
+            Binding::BlockArg(_) => "a block argument",
+            Binding::InstVar(_) => "an instance variable",
+            Binding::ClassVar(_) => "a class variable",
+            Binding::Pool(..) => "a pool variable",
+            Binding::Global => "a global",
+            Binding::Undeclared => "undeclared",
+        }
+    }
+}
+
+/// The variables a method can see from its class, and the globals.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ClassLayout {
+    /// Every instance variable, inherited ones first, in the order of the
+    /// fields of an instance.
+    pub instance_vars: Vec<String>,
+    /// The class variables of the class and its superclasses.
+    pub class_vars: Vec<String>,
+    pub pools: Vec<Pool>,
+    pub globals: Vec<String>,
+}
+
+/// A pool dictionary shared between classes, such as `TextConstants`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Pool {
+    pub name: String,
+    pub vars: Vec<String>,
+}
+
+/// An identifier with the variable it names.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Var {
+    pub name: Ident,
+    pub binding: Binding,
+}
+
+/// `syntax::Expr` with every variable resolved. Messages carry their
+/// selector and arguments directly.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expr {
+    Var(Var),
+    Assign(Var, Box<Expr>),
+    Lit(Literal),
+    Message { receiver: Box<Expr>, msg: Msg, span: Span },
+    Cascade { receiver: Box<Expr>, messages: Vec<Msg>, span: Span },
+    Block { args: Vec<Var>, temps: Vec<Var>, statements: Vec<Statement>, span: Span },
+    Brace { elements: Vec<Expr>, span: Span },
+    Pseudo(PseudoVar, Span),
+    /// A method written as an expression, resolved in a scope of its own.
+    Method(Box<Method>),
+    Error(Span),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Msg {
+    pub selector: Selector,
+    pub args: Vec<Expr>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Statement {
+    E(Expr),
+    Ret(Expr, Span),
+}
+
+/// A method, or a doIt, with every variable resolved.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Method {
+    pub selector: Selector,
+    pub args: Vec<Var>,
+    pub temps: Vec<Var>,
+    pub primitive: Option<u32>,
+    pub statements: Vec<Statement>,
+    /// The size of the temporary frame: the arguments and temporaries of the
+    /// method and of all its blocks.
+    pub num_temps: usize,
+    pub span: Span,
+}
+
+/// A problem with the variables of a method. None of them stop the method
+/// from being compiled.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum DiagnosticKind {
+    /// A name which no scope declares.
+    Undeclared(String),
+    /// A declaration which hides a variable of the same name from an
+    /// enclosing scope, which is bound as given.
+    Shadows(String, Binding),
+    /// A temporary which is never read.
+    UnusedTemp(String),
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let start = self.span.start;
+        write!(f, "line {}, column {}: ", start.line, start.column)?;
+        match self.kind {
+            DiagnosticKind::Undeclared(ref name) => write!(f, "`{}` is not declared", name),
+            DiagnosticKind::Shadows(ref name, outer) => {
+                write!(f, "`{}` shadows {} of the same name", name, outer.describe())
+            }
+            DiagnosticKind::UnusedTemp(ref name) => write!(f, "temporary `{}` is never read", name),
+        }
+    }
+}
+
+/// Resolve the variables of a method defined in a class laid out as
+/// `layout`.
+///
+/// ```
+/// use smoltok::compiler::resolve::{resolve_method, Binding, ClassLayout, Expr, Statement};
+/// use smoltok::parser::parse_method;
+///
+/// let layout = ClassLayout { instance_vars: vec![String::from("x")], ..ClassLayout::default() };
+/// let m = parse_method("x: aNumber x <- aNumber").unwrap();
+/// let (m, diagnostics) = resolve_method(&m, &layout);
+/// match m.statements[0] {
+///     Statement::E(Expr::Assign(ref var, _)) => assert_eq!(var.binding, Binding::InstVar(0)),
+///     ref s => panic!("unexpected {:?}", s),
+/// }
+/// assert!(diagnostics.is_empty());
+/// ```
+pub fn resolve_method(method: &syntax::Method, layout: &ClassLayout) -> (Method, Vec<Diagnostic>) {
+    let mut r = Resolver::new(layout);
+    let m = r.method(method);
+    (m, r.finish())
+}
+
+/// Resolve a doIt, as from `parse_do_it`, as though it were the body of a
+/// method `DoIt` defined in a class laid out as `layout`.
+pub fn resolve_do_it(do_it: &Syntax, layout: &ClassLayout) -> (Method, Vec<Diagnostic>) {
+    let mut r = Resolver::new(layout);
+    r.scopes.push(Vec::new());
+    let (temps, statements, span) = match *do_it {
+        Syntax::Expr(ref e) => (Vec::new(), vec![Statement::E(r.expr(e))], e.span()),
+        Syntax::DoIt { ref temps, ref statements } => {
+            let temps = r.declare_all(temps, Binding::Temp);
+            let span = match (statements.first(), statements.last()) {
+                (Some(first), Some(last)) => first.span().to(last.span()),
+                _ => Span::default(),
+            };
+            (temps, r.statements(statements), span)
+        }
+    };
+    r.pop_scope();
+    let m = Method {
+        selector: Selector::new("DoIt"),
+        args: Vec::new(),
+        temps,
+        primitive: None,
+        statements,
+        num_temps: r.next_slot,
+        span,
+    };
+    (m, r.finish())
+}
+
+// A variable declared in a method or block.
+struct Local {
+    name: String,
+    binding: Binding,
+    span: Span,
+    read: bool,
+}
+
+struct Resolver<'a> {
+    layout: &'a ClassLayout,
+    // The innermost scope is last.
+    scopes: Vec<Vec<Local>>,
+    next_slot: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(layout: &'a ClassLayout) -> Self {
+        Resolver { layout, scopes: Vec::new(), next_slot: 0, diagnostics: Vec::new() }
+    }
+
+    fn finish(mut self) -> Vec<Diagnostic> {
+        self.diagnostics.sort_by_key(|d| d.span.start);
+        self.diagnostics
+    }
+
+    fn method(&mut self, m: &syntax::Method) -> Method {
+        // A method nested in an expression gets a frame of its own.
+        let outer_scopes = ::std::mem::take(&mut self.scopes);
+        let outer_slot = ::std::mem::replace(&mut self.next_slot, 0);
+
+        self.scopes.push(Vec::new());
+        let args: Vec<Ident> = match m.sig {
+            MsgPat::Unary(_) => Vec::new(),
+            MsgPat::Bin(_, ref var) => vec![var.clone()],
+            MsgPat::Kwargs(ref kws) => kws.iter().map(|kw| kw.var.clone()).collect(),
+        };
+        let args = self.declare_all(&args, Binding::Arg);
+        let temps = self.declare_all(m.temps.as_deref().unwrap_or(&[]), Binding::Temp);
+        let statements = self.statements(m.stmts.as_deref().unwrap_or(&[]));
+        self.pop_scope();
+
+        let num_temps = ::std::mem::replace(&mut self.next_slot, outer_slot);
+        self.scopes = outer_scopes;
+        Method {
+            selector: m.sig.selector(),
+            args,
+            temps,
+            primitive: m.primitive,
+            statements,
+            num_temps,
+            span: m.span,
+        }
+    }
+
+    fn statements(&mut self, stmts: &[syntax::Statement]) -> Vec<Statement> {
+        stmts
+            .iter()
+            .map(|s| match *s {
+                syntax::Statement::E(ref e) => Statement::E(self.expr(e)),
+                syntax::Statement::Ret(ref e, span) => Statement::Ret(self.expr(e), span),
+            })
+            .collect()
+    }
+
+    fn expr(&mut self, e: &syntax::Expr) -> Expr {
+        match *e {
+            syntax::Expr::Id(ref id) => Expr::Var(self.lookup(id, true)),
+            syntax::Expr::Assign(ref id, ref val) => {
+                let var = self.lookup(id, false);
+                Expr::Assign(var, Box::new(self.expr(val)))
+            }
+            syntax::Expr::Lit(ref lit) => Expr::Lit(lit.clone()),
+            syntax::Expr::Message { ref receiver, ref selector, span } => Expr::Message {
+                receiver: Box::new(self.expr(receiver)),
+                msg: self.msg(selector),
+                span,
+            },
+            syntax::Expr::Cascade { ref receiver, ref messages, span } => Expr::Cascade {
+                receiver: Box::new(self.expr(receiver)),
+                messages: messages.iter().map(|m| self.msg(m)).collect(),
+                span,
+            },
+            syntax::Expr::Block { ref args, ref temps, ref statements, span } => {
+                self.scopes.push(Vec::new());
+                let args = self.declare_all(args, Binding::BlockArg);
+                let temps = self.declare_all(temps, Binding::Temp);
+                let statements = self.statements(statements);
+                self.pop_scope();
+                Expr::Block { args, temps, statements, span }
+            }
+            syntax::Expr::Brace { ref elements, span } => Expr::Brace {
+                elements: elements.iter().map(|e| self.expr(e)).collect(),
+                span,
+            },
+            syntax::Expr::Pseudo(var, span) => Expr::Pseudo(var, span),
+            syntax::Expr::Method(ref m) => Expr::Method(Box::new(self.method(m))),
+            syntax::Expr::Error(span) => Expr::Error(span),
+        }
+    }
+
+    fn msg(&mut self, m: &syntax::Msg) -> Msg {
+        let args = match *m {
+            syntax::Msg::Unary(_) => Vec::new(),
+            syntax::Msg::Binary(_, ref arg) => vec![self.expr(arg)],
+            syntax::Msg::Kwargs(ref kws) => kws.iter().map(|kw| self.expr(&kw.val)).collect(),
+        };
+        Msg { selector: m.selector(), args }
+    }
+
+    fn declare_all(&mut self, ids: &[Ident], binding: fn(usize) -> Binding) -> Vec<Var> {
+        ids.iter().map(|id| self.declare(id, binding)).collect()
+    }
+
+    // Give `id` the next slot of the frame, in the innermost scope.
+    fn declare(&mut self, id: &Ident, binding: fn(usize) -> Binding) -> Var {
+        match self.find(&id.0) {
+            Binding::Global | Binding::Undeclared => (),
+            outer => self.diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::Shadows(id.0.clone(), outer),
+                span: id.1,
+            }),
+        }
+        let binding = binding(self.next_slot);
+        self.next_slot += 1;
+        // Arguments count as read, so that only temporaries are reported.
+        let read = !matches!(binding, Binding::Temp(_));
+        let scope = self.scopes.last_mut().expect("declaration outside of a scope");
+        scope.push(Local { name: id.0.clone(), binding, span: id.1, read });
+        Var { name: id.clone(), binding }
+    }
+
+    fn lookup(&mut self, id: &Ident, read: bool) -> Var {
+        let binding = self.find(&id.0);
+        if binding == Binding::Undeclared {
+            self.diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::Undeclared(id.0.clone()),
+                span: id.1,
+            });
+        }
+        if read {
+            let local = self.scopes.iter_mut().rev().flat_map(|s| s.iter_mut().rev()).find(|l| l.name == id.0);
+            if let Some(local) = local {
+                local.read = true;
+            }
+        }
+        Var { name: id.clone(), binding }
+    }
+
+    fn find(&self, name: &str) -> Binding {
+        let local = self.scopes.iter().rev().flat_map(|s| s.iter().rev()).find(|l| l.name == name);
+        if let Some(local) = local {
+            return local.binding;
+        }
+        let layout = self.layout;
+        if let Some(i) = layout.instance_vars.iter().position(|v| v == name) {
+            return Binding::InstVar(i);
+        }
+        if let Some(i) = layout.class_vars.iter().position(|v| v == name) {
+            return Binding::ClassVar(i);
+        }
+        for (i, pool) in layout.pools.iter().enumerate() {
+            if let Some(j) = pool.vars.iter().position(|v| v == name) {
+                return Binding::Pool(i, j);
+            }
+        }
+        if layout.globals.iter().any(|g| g == name) {
+            Binding::Global
+        } else {
+            Binding::Undeclared
+        }
+    }
+
+    fn pop_scope(&mut self) {
+        for local in self.scopes.pop().unwrap_or_default() {
+            if !local.read {
+                self.diagnostics.push(Diagnostic {
+                    kind: DiagnosticKind::UnusedTemp(local.name),
+                    span: local.span,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::{parse_do_it, parse_method};
+
+    fn layout() -> ClassLayout {
+        ClassLayout {
+            instance_vars: vec![String::from("x"), String::from("y")],
+            class_vars: vec![String::from("Origin")],
+            pools: vec![Pool {
+                name: String::from("TextConstants"),
+                vars: vec![String::from("Cr"), String::from("Tab")],
+            }],
+            globals: vec![String::from("Transcript")],
+        }
+    }
+
+    fn resolve(src: &str) -> (Method, Vec<Diagnostic>) {
+        let m = parse_method(src).unwrap_or_else(|e| panic!("{}", e));
+        resolve_method(&m, &layout())
+    }
+
+    // The bindings of the variables of an expression, in the order they
+    // appear.
+    fn bindings(e: &Expr, out: &mut Vec<(String, Binding)>) {
+        let mut push = |v: &Var| out.push((v.name.0.clone(), v.binding));
+        match *e {
+            Expr::Var(ref v) => push(v),
+            Expr::Assign(ref v, ref val) => {
+                push(v);
+                bindings(val, out);
+            }
+            Expr::Message { ref receiver, ref msg, .. } => {
+                bindings(receiver, out);
+                for arg in &msg.args {
+                    bindings(arg, out);
+                }
+            }
+            Expr::Cascade { ref receiver, ref messages, .. } => {
+                bindings(receiver, out);
+                for arg in messages.iter().flat_map(|m| &m.args) {
+                    bindings(arg, out);
+                }
+            }
+            Expr::Block { ref args, ref temps, ref statements, .. } => {
+                for v in args.iter().chain(temps) {
+                    push(v);
+                }
+                statement_bindings(statements, out);
+            }
+            Expr::Brace { ref elements, .. } => for e in elements {
+                bindings(e, out);
+            },
+            _ => (),
+        }
+    }
+
+    fn statement_bindings(stmts: &[Statement], out: &mut Vec<(String, Binding)>) {
+        for s in stmts {
+            match *s {
+                Statement::E(ref e) | Statement::Ret(ref e, _) => bindings(e, out),
+            }
+        }
+    }
+
+    fn all_bindings(m: &Method) -> Vec<(String, Binding)> {
+        let mut out = Vec::new();
+        statement_bindings(&m.statements, &mut out);
+        out
+    }
+
+    fn named(pairs: &[(&str, Binding)]) -> Vec<(String, Binding)> {
+        pairs.iter().map(|&(n, b)| (String::from(n), b)).collect()
+    }
+
+    fn kinds(diagnostics: &[Diagnostic]) -> Vec<DiagnosticKind> {
+        diagnostics.iter().map(|d| d.kind.clone()).collect()
+    }
+
+    #[test]
+    fn test_frame_slots() {
+        let (m, diagnostics) = resolve("at: i put: v | t | t <- [:a :b | | u | u <- a + b + i]. ^t value: v value: 1");
+        assert_eq!(m.selector, Selector::new("at:put:"));
+        assert_eq!(m.args.iter().map(|v| v.binding).collect::<Vec<_>>(), vec![Binding::Arg(0), Binding::Arg(1)]);
+        assert_eq!(m.temps[0].binding, Binding::Temp(2));
+        assert_eq!(m.num_temps, 6);
+        assert_eq!(
+            all_bindings(&m),
+            named(&[
+                ("t", Binding::Temp(2)),
+                ("a", Binding::BlockArg(3)),
+                ("b", Binding::BlockArg(4)),
+                ("u", Binding::Temp(5)),
+                ("u", Binding::Temp(5)),
+                ("a", Binding::BlockArg(3)),
+                ("b", Binding::BlockArg(4)),
+                ("i", Binding::Arg(0)),
+                ("t", Binding::Temp(2)),
+                ("v", Binding::Arg(1)),
+            ])
+        );
+        assert_eq!(kinds(&diagnostics), vec![DiagnosticKind::UnusedTemp(String::from("u"))]);
+    }
+
+    #[test]
+    fn test_class_variables_and_globals() {
+        let (m, diagnostics) = resolve("foo ^y + x + Origin + Tab + Cr + Transcript + Smalltalk");
+        assert_eq!(
+            all_bindings(&m),
+            named(&[
+                ("y", Binding::InstVar(1)),
+                ("x", Binding::InstVar(0)),
+                ("Origin", Binding::ClassVar(0)),
+                ("Tab", Binding::Pool(0, 1)),
+                ("Cr", Binding::Pool(0, 0)),
+                ("Transcript", Binding::Global),
+                ("Smalltalk", Binding::Undeclared),
+            ])
+        );
+        assert_eq!(kinds(&diagnostics), vec![DiagnosticKind::Undeclared(String::from("Smalltalk"))]);
+        assert_eq!(diagnostics[0].span.start.column, 47);
+        assert_eq!(diagnostics[0].to_string(), "line 1, column 47: `Smalltalk` is not declared");
+    }
+
+    #[test]
+    fn test_shadowing() {
+        let (m, diagnostics) = resolve("foo: x | y | ^[:y | | Cr | Cr <- y] value: x");
+        assert_eq!(m.args[0].binding, Binding::Arg(0));
+        assert_eq!(
+            kinds(&diagnostics),
+            vec![
+                DiagnosticKind::Shadows(String::from("x"), Binding::InstVar(0)),
+                DiagnosticKind::Shadows(String::from("y"), Binding::InstVar(1)),
+                DiagnosticKind::UnusedTemp(String::from("y")),
+                DiagnosticKind::Shadows(String::from("y"), Binding::Temp(1)),
+                DiagnosticKind::Shadows(String::from("Cr"), Binding::Pool(0, 0)),
+                DiagnosticKind::UnusedTemp(String::from("Cr")),
+            ]
+        );
+        assert_eq!(
+            diagnostics[3].to_string(),
+            "line 1, column 17: `y` shadows a temporary of the same name"
+        );
+    }
+
+    #[test]
+    fn test_sibling_blocks_get_their_own_slots() {
+        let (m, diagnostics) = resolve("foo ^[:a | a] value: [:a | a]");
+        assert_eq!(m.num_temps, 2);
+        assert_eq!(
+            all_bindings(&m),
+            named(&[
+                ("a", Binding::BlockArg(0)),
+                ("a", Binding::BlockArg(0)),
+                ("a", Binding::BlockArg(1)),
+                ("a", Binding::BlockArg(1)),
+            ])
+        );
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_do_it() {
+        let do_it = parse_do_it("| a b | a <- 3. b <- 4. Transcript show: a printString").unwrap();
+        let (m, diagnostics) = resolve_do_it(&do_it, &layout());
+        assert_eq!(m.selector, Selector::new("DoIt"));
+        assert_eq!(m.num_temps, 2);
+        assert_eq!(
+            all_bindings(&m),
+            named(&[
+                ("a", Binding::Temp(0)),
+                ("b", Binding::Temp(1)),
+                ("Transcript", Binding::Global),
+                ("a", Binding::Temp(0)),
+            ])
+        );
+        assert_eq!(kinds(&diagnostics), vec![DiagnosticKind::UnusedTemp(String::from("b"))]);
+    }
+}
@@ -0,0 +1,188 @@
+//! Writing classes and methods out in the `!`-delimited chunk format, so that
+//! they can be read back in with `parser::parse_chunks`.
+//!
+//! Every `!` in the text of a chunk is doubled, class definitions are written
+//! as definition messages, and methods are grouped under
+//! `!Foo methodsFor: 'category'!` headers the way a Smalltalk-80 fileOut does:
+//!
+//! ```text
+//! Object subclass: #Point
+//!     instanceVariableNames: 'x y'
+//!     classVariableNames: ''
+//!     poolDictionaries: ''
+//!     category: 'Graphics-Primitives'!
+//!
+//! !Point methodsFor: 'accessing'!
+//! x
+//!     ^x! !
+//! ```
+
+use std::fmt::{self, Write};
+
+use pretty::{print_method, print_statements, string, Config};
+use syntax::*;
+
+/// Write `chunks` out in the chunk format.
+///
+/// ```
+/// use smoltok::fileout::file_out;
+/// use smoltok::parser::parse_chunks;
+///
+/// let src = "!Integer methodsFor: 'testing'!\nisBang\n    ^self = '!!'! !\n";
+/// let chunks = parse_chunks(src).unwrap();
+/// assert_eq!(file_out(&chunks), src);
+/// ```
+pub fn file_out(chunks: &[Chunk]) -> String {
+    let mut out = String::new();
+    write_chunks(&mut out, chunks).expect("writing to a String can't fail");
+    out
+}
+
+/// Like `file_out`, but writing to any `fmt::Write`.
+pub fn write_chunks<W: Write>(out: &mut W, chunks: &[Chunk]) -> fmt::Result {
+    for (i, chunk) in chunks.iter().enumerate() {
+        if i > 0 {
+            out.write_char('\n')?;
+        }
+        match *chunk {
+            Chunk::Class(ref class) => write_class(out, class)?,
+            Chunk::Methods(ref category) => write_category(out, category)?,
+            Chunk::DoIt { ref temps, ref statements, .. } => {
+                let mut text = String::new();
+                if !temps.is_empty() {
+                    let names: Vec<_> = temps.iter().map(|t| &t.0[..]).collect();
+                    text.push_str(&format!("| {} |\n", names.join(" ")));
+                }
+                text.push_str(&print_statements(statements, &Config::default()));
+                write_chunk(out, &text)?;
+                out.write_char('\n')?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write a class definition, followed by its comment, class instance
+/// variables and methods.
+pub fn write_class<W: Write>(out: &mut W, class: &ClassDef) -> fmt::Result {
+    let superclass = class.superclass.as_ref().map_or("nil", |s| &s.0[..]);
+    let mut text = format!("{} {} #{}", superclass, class.kind.keyword(), class.name.0);
+    text.push_str("\n    instanceVariableNames: ");
+    string(&mut text, &class.instance_vars.join(" "));
+    text.push_str("\n    classVariableNames: ");
+    string(&mut text, &class.class_vars.join(" "));
+    if let Some(ref pools) = class.pool_dictionaries {
+        text.push_str("\n    poolDictionaries: ");
+        string(&mut text, &pools.join(" "));
+    }
+    text.push_str("\n    category: ");
+    string(&mut text, &class.category);
+    write_chunk(out, &text)?;
+    out.write_char('\n')?;
+
+    if let Some(ref comment) = class.comment {
+        let mut text = format!("{} comment: ", class.name.0);
+        string(&mut text, comment);
+        out.write_char('\n')?;
+        write_chunk(out, &text)?;
+        out.write_char('\n')?;
+    }
+    if !class.class_instance_vars.is_empty() {
+        let mut text = format!("{} class instanceVariableNames: ", class.name.0);
+        string(&mut text, &class.class_instance_vars.join(" "));
+        out.write_char('\n')?;
+        write_chunk(out, &text)?;
+        out.write_char('\n')?;
+    }
+    for category in &class.categories {
+        out.write_char('\n')?;
+        write_category(out, category)?;
+    }
+    Ok(())
+}
+
+/// Write a `!Foo methodsFor: 'category'!` section.
+pub fn write_category<W: Write>(out: &mut W, category: &MethodCategory) -> fmt::Result {
+    let mut header = category.class.0.clone();
+    if category.meta {
+        header.push_str(" class");
+    }
+    header.push_str(" methodsFor: ");
+    string(&mut header, &category.name);
+    out.write_char('!')?;
+    write_chunk(out, &header)?;
+    for method in &category.methods {
+        out.write_char('\n')?;
+        write_chunk(out, &print_method(method, &Config::default()))?;
+    }
+    out.write_str(" !\n")
+}
+
+// Write the text of a chunk, doubling any `!` in it, and the `!` ending it.
+fn write_chunk<W: Write>(out: &mut W, text: &str) -> fmt::Result {
+    for c in text.chars() {
+        if c == '!' {
+            out.write_char('!')?;
+        }
+        out.write_char(c)?;
+    }
+    out.write_char('!')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parse_chunks;
+
+    const POINT: &str = "Object subclass: #Point
+    instanceVariableNames: 'x y'
+    classVariableNames: ''
+    poolDictionaries: ''
+    category: 'Graphics-Primitives'!
+
+Point comment: 'I''m a point!! Really.'!
+
+Point class instanceVariableNames: 'origin'!
+
+!Point methodsFor: 'accessing'!
+x
+    \"Answer the x coordinate.\"
+    ^x!
+x: xValue y: yValue
+    | old |
+    old <- x.
+    x <- xValue.
+    y <- yValue.
+    ^old! !
+
+!Point class methodsFor: 'instance creation'!
+x: xInteger y: yInteger
+    ^self new x: xInteger y: yInteger! !
+
+!Integer methodsFor: 'testing'!
+isBang
+    ^self printString = '!!'! !
+
+Transcript show: 'done!!'!
+";
+
+    #[test]
+    fn test_file_out_is_canonical() {
+        let chunks = parse_chunks(POINT).unwrap();
+        assert_eq!(file_out(&chunks), POINT);
+    }
+
+    #[test]
+    fn test_file_out_round_trips() {
+        let src = "Object variableSubclass: #Stack instanceVariableNames: 'top' classVariableNames: 'Empty' category: 'Collections'!
+!Stack methodsFor: 'access'!
+push: x
+  ^(self at: top + 1 put: x) foo: (3 - -4) bar: [:a | a + 1. ^a at: 2r101e-2]! !
+\"a do-it\"
+(1 + 2) printString: #(1 $a 'b' c (16r-FF)) printString!";
+        let chunks = parse_chunks(src).unwrap();
+        let out = file_out(&chunks);
+        assert_eq_ignoring_spans!(parse_chunks(&out).unwrap(), chunks);
+        assert_eq!(file_out(&parse_chunks(&out).unwrap()), out);
+    }
+}
@@ -0,0 +1,756 @@
+//! Lexer for Smalltalk source text.
+//!
+//! The lexer turns source text into `Token`s, skipping whitespace and
+//! comments, so that the parser works on whole identifiers, selectors and
+//! literals rather than on characters. Every token records its span.
+//!
+//! Smalltalk is not quite context free at the level of characters: `-3` is a
+//! negative number at the start of an expression but a subtraction after a
+//! receiver, as in `x-3`, and `at:put:` is a single keyword selector inside a
+//! literal array. The lexer decides these from the tokens before, so that the
+//! parser never has to.
+
+use std::fmt;
+use std::str::Chars;
+
+use parser::{ParserConfig, SyntaxError};
+use syntax::{Num, Position, Span};
+
+/// A token and the span of text it was read from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    /// An identifier, such as `foo`. Pseudo-variables such as `self` are
+    /// identifiers too.
+    Ident(String),
+    /// A keyword, such as `at:`. Inside a literal array the keywords of a
+    /// selector, such as `at:put:`, are a single keyword.
+    Keyword(String),
+    /// A binary selector, such as `+` or `->`. The `|` around temporaries and
+    /// the `<` and `>` around pragmas are binary selectors too.
+    Binary(String),
+    Number(Num),
+    Char(char),
+    Str(String),
+    /// A symbol, such as `#foo` or `#at:put:`, without its `#`.
+    Symbol(String),
+    /// Assignment: `<-`, or any other spelling the configuration accepts.
+    Assign,
+    /// The return marker: `^`, or `↑` if the configuration accepts it.
+    Return,
+    /// The `:` in front of a block argument.
+    Colon,
+    Period,
+    Semicolon,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
+    /// The `#(` starting a literal array.
+    ArrayStart,
+    /// The `#[` starting a byte array.
+    ByteArrayStart,
+    /// Text which is not a token, such as a stray `_`, an unterminated
+    /// string or a digit which is too large for its radix. Lexing stops after
+    /// it, so it is always the last token; `tokenize` reports it as an error.
+    Error,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            TokenKind::Ident(ref s) | TokenKind::Keyword(ref s) | TokenKind::Binary(ref s) =>
+                f.write_str(s),
+            TokenKind::Number(ref n) => {
+                if n.negative {
+                    f.write_str("-")?;
+                }
+                if let Some(radix) = n.radix {
+                    write!(f, "{}r", radix)?;
+                }
+                f.write_str(&n.integer)?;
+                if let Some(ref m) = n.mantissa {
+                    write!(f, ".{}", m)?;
+                }
+                if let Some(e) = n.exponent {
+                    write!(f, "e{}", e)?;
+                }
+                match n.scale {
+                    Some(s) => write!(f, "s{}", s),
+                    None => Ok(()),
+                }
+            }
+            TokenKind::Char(c) => write!(f, "${}", c),
+            TokenKind::Str(ref s) => write!(f, "'{}'", s.replace('\'', "''")),
+            TokenKind::Symbol(ref s) => write!(f, "#{}", s),
+            TokenKind::Assign => f.write_str("<-"),
+            TokenKind::Return => f.write_str("^"),
+            TokenKind::Colon => f.write_str(":"),
+            TokenKind::Period => f.write_str("."),
+            TokenKind::Semicolon => f.write_str(";"),
+            TokenKind::LParen => f.write_str("("),
+            TokenKind::RParen => f.write_str(")"),
+            TokenKind::LBracket => f.write_str("["),
+            TokenKind::RBracket => f.write_str("]"),
+            TokenKind::LBrace => f.write_str("{"),
+            TokenKind::RBrace => f.write_str("}"),
+            TokenKind::ArrayStart => f.write_str("#("),
+            TokenKind::ByteArrayStart => f.write_str("#["),
+            TokenKind::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// Split `src` into tokens, in the syntax `config` accepts.
+///
+/// ```
+/// use smoltok::lexer::{tokenize, TokenKind};
+/// use smoltok::parser::ParserConfig;
+///
+/// let tokens = tokenize(&ParserConfig::default(), "x <- 3 - -4. \"done\"").unwrap();
+/// let kinds: Vec<String> = tokens.iter().map(|t| t.to_string()).collect();
+/// assert_eq!(kinds, vec!["x", "<-", "3", "-", "-4", "."]);
+/// assert_eq!(tokens[2].kind, TokenKind::Number(smoltok::syntax::Num::int_from_str("3")));
+/// assert!(tokenize(&ParserConfig::default(), "'unterminated").is_err());
+/// ```
+pub fn tokenize(config: &ParserConfig, src: &str) -> Result<Vec<Token>, SyntaxError> {
+    let lexed = lex(config, src, src, Position { line: 1, column: 1 });
+    match lexed.tokens.last() {
+        Some(&Token { kind: TokenKind::Error, span }) => Err(lexed.error.unwrap_or_else(|| {
+            let c = src_char(src, span.start).map(|c| c.to_string());
+            SyntaxError::new(src, span.start, c, Vec::new(), Vec::new())
+        })),
+        _ => Ok(lexed.tokens),
+    }
+}
+
+// The character of `src` at `position`.
+pub(crate) fn src_char(src: &str, position: Position) -> Option<char> {
+    src.lines().nth(position.line - 1)
+        .and_then(|line| line.chars().nth(position.column - 1))
+}
+
+/// The tokens of a piece of source text, along with its comments and why
+/// lexing stopped early, if it did.
+pub(crate) struct Lexed {
+    pub tokens: Vec<Token>,
+    /// The comments, without their quotes, in order.
+    pub comments: Vec<(String, Span)>,
+    /// The error which explains the `TokenKind::Error` at the end of the
+    /// tokens, if the lexer can say better than the parser what is wrong.
+    pub error: Option<SyntaxError>,
+    /// The position of the first character.
+    pub start: Position,
+    /// The position after the last character.
+    pub end: Position,
+}
+
+/// Lex `text`, which starts at `start` in `src`, so that spans and errors
+/// point into `src` itself.
+pub(crate) fn lex(config: &ParserConfig, src: &str, text: &str, start: Position) -> Lexed {
+    lex_collapsed(config, src, text, start, &[])
+}
+
+/// Like `lex`, for text in which each `!!` of `src` has been collapsed to a
+/// single `!`. `collapsed` holds the position in `src` of every `!!`, so
+/// that the positions after one still point at the right column.
+pub(crate) fn lex_collapsed(config: &ParserConfig, src: &str, text: &str, start: Position,
+                            collapsed: &[Position]) -> Lexed {
+    let mut lexer = Lexer {
+        config,
+        src,
+        collapsed,
+        chars: text.chars(),
+        position: start,
+        tokens: Vec::new(),
+        comments: Vec::new(),
+        arrays: Vec::new(),
+    };
+    let error = lexer.run().err();
+    Lexed {
+        tokens: lexer.tokens,
+        comments: lexer.comments,
+        error: error.and_then(|e| e),
+        start,
+        end: lexer.position,
+    }
+}
+
+struct Lexer<'a> {
+    config: &'a ParserConfig,
+    src: &'a str,
+    // Where the `!!` collapsed in the text were in `src`.
+    collapsed: &'a [Position],
+    chars: Chars<'a>,
+    position: Position,
+    tokens: Vec<Token>,
+    comments: Vec<(String, Span)>,
+    // For each open bracket, whether it is a literal array.
+    arrays: Vec<bool>,
+}
+
+// Why a token could not be read: an error which explains itself, or `None`
+// to leave the explanation to the parser.
+type Failure = Option<SyntaxError>;
+
+const SPECIAL: &str = "+/\\*~<>=@%|&?!,";
+
+fn is_special(c: char) -> bool {
+    SPECIAL.contains(c)
+}
+
+fn is_digit_in(c: char, base: u32) -> bool {
+    !c.is_lowercase() && c.to_digit(36).is_some_and(|d| d < base)
+}
+
+impl<'a> Lexer<'a> {
+    fn peek(&self) -> Option<char> {
+        self.chars.clone().next()
+    }
+
+    fn peek2(&self) -> Option<char> {
+        let mut chars = self.chars.clone();
+        chars.next();
+        chars.next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else if c == '!' && self.collapsed.contains(&self.position) {
+            self.position.column += 2;
+        } else {
+            self.position.column += 1;
+        }
+        Some(c)
+    }
+
+    fn in_array(&self) -> bool {
+        self.arrays.last() == Some(&true)
+    }
+
+    fn push(&mut self, kind: TokenKind, start: Position) {
+        let span = Span::new(start, self.position);
+        self.tokens.push(Token { kind, span });
+    }
+
+    fn error(&self, position: Position, unexpected: Option<char>, expected: &[&str],
+             hint: Option<String>) -> Failure {
+        Some(SyntaxError::new(
+            self.src,
+            position,
+            unexpected.map(|c| c.to_string()),
+            expected.iter().map(|e| e.to_string()).collect(),
+            hint.into_iter().collect(),
+        ))
+    }
+
+    // Lex all of the text. On failure, the last token is a
+    // `TokenKind::Error` at the start of the text which could not be read.
+    fn run(&mut self) -> Result<(), Failure> {
+        loop {
+            self.skip_blank()?;
+            let start = self.position;
+            let c = match self.peek() {
+                Some(c) => c,
+                None => return Ok(()),
+            };
+            if let Err(failure) = self.token(c, start) {
+                self.tokens.push(Token { kind: TokenKind::Error, span: Span::new(start, start) });
+                return Err(failure);
+            }
+        }
+    }
+
+    // Skip whitespace and comments, keeping the text of the comments.
+    fn skip_blank(&mut self) -> Result<(), Failure> {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                Some('"') => {
+                    let start = self.position;
+                    self.bump();
+                    let mut text = String::new();
+                    loop {
+                        match self.bump() {
+                            Some('"') => break,
+                            Some(c) => text.push(c),
+                            None => {
+                                self.tokens.push(Token {
+                                    kind: TokenKind::Error,
+                                    span: Span::new(start, start),
+                                });
+                                let hint = String::from("unterminated comment");
+                                return Err(self.error(self.position, None, &["`\"`"], Some(hint)));
+                            }
+                        }
+                    }
+                    self.comments.push((text, Span::new(start, self.position)));
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn token(&mut self, c: char, start: Position) -> Result<(), Failure> {
+        let kind = match c {
+            c if c.is_alphabetic() || self.identifier_char(c) => return self.word(start),
+            c if c.is_ascii_digit() => TokenKind::Number(self.number(false)?),
+            '-' if self.peek2().is_some_and(|c| c.is_ascii_digit()) && self.negative_allowed() => {
+                self.bump();
+                TokenKind::Number(self.number(true)?)
+            }
+            '<' if self.peek2() == Some('-') && self.config.left_arrow_assignment => {
+                self.bump();
+                self.bump();
+                TokenKind::Assign
+            }
+            ':' if self.peek2() == Some('=') && self.config.colon_equals => {
+                self.bump();
+                self.bump();
+                TokenKind::Assign
+            }
+            '_' if self.config.underscore_assignment => self.single(TokenKind::Assign),
+            '←' if self.config.arrow_glyphs => self.single(TokenKind::Assign),
+            '^' => self.single(TokenKind::Return),
+            '↑' if self.config.arrow_glyphs => self.single(TokenKind::Return),
+            c if is_special(c) || c == '-' => TokenKind::Binary(self.binary()),
+            '$' => {
+                self.bump();
+                match self.bump() {
+                    Some(c) => TokenKind::Char(c),
+                    None => return Err(self.error(self.position, None, &["character"], None)),
+                }
+            }
+            '\'' => TokenKind::Str(self.string()?),
+            '#' => return self.hashed(start),
+            ':' => self.single(TokenKind::Colon),
+            '.' => self.single(TokenKind::Period),
+            ';' => self.single(TokenKind::Semicolon),
+            '(' => {
+                let array = self.in_array();
+                self.arrays.push(array);
+                self.single(TokenKind::LParen)
+            }
+            '[' => {
+                self.arrays.push(false);
+                self.single(TokenKind::LBracket)
+            }
+            '{' => {
+                self.arrays.push(false);
+                self.single(TokenKind::LBrace)
+            }
+            ')' => {
+                self.arrays.pop();
+                self.single(TokenKind::RParen)
+            }
+            ']' => {
+                self.arrays.pop();
+                self.single(TokenKind::RBracket)
+            }
+            '}' => {
+                self.arrays.pop();
+                self.single(TokenKind::RBrace)
+            }
+            c => return Err(self.error(start, Some(c), &[], None)),
+        };
+        self.push(kind, start);
+        Ok(())
+    }
+
+    fn single(&mut self, kind: TokenKind) -> TokenKind {
+        self.bump();
+        kind
+    }
+
+    // A `-` in front of digits is part of a number, unless it follows
+    // something which can receive a binary message: `x-1` subtracts, but
+    // `x - -1` and `#(-1)` do not.
+    fn negative_allowed(&self) -> bool {
+        if self.in_array() {
+            return true;
+        }
+        match self.tokens.last().map(|t| &t.kind) {
+            Some(&TokenKind::Ident(_))
+            | Some(&TokenKind::Number(_))
+            | Some(&TokenKind::Char(_))
+            | Some(&TokenKind::Str(_))
+            | Some(&TokenKind::Symbol(_))
+            | Some(&TokenKind::RParen)
+            | Some(&TokenKind::RBracket)
+            | Some(&TokenKind::RBrace)
+            // The next message of a cascade.
+            | Some(&TokenKind::Semicolon) => false,
+            _ => true,
+        }
+    }
+
+    // An identifier or keyword. Inside a literal array, keywords written
+    // without spaces between them are a single keyword.
+    fn word(&mut self, start: Position) -> Result<(), Failure> {
+        let mut word = self.identifier();
+        let kind = if self.peek() == Some(':') && self.peek2() != Some('=') {
+            self.bump();
+            word.push(':');
+            if self.in_array() {
+                self.more_keywords(&mut word);
+            }
+            TokenKind::Keyword(word)
+        } else {
+            TokenKind::Ident(word)
+        };
+        self.push(kind, start);
+        Ok(())
+    }
+
+    // Add any keywords directly after the first one of a selector, as in
+    // `at:put:`, leaving an identifier without a colon after them alone.
+    fn more_keywords(&mut self, selector: &mut String) {
+        while self.peek().is_some_and(char::is_alphabetic) {
+            let (chars, position) = (self.chars.clone(), self.position);
+            let part = self.identifier();
+            if self.peek() != Some(':') {
+                self.chars = chars;
+                self.position = position;
+                return;
+            }
+            self.bump();
+            selector.push_str(&part);
+            selector.push(':');
+        }
+    }
+
+    // Whether `c` is a character of identifiers other than a letter or a
+    // digit.
+    fn identifier_char(&self, c: char) -> bool {
+        c == '_' && self.config.underscore_identifiers && !self.config.underscore_assignment
+    }
+
+    fn identifier(&mut self) -> String {
+        let mut s = String::new();
+        while let Some(c) = self.peek() {
+            if !c.is_alphanumeric() && !self.identifier_char(c) {
+                break;
+            }
+            s.push(c);
+            self.bump();
+        }
+        s
+    }
+
+    // A binary selector: any number of special characters and `-`s. A `-`
+    // directly before a digit is left to start a negative number, so that
+    // `3--4` is `3 - -4`.
+    fn binary(&mut self) -> String {
+        let mut s = String::new();
+        if let Some(c) = self.bump() {
+            s.push(c);
+        }
+        while let Some(c) = self.peek() {
+            let minus = c == '-' && !self.peek2().is_some_and(|c| c.is_ascii_digit());
+            if !is_special(c) && !minus {
+                break;
+            }
+            s.push(c);
+            self.bump();
+        }
+        s
+    }
+
+    fn string(&mut self) -> Result<String, Failure> {
+        self.bump();
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                Some('\'') if self.peek() == Some('\'') => {
+                    self.bump();
+                    s.push('\'');
+                }
+                Some('\'') => return Ok(s),
+                Some(c) => s.push(c),
+                None => {
+                    let hint = String::from("unterminated string literal");
+                    return Err(self.error(self.position, None, &["`'`"], Some(hint)));
+                }
+            }
+        }
+    }
+
+    // A literal starting with `#`: a symbol, or the start of a literal array
+    // or byte array.
+    fn hashed(&mut self, start: Position) -> Result<(), Failure> {
+        self.bump();
+        let kind = match self.peek() {
+            Some('(') => {
+                self.bump();
+                self.arrays.push(true);
+                TokenKind::ArrayStart
+            }
+            Some('[') if self.config.byte_arrays => {
+                self.bump();
+                self.arrays.push(false);
+                TokenKind::ByteArrayStart
+            }
+            Some(c) if c.is_alphabetic() => {
+                let mut name = self.identifier();
+                if self.peek() == Some(':') {
+                    self.bump();
+                    name.push(':');
+                    self.more_keywords(&mut name);
+                }
+                TokenKind::Symbol(name)
+            }
+            Some(c) if is_special(c) || c == '-' => TokenKind::Symbol(self.binary()),
+            _ => return Err(self.error(start, Some('#'), &["symbol", "`(`"], None)),
+        };
+        self.push(kind, start);
+        Ok(())
+    }
+
+    // A number, after any `-` in front of it:
+    //
+    //     [radix 'r'] ['-'] digits ['.' digits] ['e' ['-'] digits] ['s' [digits]]
+    //
+    // The digits before the exponent must be valid in the radix, which is 10
+    // by default. A number may only have one `-` before its digits, and only
+    // has a scale if the configuration accepts scaled decimals.
+    fn number(&mut self, negative: bool) -> Result<Num, Failure> {
+        let radix_start = self.position;
+        let mut radix = None;
+        let rest = (self.chars.clone(), self.position);
+        let digits = self.decimal_digits();
+        if self.peek() == Some('r') {
+            self.bump();
+            match digits.parse::<u32>() {
+                Ok(r) if (2..=36).contains(&r) => radix = Some(r as u8),
+                _ => {
+                    let hint = format!("radix must be between 2 and 36, not {}", digits);
+                    return Err(self.error(radix_start, digits.chars().next(), &[], Some(hint)));
+                }
+            }
+        } else {
+            self.chars = rest.0;
+            self.position = rest.1;
+        }
+        let base = u32::from(radix.unwrap_or(10));
+
+        let mut radix_negative = false;
+        if radix.is_some() && self.peek() == Some('-')
+            && self.peek2().is_some_and(|c| c.is_ascii_digit() || c.is_uppercase())
+        {
+            if negative {
+                let hint = String::from("a number can only have one `-`");
+                return Err(self.error(self.position, Some('-'), &[], Some(hint)));
+            }
+            self.bump();
+            radix_negative = true;
+        }
+
+        let integer = self.radix_digits(base)?;
+        let mut mantissa = None;
+        if self.peek() == Some('.') && self.peek2().is_some_and(|c| is_digit_in(c, base)) {
+            self.bump();
+            mantissa = Some(self.radix_digits(base)?);
+        }
+        let mut exponent = None;
+        let minus = self.peek2() == Some('-');
+        let after = {
+            let mut chars = self.chars.clone();
+            chars.nth(if minus { 2 } else { 1 })
+        };
+        if self.peek() == Some('e') && after.is_some_and(|c| c.is_ascii_digit()) {
+            self.bump();
+            if minus {
+                self.bump();
+            }
+            let e_start = self.position;
+            let digits = self.decimal_digits();
+            let e: i32 = match digits.parse() {
+                Ok(e) => e,
+                Err(_) => {
+                    let hint = format!("exponent {} is too large", digits);
+                    return Err(self.error(e_start, digits.chars().next(), &[], Some(hint)));
+                }
+            };
+            exponent = Some(if minus { -e } else { e });
+        }
+        let mut scale = None;
+        if self.config.scaled_decimals && self.peek() == Some('s')
+            && !self.peek2().is_some_and(char::is_alphabetic)
+        {
+            self.bump();
+            let s_start = self.position;
+            let digits = self.decimal_digits();
+            scale = Some(if digits.is_empty() {
+                mantissa.as_ref().map_or(0, |m: &String| m.len() as u32)
+            } else {
+                match digits.parse() {
+                    Ok(scale) => scale,
+                    Err(_) => {
+                        let hint = format!("scale {} is too large", digits);
+                        return Err(self.error(s_start, digits.chars().next(), &[], Some(hint)));
+                    }
+                }
+            });
+        }
+        Ok(Num {
+            negative: negative || radix_negative,
+            radix,
+            integer,
+            mantissa,
+            exponent,
+            scale,
+        })
+    }
+
+    fn decimal_digits(&mut self) -> String {
+        let mut s = String::new();
+        while let Some(c) = self.peek().filter(char::is_ascii_digit) {
+            s.push(c);
+            self.bump();
+        }
+        s
+    }
+
+    // Digits in `base`, which may be uppercase letters in radixes above 10.
+    // An uppercase letter or digit which is not valid in `base` is an error.
+    fn radix_digits(&mut self, base: u32) -> Result<String, Failure> {
+        let mut s = String::new();
+        while let Some(c) = self.peek().filter(|&c| c.is_ascii_digit() || c.is_uppercase()) {
+            if !is_digit_in(c, base) {
+                let hint = format!("`{}` is not a digit in base {}", c, base);
+                return Err(self.error(self.position, Some(c), &[], Some(hint)));
+            }
+            s.push(c);
+            self.bump();
+        }
+        if s.is_empty() {
+            let c = self.peek();
+            return Err(self.error(self.position, c, &["digit", "uppercase letter"], None));
+        }
+        Ok(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::Dialect;
+
+    fn kinds(src: &str) -> Vec<TokenKind> {
+        tokenize(&ParserConfig::default(), src).unwrap().into_iter().map(|t| t.kind).collect()
+    }
+
+    fn texts(config: &ParserConfig, src: &str) -> Vec<String> {
+        tokenize(config, src).unwrap().iter().map(|t| t.to_string()).collect()
+    }
+
+    fn at(line: usize, column: usize) -> Position {
+        Position { line, column }
+    }
+
+    #[test]
+    fn test_tokens() {
+        assert_eq!(kinds("foo at: 3 put: #bar; yourself"), vec![
+            TokenKind::Ident(String::from("foo")),
+            TokenKind::Keyword(String::from("at:")),
+            TokenKind::Number(Num::int_from_str("3")),
+            TokenKind::Keyword(String::from("put:")),
+            TokenKind::Symbol(String::from("bar")),
+            TokenKind::Semicolon,
+            TokenKind::Ident(String::from("yourself")),
+        ]);
+        let config = ParserConfig::default();
+        assert_eq!(
+            texts(&config, "[:x | | t | t <- x ==> $a. ^'it''s' , #at:put:]"),
+            vec!["[", ":", "x", "|", "|", "t", "|", "t", "<-", "x", "==>", "$a", ".", "^",
+                 "'it''s'", ",", "#at:put:", "]"]
+        );
+    }
+
+    #[test]
+    fn test_spans_skip_comments() {
+        let tokens = tokenize(&ParserConfig::default(), "foo \"a comment\"\n  bar: 'x'").unwrap();
+        let spans: Vec<_> = tokens.iter().map(|t| (t.span.start, t.span.end)).collect();
+        assert_eq!(spans, vec![(at(1, 1), at(1, 4)), (at(2, 3), at(2, 7)), (at(2, 8), at(2, 11))]);
+        let lexed = lex(&ParserConfig::default(), "x \"one\" \"two\"", "x \"one\" \"two\"", at(1, 1));
+        let comments: Vec<&str> = lexed.comments.iter().map(|c| &c.0[..]).collect();
+        assert_eq!(comments, vec!["one", "two"]);
+    }
+
+    #[test]
+    fn test_negative_numbers() {
+        let config = ParserConfig::default();
+        assert_eq!(texts(&config, "3-4 x-1 3--4 x - -1"),
+                   vec!["3", "-", "4", "x", "-", "1", "3", "-", "-4", "x", "-", "-1"]);
+        assert_eq!(texts(&config, "#(1 -2 foo -3 - 4) a foo: -1; - 2"),
+                   vec!["#(", "1", "-2", "foo", "-3", "-", "4", ")", "a", "foo:", "-1", ";",
+                        "-", "2"]);
+    }
+
+    #[test]
+    fn test_keywords_in_arrays() {
+        let config = ParserConfig::default();
+        assert_eq!(texts(&config, "#(at:put: foo:bar) at:put:"),
+                   vec!["#(", "at:put:", "foo:", "bar", ")", "at:", "put:"]);
+        assert_eq!(texts(&config, "#at:put: #foo:bar #a:b:c"),
+                   vec!["#at:put:", "#foo:", "bar", "#a:b:", "c"]);
+    }
+
+    #[test]
+    fn test_assignment_spellings() {
+        let config = ParserConfig::default();
+        assert_eq!(texts(&config, "x<-3"), vec!["x", "<-", "3"]);
+        assert_eq!(texts(&config, "x := 3"), vec!["x", ":", "=", "3"]);
+        assert_eq!(texts(&config, "x:=3"), vec!["x", ":", "=", "3"]);
+        assert!(tokenize(&config, "x _ 3").is_err());
+        let all = ParserConfig::ALL_SPELLINGS;
+        assert_eq!(texts(&all, "x := y _ z ← 3. ↑x"),
+                   vec!["x", "<-", "y", "<-", "z", "<-", "3", ".", "^", "x"]);
+    }
+
+    #[test]
+    fn test_config_gates_literals() {
+        let pharo = Dialect::Pharo.config();
+        assert_eq!(texts(&pharo, "#[1 2] 1.5s2 3s"), vec!["#[", "1", "2", "]", "1.5s2", "3s0"]);
+        let blue = ParserConfig::default();
+        assert!(tokenize(&blue, "#[1 2]").is_err());
+        assert_eq!(texts(&blue, "1.5s2 3s"), vec!["1.5", "s2", "3", "s"]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let config = ParserConfig::default();
+        let err = tokenize(&config, "a 'abc").unwrap_err();
+        assert_eq!((err.position, err.unexpected), (at(1, 7), None));
+        assert_eq!(err.hints, vec![String::from("unterminated string literal")]);
+        let err = tokenize(&config, "a \"abc").unwrap_err();
+        assert_eq!(err.hints, vec![String::from("unterminated comment")]);
+        let err = tokenize(&config, "x <- 2r1013").unwrap_err();
+        assert_eq!((err.position, err.unexpected), (at(1, 11), Some(String::from("3"))));
+        let err = tokenize(&config, "37r1").unwrap_err();
+        assert_eq!(err.hints, vec![String::from("radix must be between 2 and 36, not 37")]);
+        let err = tokenize(&config, "a _ b").unwrap_err();
+        assert_eq!((err.position, err.unexpected), (at(1, 3), Some(String::from("_"))));
+    }
+
+    #[test]
+    fn test_underscores_in_identifiers() {
+        let pharo = Dialect::Pharo.config();
+        assert_eq!(texts(&pharo, "my_var at_end: _x"), vec!["my_var", "at_end:", "_x"]);
+        let squeak = Dialect::Squeak.config();
+        assert_eq!(texts(&squeak, "my_var"), vec!["my", "<-", "var"]);
+        assert!(tokenize(&ParserConfig::default(), "my_var").is_err());
+    }
+}
@@ -1,9 +1,40 @@
 #[macro_use]
 extern crate combine;
+extern crate num_bigint;
+extern crate num_traits;
+#[cfg(test)]
+extern crate quickcheck;
+
+// `assert_eq!` for syntax trees built with `Span::default()`, which only
+// match parsed ones when spans are ignored.
+#[cfg(test)]
+macro_rules! assert_eq_ignoring_spans {
+    ($left:expr, $right:expr) => {
+        assert_eq_ignoring_spans!($left, $right, "")
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left, right) => if !::syntax::SpanlessEq::spanless_eq(left, right) {
+                panic!(
+                    "assertion failed: `(left == right)` ignoring spans\n  left: `{:?}`,\n right: `{:?}`: {}",
+                    left,
+                    right,
+                    format_args!($($arg)+)
+                )
+            }
+        }
+    };
+}
 
 pub mod syntax;
 pub mod compiler;
 pub mod parser;
+pub mod lexer;
+pub mod number;
+pub mod fileout;
+pub mod pretty;
+#[cfg(test)]
+mod arbitrary;
 
 #[cfg(test)]
 mod tests {
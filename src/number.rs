@@ -0,0 +1,276 @@
+//! The values of numeric literals.
+//!
+//! The parser keeps the digits of a number literal as text, so that numbers
+//! of any size and radix can be represented. `Num::evaluate` turns that text
+//! into the object the literal stands for, following the Blue Book: integers
+//! are SmallIntegers when they fit in 15 bits and LargePositiveIntegers or
+//! LargeNegativeIntegers when they don't, and a literal with a decimal point
+//! or a negative exponent is a Float.
+
+use std::error::Error;
+use std::fmt;
+
+use num_bigint::{BigInt, BigUint, Sign};
+use num_traits::{One, ToPrimitive};
+
+use syntax::Num;
+
+/// The smallest integer which is a SmallInteger rather than a
+/// LargeNegativeInteger.
+pub const SMALL_INTEGER_MIN: i16 = -16384;
+
+/// The largest integer which is a SmallInteger rather than a
+/// LargePositiveInteger.
+pub const SMALL_INTEGER_MAX: i16 = 16383;
+
+/// The most bits a literal's value may take. A large integer is an object
+/// of at most 65535 bytes in the Blue Book's object memory.
+pub const MAX_LITERAL_BITS: u64 = 65535 * 8;
+
+/// The value of a number literal.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NumberValue {
+    /// An integer between `SMALL_INTEGER_MIN` and `SMALL_INTEGER_MAX`.
+    SmallInteger(i16),
+    /// An integer above `SMALL_INTEGER_MAX`.
+    LargePositiveInteger(BigUint),
+    /// An integer below `SMALL_INTEGER_MIN`, kept as its magnitude like the
+    /// Blue Book does.
+    LargeNegativeInteger(BigUint),
+    Float(f64),
+    /// The exact value `numerator / denominator`, printed with `scale`
+    /// decimal places.
+    ScaledDecimal {
+        numerator: BigInt,
+        denominator: BigUint,
+        scale: u32,
+    },
+}
+
+impl NumberValue {
+    /// The integer `n`, as a SmallInteger when it fits and as a large integer
+    /// otherwise.
+    pub fn from_integer(n: BigInt) -> Self {
+        if let Some(small) = n.to_i16() {
+            if (SMALL_INTEGER_MIN..=SMALL_INTEGER_MAX).contains(&small) {
+                return NumberValue::SmallInteger(small);
+            }
+        }
+        match n.into_parts() {
+            (Sign::Minus, magnitude) => NumberValue::LargeNegativeInteger(magnitude),
+            (_, magnitude) => NumberValue::LargePositiveInteger(magnitude),
+        }
+    }
+}
+
+/// A `Num` which the parser would not have produced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NumberError {
+    /// The radix is not between 2 and 36.
+    InvalidRadix(u8),
+    /// A digit is not valid in the radix of its number.
+    InvalidDigit { digit: char, radix: u8 },
+    /// The exponent makes the value larger than `MAX_LITERAL_BITS`.
+    TooLarge,
+}
+
+impl fmt::Display for NumberError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NumberError::InvalidRadix(radix) =>
+                write!(f, "radix must be between 2 and 36, not {}", radix),
+            NumberError::InvalidDigit { digit, radix } =>
+                write!(f, "`{}` is not a digit in base {}", digit, radix),
+            NumberError::TooLarge =>
+                write!(f, "number is too large, its value needs more than {} bits", MAX_LITERAL_BITS),
+        }
+    }
+}
+
+impl Error for NumberError {}
+
+impl Num {
+    /// The value of the number literal.
+    ///
+    /// The exponent scales the number by a power of its radix, so `2r1e4` is
+    /// 16. Integers with a non-negative exponent stay integers, while any
+    /// number with a mantissa or a negative exponent is a Float.
+    ///
+    /// ```
+    /// use smoltok::number::NumberValue;
+    /// use smoltok::syntax::Num;
+    ///
+    /// let n = Num { radix: Some(16), exponent: Some(2), ..Num::int_from_str("FF") };
+    /// assert_eq!(n.evaluate(), Ok(NumberValue::LargePositiveInteger(65280u32.into())));
+    /// ```
+    pub fn evaluate(&self) -> Result<NumberValue, NumberError> {
+        let radix = self.radix.unwrap_or(10);
+        if !(2..=36).contains(&radix) {
+            return Err(NumberError::InvalidRadix(radix));
+        }
+        let mantissa = self.mantissa.as_ref().map_or("", |m| &m[..]);
+        let mut digits = Vec::with_capacity(self.integer.len() + mantissa.len());
+        for c in self.integer.chars().chain(mantissa.chars()) {
+            match c.to_digit(36) {
+                Some(d) if d < u32::from(radix) && !c.is_lowercase() => digits.push(d as u8),
+                _ => return Err(NumberError::InvalidDigit { digit: c, radix }),
+            }
+        }
+        let magnitude = BigUint::from_radix_be(&digits, u32::from(radix))
+            .unwrap_or_default();
+        // The power of the radix to multiply all of the digits by.
+        let exponent = i64::from(self.exponent.unwrap_or(0)) - mantissa.len() as i64;
+        let sign = if self.negative { Sign::Minus } else { Sign::Plus };
+
+        if let Some(scale) = self.scale {
+            let (numerator, denominator) = if exponent >= 0 {
+                (magnitude * power(radix, exponent)?, BigUint::one())
+            } else {
+                (magnitude, power(radix, -exponent)?)
+            };
+            return Ok(NumberValue::ScaledDecimal {
+                numerator: BigInt::from_biguint(sign, numerator),
+                denominator,
+                scale,
+            });
+        }
+        if self.mantissa.is_none() && exponent >= 0 {
+            let n = magnitude * power(radix, exponent)?;
+            return Ok(NumberValue::from_integer(BigInt::from_biguint(sign, n)));
+        }
+
+        let float = if radix == 10 {
+            // Rust's own parser rounds decimal numbers correctly.
+            format!("{}{}e{}", self.integer, mantissa, exponent)
+                .parse::<f64>()
+                .unwrap_or(0.0)
+        } else if exponent >= 0 {
+            to_f64(&(magnitude * power(radix, exponent)?))
+        } else {
+            to_f64(&magnitude) / f64::from(radix).powf(-exponent as f64)
+        };
+        Ok(NumberValue::Float(if self.negative { -float } else { float }))
+    }
+}
+
+// `radix` to the power `exponent`, refusing before it is worked out when it
+// would not fit in `MAX_LITERAL_BITS`.
+fn power(radix: u8, exponent: i64) -> Result<BigUint, NumberError> {
+    let bits = f64::from(radix).log2() * exponent as f64;
+    if bits > MAX_LITERAL_BITS as f64 {
+        return Err(NumberError::TooLarge);
+    }
+    Ok(BigUint::from(radix).pow(exponent as u32))
+}
+
+fn to_f64(n: &BigUint) -> f64 {
+    n.to_f64().unwrap_or(f64::INFINITY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::Dialect;
+    use syntax::Literal;
+
+    fn eval(src: &str) -> NumberValue {
+        match Dialect::Ansi.config().parse_literal(src) {
+            Ok(Literal::Number(n, _)) => n.evaluate().unwrap(),
+            other => panic!("expected a number, got {:?}", other),
+        }
+    }
+
+    fn large(n: u64) -> BigUint {
+        BigUint::from(n)
+    }
+
+    #[test]
+    fn test_small_integers() {
+        assert_eq!(eval("0"), NumberValue::SmallInteger(0));
+        assert_eq!(eval("42"), NumberValue::SmallInteger(42));
+        assert_eq!(eval("-3"), NumberValue::SmallInteger(-3));
+        assert_eq!(eval("16r1F"), NumberValue::SmallInteger(31));
+        assert_eq!(eval("16r-1F"), NumberValue::SmallInteger(-31));
+        assert_eq!(eval("2r1e4"), NumberValue::SmallInteger(16));
+        assert_eq!(eval("1e3"), NumberValue::SmallInteger(1000));
+    }
+
+    #[test]
+    fn test_small_integer_bounds() {
+        assert_eq!(eval("16383"), NumberValue::SmallInteger(16383));
+        assert_eq!(eval("16384"), NumberValue::LargePositiveInteger(large(16384)));
+        assert_eq!(eval("-16384"), NumberValue::SmallInteger(-16384));
+        assert_eq!(eval("-16385"), NumberValue::LargeNegativeInteger(large(16385)));
+    }
+
+    #[test]
+    fn test_large_integers() {
+        assert_eq!(eval("1e20"), NumberValue::LargePositiveInteger(BigUint::from(10u32).pow(20)));
+        let big = "123456789012345678901234567890";
+        assert_eq!(
+            eval(&format!("-{}", big)),
+            NumberValue::LargeNegativeInteger(big.parse().unwrap()),
+        );
+        assert_eq!(
+            eval("36rZZZZZZZZZZZZZZ"),
+            NumberValue::LargePositiveInteger(BigUint::from(36u32).pow(14) - 1u32),
+        );
+    }
+
+    #[test]
+    fn test_floats() {
+        assert_eq!(eval("1.5"), NumberValue::Float(1.5));
+        assert_eq!(eval("-1.5"), NumberValue::Float(-1.5));
+        assert_eq!(eval("1.5e2"), NumberValue::Float(150.0));
+        assert_eq!(eval("1.5e-3"), NumberValue::Float(0.0015));
+        assert_eq!(eval("1e-2"), NumberValue::Float(0.01));
+        assert_eq!(eval("0.1"), NumberValue::Float(0.1));
+        assert_eq!(eval("2r1.1"), NumberValue::Float(1.5));
+        assert_eq!(eval("16rA.8e1"), NumberValue::Float(168.0));
+        assert_eq!(eval("2r1e-2"), NumberValue::Float(0.25));
+    }
+
+    #[test]
+    fn test_scaled_decimals() {
+        assert_eq!(
+            eval("1.25s2"),
+            NumberValue::ScaledDecimal {
+                numerator: BigInt::from(125),
+                denominator: large(100),
+                scale: 2,
+            },
+        );
+        assert_eq!(
+            eval("-3s"),
+            NumberValue::ScaledDecimal {
+                numerator: BigInt::from(-3),
+                denominator: large(1),
+                scale: 0,
+            },
+        );
+    }
+
+    #[test]
+    fn test_invalid_numbers() {
+        let n = Num { radix: Some(2), ..Num::int_from_str("3") };
+        assert_eq!(n.evaluate(), Err(NumberError::InvalidDigit { digit: '3', radix: 2 }));
+        let n = Num { radix: Some(16), ..Num::int_from_str("ff") };
+        assert_eq!(n.evaluate(), Err(NumberError::InvalidDigit { digit: 'f', radix: 16 }));
+        let n = Num { radix: Some(37), ..Num::int_from_str("1") };
+        assert_eq!(n.evaluate(), Err(NumberError::InvalidRadix(37)));
+    }
+
+    #[test]
+    fn test_huge_exponents() {
+        for src in &["1e100000000", "2r1e2000000000", "1e-100000000s2", "16r1.8e100000000"] {
+            match Dialect::Ansi.config().parse_literal(src) {
+                Ok(Literal::Number(n, _)) => assert_eq!(n.evaluate(), Err(NumberError::TooLarge), "{}", src),
+                other => panic!("expected a number, got {:?}", other),
+            }
+        }
+        // Floats in base 10 just overflow.
+        assert_eq!(eval("1.5e100000000"), NumberValue::Float(f64::INFINITY));
+        assert_eq!(eval("1.5e-100000000"), NumberValue::Float(0.0));
+        assert_eq!(eval("2r1e524000"), NumberValue::LargePositiveInteger(BigUint::one() << 524000));
+    }
+}
@@ -0,0 +1,500 @@
+// Parser for the `!`-delimited chunk format that Smalltalk source files are
+// filed in and out with.
+//
+// A file is a sequence of chunks, each ending with a `!`. A `!` inside a chunk
+// is written twice. Most chunks are statements to evaluate, but an empty chunk
+// starts a section of methods: the chunk after it says which class and
+// category the methods are for, and each following chunk is a method, until an
+// empty chunk ends the section:
+//
+//     !Point methodsFor: 'arithmetic'!
+//     + delta
+//         ^(x + delta x) @ (y + delta y)! !
+
+use combine::{optional, Parser};
+use combine::combinator::satisfy_map;
+use combine::primitives::{Positioner, SourcePosition};
+
+use super::*;
+
+/// Parse a source file in the chunk (fileIn) format.
+///
+/// Class definition messages become `Chunk::Class`, and the comment, class
+/// instance variables and methods given for a class later in the file are
+/// collected into its definition. Sections of methods for classes defined
+/// elsewhere become `Chunk::Methods`, and any other chunk is a `Chunk::DoIt`.
+///
+/// ```
+/// use smoltok::parser::parse_chunks;
+/// use smoltok::syntax::Chunk;
+///
+/// let src = "Object subclass: #Point
+///     instanceVariableNames: 'x y'
+///     classVariableNames: ''
+///     category: 'Graphics-Primitives'!
+///
+/// !Point methodsFor: 'accessing'!
+/// x
+///     ^x! !";
+/// match parse_chunks(src).unwrap()[0] {
+///     Chunk::Class(ref class) => {
+///         assert_eq!(class.name.0, "Point");
+///         assert_eq!(class.instance_vars, vec!["x", "y"]);
+///         assert_eq!(class.categories[0].name, "accessing");
+///     }
+///     ref c => panic!("expected a class, got {:?}", c),
+/// }
+/// ```
+pub fn parse_chunks(src: &str) -> Result<Vec<Chunk>, SyntaxError> {
+    ParserConfig::default().parse_chunks(src)
+}
+
+impl ParserConfig {
+    /// Parse a source file in the chunk format in this configuration. See
+    /// `parse_chunks`.
+    pub fn parse_chunks(&self, src: &str) -> Result<Vec<Chunk>, SyntaxError> {
+        let raw = split_chunks(src);
+        let pieces = roles(&raw).into_iter().zip(&raw)
+            .map(|(role, chunk)| parse_piece(self, src, chunk, role))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(assemble(&pieces))
+    }
+}
+
+// The text of a chunk, with `!!` turned back into `!`.
+pub(super) struct RawChunk {
+    pub(super) text: String,
+    // Where each `!!` is in the file.
+    pub(super) collapsed: Vec<Position>,
+    // Where the text starts in the file.
+    pub(super) start: SourcePosition,
+    // Where the `!` ending the chunk is, or the end of the file.
+    pub(super) end: SourcePosition,
+    // The byte offsets of `start` and `end`.
+    pub(super) offsets: (usize, usize),
+}
+
+impl RawChunk {
+    fn is_blank(&self) -> bool {
+        self.text.trim().is_empty()
+    }
+}
+
+pub(super) fn split_chunks(src: &str) -> Vec<RawChunk> {
+    let mut chunks = Vec::new();
+    let mut position = char::start();
+    let new_chunk = |start, offset| {
+        RawChunk { text: String::new(), collapsed: Vec::new(), start, end: start, offsets: (offset, offset) }
+    };
+    let mut chunk = new_chunk(position, 0);
+    let mut chars = src.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        let here = position;
+        c.update(&mut position);
+        if c != '!' {
+            chunk.text.push(c);
+        } else if chars.peek().map(|&(_, c)| c) == Some('!') {
+            chars.next();
+            '!'.update(&mut position);
+            chunk.text.push('!');
+            chunk.collapsed.push(pos(here));
+        } else {
+            chunk.end = here;
+            chunk.offsets.1 = offset;
+            chunks.push(chunk);
+            chunk = new_chunk(position, offset + 1);
+        }
+    }
+    chunk.end = position;
+    chunk.offsets.1 = src.len();
+    chunks.push(chunk);
+    chunks
+}
+
+// What a chunk is, which depends on the chunks before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Role {
+    // An empty chunk, which starts or ends a section of methods.
+    Blank,
+    // The chunk after an empty one, which says what the methods are for.
+    Header,
+    Method,
+    Statements,
+}
+
+pub(super) fn roles(raw: &[RawChunk]) -> Vec<Role> {
+    let mut roles = Vec::with_capacity(raw.len());
+    let mut chunks = raw.iter().peekable();
+    while let Some(chunk) = chunks.next() {
+        if !chunk.is_blank() {
+            roles.push(Role::Statements);
+            continue;
+        }
+        roles.push(Role::Blank);
+        if chunks.peek().is_none_or(|header| header.is_blank()) {
+            continue;
+        }
+        chunks.next();
+        roles.push(Role::Header);
+        for method in chunks.by_ref() {
+            if method.is_blank() {
+                roles.push(Role::Blank);
+                break;
+            }
+            roles.push(Role::Method);
+        }
+    }
+    roles
+}
+
+// A chunk parsed according to its role.
+#[derive(Debug, Clone)]
+pub(super) enum Piece {
+    Blank,
+    Header(Ident, bool, String, Span),
+    Method(Method),
+    Statements(Vec<Ident>, Vec<Statement>),
+}
+
+pub(super) fn parse_piece(config: &ParserConfig, src: &str, chunk: &RawChunk, role: Role)
+    -> Result<Piece, SyntaxError>
+{
+    let lexed = lex_collapsed(config, src, &chunk.text, pos(chunk.start), &chunk.collapsed);
+    Ok(match role {
+        Role::Blank => Piece::Blank,
+        Role::Header => {
+            let (class, meta, name) = parse_all(config, &lexed, src, category_header())?;
+            let span = class.1.to(Span::new(pos(chunk.end), pos(chunk.end)));
+            Piece::Header(class, meta, name, span)
+        }
+        Role::Method => Piece::Method(parse_all(config, &lexed, src, method_p())?),
+        Role::Statements => match parse_all(config, &lexed, src, do_it())? {
+            Syntax::DoIt { temps, statements } => Piece::Statements(temps, statements),
+            Syntax::Expr(_) => unreachable!("do_it() only parses doIts"),
+        },
+    })
+}
+
+// Group the parsed chunks of a file into classes, sections of methods and
+// doIts.
+pub(super) fn assemble(pieces: &[Piece]) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut section = None;
+    for piece in pieces {
+        match *piece {
+            Piece::Blank => if let Some(category) = section.take() {
+                add_methods(&mut chunks, category);
+            },
+            Piece::Header(ref class, meta, ref name, span) => {
+                section = Some(MethodCategory {
+                    class: class.clone(),
+                    meta,
+                    name: name.clone(),
+                    methods: Vec::new(),
+                    span,
+                });
+            }
+            Piece::Method(ref method) => if let Some(ref mut category) = section {
+                category.methods.push(method.clone());
+            },
+            Piece::Statements(ref temps, ref stmts) => {
+                let first = temps.first().map(|t| t.1).or_else(|| stmts.first().map(Statement::span));
+                let span = match (first, stmts.last()) {
+                    (Some(first), Some(last)) => first.to(last.span()),
+                    _ => continue,
+                };
+                if !temps.is_empty() {
+                    chunks.push(Chunk::DoIt { temps: temps.clone(), statements: stmts.clone(), span });
+                } else if let Some(class) = class_def(stmts, span) {
+                    chunks.push(Chunk::Class(class));
+                } else if !describe_class(&mut chunks, stmts) {
+                    chunks.push(Chunk::DoIt { temps: Vec::new(), statements: stmts.clone(), span });
+                }
+            }
+        }
+    }
+    if let Some(category) = section {
+        add_methods(&mut chunks, category);
+    }
+    chunks
+}
+
+// Parse the header of a section of methods: `Foo methodsFor: 'category'` or
+// `Foo class methodsFor: 'category'`. The `stamp:` that later dialects add
+// is allowed and ignored.
+parser! {
+    fn category_header['a]()(Input<'a>) -> (Ident, bool, String)
+    {
+        let keyword = |name: &'static str| satisfy_map(move |t: &Token| match t.kind {
+            TokenKind::Keyword(ref k) if k == name => Some(()),
+            _ => None,
+        });
+        let class_side = satisfy_map(|t: &Token| match t.kind {
+            TokenKind::Ident(ref id) if id == "class" => Some(()),
+            _ => None,
+        });
+        let string = || label(satisfy_map(|t: &Token| match t.kind {
+            TokenKind::Str(ref s) => Some(s.clone()),
+            _ => None,
+        }), "string");
+        let stamp = (keyword("stamp:"), string());
+
+        (ident(), optional(class_side), label(keyword("methodsFor:"), "`methodsFor:`"), string(),
+         optional(stamp))
+            .map(|(class, meta, _, name, _)| (class, meta.is_some(), name))
+    }
+}
+
+// Recognise a class definition message.
+fn class_def(stmts: &[Statement], span: Span) -> Option<ClassDef> {
+    let (receiver, kws) = match stmts {
+        [Statement::E(Expr::Message { receiver, selector: Msg::Kwargs(kws), .. })] =>
+            (receiver, kws),
+        _ => return None,
+    };
+    let superclass = match **receiver {
+        Expr::Pseudo(PseudoVar::Nil, _) => None,
+        Expr::Id(ref id) => Some(id.clone()),
+        _ => return None,
+    };
+    let kind = match kws[0].id.0.as_str() {
+        "subclass:" => ClassKind::Fixed,
+        "variableSubclass:" => ClassKind::Pointers,
+        "variableByteSubclass:" => ClassKind::Bytes,
+        "variableWordSubclass:" => ClassKind::Words,
+        _ => return None,
+    };
+    let rest: Vec<&str> = kws[1..].iter().map(|kw| kw.id.0.as_str()).collect();
+    let with_pools = match rest[..] {
+        ["instanceVariableNames:", "classVariableNames:", "poolDictionaries:", "category:"] =>
+            true,
+        ["instanceVariableNames:", "classVariableNames:", "category:"] => false,
+        _ => return None,
+    };
+    let name = match kws[0].val {
+        Expr::Lit(Literal::Symbol(ref s, span)) => Ident(s.0.clone(), span),
+        _ => return None,
+    };
+    let mut strings = Vec::new();
+    for kw in &kws[1..] {
+        match kw.val {
+            Expr::Lit(Literal::Str(ref s, _)) => strings.push(s.as_str()),
+            _ => return None,
+        }
+    }
+    let names = |s: &str| s.split_whitespace().map(String::from).collect();
+    Some(ClassDef {
+        name,
+        superclass,
+        kind,
+        instance_vars: names(strings[0]),
+        class_vars: names(strings[1]),
+        pool_dictionaries: if with_pools { Some(names(strings[2])) } else { None },
+        category: String::from(strings[strings.len() - 1]),
+        comment: None,
+        class_instance_vars: Vec::new(),
+        categories: Vec::new(),
+        span,
+    })
+}
+
+// Record `Foo comment: '...'` or `Foo class instanceVariableNames: '...'` on
+// the definition of `Foo`, if it was read earlier. Returns whether the
+// statements were one of those.
+fn describe_class(chunks: &mut [Chunk], stmts: &[Statement]) -> bool {
+    let (receiver, kw) = match stmts {
+        [Statement::E(Expr::Message { receiver, selector: Msg::Kwargs(kws), .. })]
+            if kws.len() == 1 => (receiver, &kws[0]),
+        _ => return false,
+    };
+    let text = match kw.val {
+        Expr::Lit(Literal::Str(ref s, _)) => s.clone(),
+        _ => return false,
+    };
+    let (name, meta) = match **receiver {
+        Expr::Id(ref id) => (&id.0, false),
+        Expr::Message { ref receiver, selector: Msg::Unary(ref sel), .. }
+            if sel.0 == "class" => match **receiver {
+                Expr::Id(ref id) => (&id.0, true),
+                _ => return false,
+            },
+        _ => return false,
+    };
+    let class = match find_class(chunks, name) {
+        Some(class) => class,
+        None => return false,
+    };
+    match (kw.id.0.as_str(), meta) {
+        ("comment:", false) => class.comment = Some(text),
+        ("instanceVariableNames:", true) =>
+            class.class_instance_vars = text.split_whitespace().map(String::from).collect(),
+        _ => return false,
+    }
+    true
+}
+
+fn find_class<'a>(chunks: &'a mut [Chunk], name: &str) -> Option<&'a mut ClassDef> {
+    chunks.iter_mut().filter_map(|chunk| match *chunk {
+        Chunk::Class(ref mut class) if class.name.0 == name => Some(class),
+        _ => None,
+    }).last()
+}
+
+// Add a section of methods to the definition of its class, or as a chunk of
+// its own if the class was not defined in the file.
+fn add_methods(chunks: &mut Vec<Chunk>, category: MethodCategory) {
+    match find_class(chunks, &category.class.0) {
+        Some(class) => class.categories.push(category),
+        None => chunks.push(Chunk::Methods(category)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fileout::file_out;
+
+    const POINT: &str = "\"A point in the plane.\"
+Object subclass: #Point
+    instanceVariableNames: 'x y'
+    classVariableNames: ''
+    poolDictionaries: ''
+    category: 'Graphics-Primitives'!
+
+Point comment: 'I represent an x-y pair of numbers usually designating a location on the screen.'!
+
+Point class instanceVariableNames: 'origin'!
+
+!Point methodsFor: 'accessing'!
+x
+    \"Answer the x coordinate.\"
+    ^x!
+y
+    ^y! !
+
+!Point class methodsFor: 'instance creation'!
+x: xInteger y: yInteger
+    ^self new setX: xInteger setY: yInteger! !
+
+!Integer methodsFor: 'testing'!
+isBang
+    ^self printString = '!!'! !
+
+Transcript show: 'done!!'!
+";
+
+    fn class(chunk: &Chunk) -> &ClassDef {
+        match *chunk {
+            Chunk::Class(ref class) => class,
+            ref c => panic!("expected a class, got {:?}", c),
+        }
+    }
+
+    #[test]
+    fn test_class_definition() {
+        let chunks = parse_chunks(POINT).unwrap();
+        assert_eq!(chunks.len(), 3);
+        let point = class(&chunks[0]);
+        assert_eq_ignoring_spans!(point.name, mk_ident("Point"));
+        assert_eq_ignoring_spans!(point.superclass, Some(mk_ident("Object")));
+        assert_eq!(point.kind, ClassKind::Fixed);
+        assert_eq!(point.instance_vars, vec!["x", "y"]);
+        assert!(point.class_vars.is_empty());
+        assert_eq!(point.pool_dictionaries, Some(vec![]));
+        assert_eq!(point.category, "Graphics-Primitives");
+        assert!(point.comment.as_ref().unwrap().starts_with("I represent"));
+        assert_eq!(point.class_instance_vars, vec!["origin"]);
+        assert_eq!((point.span.start.line, point.span.end.line), (2, 6));
+    }
+
+    #[test]
+    fn test_methods_grouped_by_class_and_category() {
+        let chunks = parse_chunks(POINT).unwrap();
+        let point = class(&chunks[0]);
+        assert_eq!(point.categories.len(), 2);
+        let accessing = &point.categories[0];
+        assert_eq!((accessing.name.as_str(), accessing.meta), ("accessing", false));
+        assert_eq!(accessing.methods.len(), 2);
+        assert_eq!(accessing.methods[0].comment, Some(String::from("Answer the x coordinate.")));
+        assert_eq_ignoring_spans!(accessing.methods[1], parse_method("y ^y").unwrap());
+        assert_eq!(accessing.methods[1].span.start.line, 16);
+        let creation = &point.categories[1];
+        assert_eq!((creation.name.as_str(), creation.meta), ("instance creation", true));
+        assert_eq!(creation.methods.len(), 1);
+    }
+
+    #[test]
+    fn test_methods_for_other_classes_and_do_its() {
+        let chunks = parse_chunks(POINT).unwrap();
+        match chunks[1] {
+            Chunk::Methods(ref category) => {
+                assert_eq_ignoring_spans!(category.class, mk_ident("Integer"));
+                assert_eq_ignoring_spans!(category.methods[0], parse_method("isBang ^self printString = '!'").unwrap());
+            }
+            ref c => panic!("expected methods, got {:?}", c),
+        }
+        match chunks[2] {
+            Chunk::DoIt { statements: ref stmts, .. } =>
+                assert_eq_ignoring_spans!(*stmts, parse_statements("Transcript show: 'done!'").unwrap()),
+            ref c => panic!("expected a do-it, got {:?}", c),
+        }
+    }
+
+    #[test]
+    fn test_do_it_temporaries() {
+        let chunks = parse_chunks("| t | t <- 3. t!\nTranscript show: 'hi'!").unwrap();
+        assert_eq!(chunks.len(), 2);
+        match chunks[0] {
+            Chunk::DoIt { ref temps, ref statements, span } => {
+                assert_eq_ignoring_spans!(*temps, vec![mk_ident("t")]);
+                assert_eq_ignoring_spans!(*statements, parse_statements("t <- 3. t").unwrap());
+                assert_eq!((span.start.column, span.end.column), (3, 16));
+            }
+            ref c => panic!("expected a do-it, got {:?}", c),
+        }
+        assert_eq!(file_out(&chunks), "| t |\nt <- 3.\nt!\n\nTranscript show: 'hi'!\n");
+    }
+
+    #[test]
+    fn test_class_kinds() {
+        let src = "nil variableByteSubclass: #Bytes
+            instanceVariableNames: '' classVariableNames: 'A B' category: 'Kernel'!";
+        let chunks = parse_chunks(src).unwrap();
+        let bytes = class(&chunks[0]);
+        assert_eq!(bytes.superclass, None);
+        assert_eq!(bytes.kind, ClassKind::Bytes);
+        assert_eq!(bytes.class_vars, vec!["A", "B"]);
+        assert_eq!(bytes.pool_dictionaries, None);
+    }
+
+    #[test]
+    fn test_error_in_method_points_into_file() {
+        let src = "!Foo methodsFor: 'bar'!\nbaz\n    ^self at: )! !";
+        let err = parse_chunks(src).unwrap_err();
+        assert_eq!((err.position.line, err.position.column), (3, 15));
+        assert_eq!(err.snippet, "    ^self at: )! !");
+    }
+
+    #[test]
+    fn test_error_after_doubled_bang() {
+        let err = parse_chunks("'a!!b' size. x foo: ?!").unwrap_err();
+        assert_eq!((err.position.line, err.position.column), (1, 21));
+        assert_eq!(err.unexpected, Some(String::from("?")));
+
+        let chunks = parse_chunks("'!!!!' , 'x'. y!").unwrap();
+        match chunks[0] {
+            Chunk::DoIt { ref statements, .. } => {
+                assert_eq!(statements[0].span().end.column, 13);
+                assert_eq!(statements[1].span().start.column, 15);
+            }
+            ref c => panic!("expected a do-it, got {:?}", c),
+        }
+    }
+
+    #[test]
+    fn test_error_in_category_header() {
+        let err = parse_chunks("!Foo categoryFor: 'bar'!\nbaz! !").unwrap_err();
+        assert_eq!((err.position.line, err.position.column), (1, 6));
+        assert_eq!(err.expected, vec!["`methodsFor:`"]);
+    }
+}
@@ -0,0 +1,143 @@
+//! The syntax the parser accepts, beyond that of the blue book.
+//!
+//! Smalltalk sources come from many places. The blue book prints assignment
+//! and return as the arrow glyphs `←` and `↑`, Xerox images store the left
+//! arrow as `_`, and Squeak and Pharo spell assignment `:=`. Later dialects
+//! also added syntax of their own, such as byte arrays and pragmas. A
+//! `ParserConfig` says which of these to accept, so that sources can be loaded
+//! as they are, without preprocessing, and a `Dialect` gives the configuration
+//! for a known family of sources.
+
+/// Options for the parser. The `Default` configuration accepts only the ASCII
+/// spellings of the blue book syntax, `<-` and `^`.
+///
+/// ```
+/// use smoltok::parser::ParserConfig;
+///
+/// let squeak = ParserConfig { colon_equals: true, ..ParserConfig::default() };
+/// assert!(squeak.parse_statements("x := 3. ^x").is_ok());
+/// assert!(ParserConfig::default().parse_statements("x := 3. ^x").is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserConfig {
+    /// Accept `<-` for assignment. Without it, `a<-1` sends `<` with the
+    /// argument `-1`, as it does in dialects which spell assignment `:=`.
+    pub left_arrow_assignment: bool,
+    /// Accept `_` for assignment, which is how Xerox images store `←`.
+    pub underscore_assignment: bool,
+    /// Accept `_` in identifiers, as in `my_var`. Ignored when
+    /// `underscore_assignment` is set, which reads every `_` as assignment.
+    pub underscore_identifiers: bool,
+    /// Accept the glyphs `←` for assignment and `↑` for return.
+    pub arrow_glyphs: bool,
+    /// Accept `:=` for assignment.
+    pub colon_equals: bool,
+    /// Accept byte array literals, such as `#[1 2 255]`.
+    pub byte_arrays: bool,
+    /// Accept brace arrays, such as `{a. b + 1}`, whose elements are
+    /// evaluated.
+    pub brace_arrays: bool,
+    /// Accept scaled decimals, such as `1.5s2`.
+    pub scaled_decimals: bool,
+    /// Read `true`, `false` and `nil` inside a literal array as those
+    /// constants, rather than as symbols.
+    pub literal_constants: bool,
+    /// Accept pragmas other than `<primitive: N>`, such as
+    /// `<category: 'accessing'>`, at the start of a method.
+    pub pragmas: bool,
+    /// Read `thisContext` as the active context, rather than as a variable.
+    pub this_context: bool,
+}
+
+impl ParserConfig {
+    /// The blue book syntax, spelled in ASCII.
+    pub const BLUE_BOOK: ParserConfig = ParserConfig {
+        left_arrow_assignment: true,
+        underscore_assignment: false,
+        underscore_identifiers: false,
+        arrow_glyphs: false,
+        colon_equals: false,
+        byte_arrays: false,
+        brace_arrays: false,
+        scaled_decimals: false,
+        literal_constants: false,
+        pragmas: false,
+        this_context: false,
+    };
+
+    /// Every spelling of assignment and return that any supported source
+    /// uses, and no other extensions.
+    pub const ALL_SPELLINGS: ParserConfig = ParserConfig {
+        underscore_assignment: true,
+        arrow_glyphs: true,
+        colon_equals: true,
+        ..ParserConfig::BLUE_BOOK
+    };
+}
+
+/// A family of Smalltalk sources, each with its own syntax.
+///
+/// ```
+/// use smoltok::parser::Dialect;
+///
+/// let pharo = Dialect::Pharo.config();
+/// assert!(pharo.parse_expr("{#[1 2]. 1.5s2. thisContext}").is_ok());
+/// assert!(Dialect::BlueBook.config().parse_expr("#[1 2]").is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dialect {
+    /// The language of the blue book, spelled in ASCII.
+    #[default]
+    BlueBook,
+    /// Squeak, which still reads the `_` assignment of older sources.
+    Squeak,
+    /// Pharo.
+    Pharo,
+    /// The ANSI standard, which has byte arrays, scaled decimals and literal
+    /// constants, but no brace arrays, pragmas or `thisContext`.
+    Ansi,
+}
+
+impl Dialect {
+    /// The parser configuration for sources in this dialect.
+    pub fn config(self) -> ParserConfig {
+        let modern = ParserConfig {
+            left_arrow_assignment: false,
+            colon_equals: true,
+            byte_arrays: true,
+            brace_arrays: true,
+            scaled_decimals: true,
+            literal_constants: true,
+            pragmas: true,
+            this_context: true,
+            underscore_identifiers: true,
+            ..ParserConfig::BLUE_BOOK
+        };
+        match self {
+            Dialect::BlueBook => ParserConfig::BLUE_BOOK,
+            Dialect::Squeak => ParserConfig { underscore_assignment: true, ..modern },
+            Dialect::Pharo => modern,
+            Dialect::Ansi => ParserConfig {
+                left_arrow_assignment: false,
+                colon_equals: true,
+                byte_arrays: true,
+                scaled_decimals: true,
+                literal_constants: true,
+                underscore_identifiers: true,
+                ..ParserConfig::BLUE_BOOK
+            },
+        }
+    }
+}
+
+impl From<Dialect> for ParserConfig {
+    fn from(dialect: Dialect) -> Self {
+        dialect.config()
+    }
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        ParserConfig::BLUE_BOOK
+    }
+}
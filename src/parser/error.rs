@@ -0,0 +1,187 @@
+// Human readable syntax errors.
+
+use std::error::Error as StdError;
+use std::fmt;
+
+use combine::primitives::{Error, Info, ParseError, StreamOnce};
+
+use syntax::Position;
+
+/// A failure to parse some Smalltalk source.
+///
+/// The `Display` implementation renders the error the way a compiler would:
+/// a message, the offending source line and a caret under the problem, with
+/// any hints underneath.
+///
+/// ```
+/// use smoltok::parser::parse_expr;
+///
+/// let err = parse_expr("a at: )").unwrap_err();
+/// assert_eq!((err.position.line, err.position.column), (1, 7));
+/// assert_eq!(
+///     err.to_string(),
+///     "error: unexpected `)`, expected expression
+///  --> line 1, column 7
+///   |
+/// 1 | a at: )
+///   |       ^
+///   = hint: keyword message argument missing after `at:`"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    /// Where the parser gave up.
+    pub position: Position,
+    /// The text found at `position`, or `None` at the end of the input.
+    pub unexpected: Option<String>,
+    /// Descriptions of what would have been accepted at `position`.
+    pub expected: Vec<String>,
+    /// Suggestions about what is wrong, in Smalltalk terms.
+    pub hints: Vec<String>,
+    /// The source line containing `position`, without its line terminator.
+    pub snippet: String,
+}
+
+impl SyntaxError {
+    /// Build a `SyntaxError` at `position` in `src`.
+    pub(crate) fn new(src: &str, position: Position, unexpected: Option<String>,
+                      expected: Vec<String>, hints: Vec<String>) -> Self {
+        let snippet = src.lines().nth(position.line - 1).unwrap_or("");
+        SyntaxError { position, unexpected, expected, hints, snippet: String::from(snippet) }
+    }
+
+    /// Build a `SyntaxError` from the error `combine` produced while parsing
+    /// `src`.
+    pub(crate) fn from_parse_error<S>(src: &str, err: &ParseError<S>) -> Self
+        where S: StreamOnce<Position = Position>,
+              S::Item: fmt::Display,
+              S::Range: fmt::Display
+    {
+        let position = err.position;
+        let snippet = src.lines().nth(position.line - 1).unwrap_or("");
+        let rest = rest_of_line(snippet, position.column);
+        // Errors which only explain themselves, such as a digit which is too
+        // large for its radix, are about the character at `position`.
+        let mut unexpected = rest.chars().next().map(|c| c.to_string());
+        let mut expected = Vec::new();
+        let mut hints = Vec::new();
+        for e in &err.errors {
+            match *e {
+                Error::Unexpected(Info::Borrowed("end of input")) => unexpected = None,
+                Error::Unexpected(ref info) =>
+                    unexpected = Some(info_text(info)),
+                Error::Expected(ref info) =>
+                    push_new(&mut expected, expected_text(info)),
+                Error::Message(ref info) => push_new(&mut hints, info_text(info)),
+                Error::Other(ref err) => push_new(&mut hints, err.to_string()),
+            }
+        }
+
+        if rest.starts_with(":=") {
+            push_new(
+                &mut hints,
+                String::from("blue book assignment is written `<-`, not `:=`"),
+            );
+        }
+        let at_end = expected.iter().any(|e| e == "end of input");
+        match unexpected {
+            Some(ref u) if at_end && (u == ")" || u == "]") =>
+                push_new(&mut hints, format!("unmatched `{}`", u)),
+            _ => (),
+        }
+
+        SyntaxError::new(src, position, unexpected, expected, hints)
+    }
+
+    /// A one line summary of the error, such as
+    /// "unexpected `)`, expected expression".
+    pub fn message(&self) -> String {
+        let found = match self.unexpected {
+            Some(ref u) => format!("unexpected `{}`", u),
+            None => String::from("unexpected end of input"),
+        };
+        if self.expected.is_empty() {
+            found
+        } else {
+            format!("{}, expected {}", found, or_list(&self.expected))
+        }
+    }
+
+    /// The source line with a caret underline marking the offending token.
+    pub fn underline(&self) -> String {
+        let before: String = self.snippet
+            .chars()
+            .take(self.position.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let rest = rest_of_line(&self.snippet, self.position.column);
+        let width = match rest.chars().next() {
+            Some(c) if c.is_alphanumeric() =>
+                rest.chars().take_while(|c| c.is_alphanumeric()).count(),
+            _ => 1,
+        };
+        format!("{}{}", before, "^".repeat(width))
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let line_no = self.position.line.to_string();
+        let gutter = " ".repeat(line_no.len());
+        writeln!(f, "error: {}", self.message())?;
+        writeln!(
+            f,
+            "{}--> line {}, column {}",
+            gutter, self.position.line, self.position.column
+        )?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", line_no, self.snippet)?;
+        write!(f, "{} | {}", gutter, self.underline())?;
+        for hint in &self.hints {
+            write!(f, "\n{} = hint: {}", gutter, hint)?;
+        }
+        Ok(())
+    }
+}
+
+impl StdError for SyntaxError {}
+
+fn info_text<T: fmt::Display, R: fmt::Display>(info: &Info<T, R>) -> String {
+    match *info {
+        Info::Token(ref t) => t.to_string(),
+        Info::Range(ref r) => r.to_string(),
+        Info::Owned(ref s) => s.clone(),
+        Info::Borrowed(s) => s.to_string(),
+    }
+}
+
+// Quote literal tokens, but leave descriptions like "expression" alone.
+fn expected_text<T: fmt::Display, R: fmt::Display>(info: &Info<T, R>) -> String {
+    match *info {
+        Info::Token(_) | Info::Range(_) => format!("`{}`", info_text(info)),
+        _ => info_text(info),
+    }
+}
+
+fn push_new(v: &mut Vec<String>, s: String) {
+    if !v.contains(&s) {
+        v.push(s);
+    }
+}
+
+// The part of `line` starting at the 1-based `column`.
+fn rest_of_line(line: &str, column: usize) -> &str {
+    match line.char_indices().nth(column.saturating_sub(1)) {
+        Some((i, _)) => &line[i..],
+        None => "",
+    }
+}
+
+// Join alternatives as "a, b or c".
+fn or_list(items: &[String]) -> String {
+    match items.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, init)) => format!("{} or {}", init.join(", "), last),
+        None => String::new(),
+    }
+}
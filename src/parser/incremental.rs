@@ -0,0 +1,438 @@
+// Reparsing a chunk file after an edit.
+//
+// Parsing a chunk only depends on its text, on where it starts and on what
+// the chunks before it make it: statements, the header of a section of
+// methods, or a method. After an edit the file is split into chunks again,
+// which is cheap, and a chunk whose text and role are the same as those of a
+// chunk outside the edited region, and which starts at the same column, is not
+// parsed again: its old tree is moved to the line it now starts on. Grouping
+// the chunks into classes is also cheap, so it is redone from scratch.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use super::*;
+use super::chunk::{assemble, parse_piece, roles, split_chunks, Piece, RawChunk, Role};
+
+/// A change to a source text: the bytes in `range` are replaced by `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn new(range: Range<usize>, text: &str) -> Self {
+        TextEdit { range, text: String::from(text) }
+    }
+
+    /// The text `src` becomes after the edit.
+    ///
+    /// Panics if the range is out of bounds or splits a character.
+    pub fn apply(&self, src: &str) -> String {
+        let mut out = String::with_capacity(src.len() + self.text.len());
+        out.push_str(&src[..self.range.start]);
+        out.push_str(&self.text);
+        out.push_str(&src[self.range.end..]);
+        out
+    }
+}
+
+/// A source file in the chunk format, parsed so that it can be parsed again
+/// cheaply after an edit. `chunks` is always what `parse_chunks` gives for
+/// `source`, spans included.
+///
+/// ```
+/// use smoltok::parser::{ChunkFile, ParserConfig, TextEdit};
+///
+/// let src = "!Point methodsFor: 'accessing'!
+/// x
+///     ^x!
+/// y
+///     ^y! !";
+/// let mut file = ChunkFile::new(ParserConfig::default(), src).unwrap();
+/// let at = src.find("^x").unwrap() + 2;
+/// file.edit(&TextEdit::new(at..at, " + 1")).unwrap();
+/// assert_eq!(file.reparsed(), 1);
+/// assert_eq!(file.chunks(), &ParserConfig::default().parse_chunks(file.source()).unwrap()[..]);
+/// ```
+pub struct ChunkFile {
+    config: ParserConfig,
+    src: String,
+    raw: Vec<RawChunk>,
+    roles: Vec<Role>,
+    pieces: Vec<Piece>,
+    chunks: Vec<Chunk>,
+    reparsed: usize,
+}
+
+impl ChunkFile {
+    /// Parse a whole file.
+    pub fn new(config: ParserConfig, src: &str) -> Result<ChunkFile, SyntaxError> {
+        let raw = split_chunks(src);
+        let roles = roles(&raw);
+        let pieces = roles.iter().zip(&raw)
+            .map(|(&role, chunk)| parse_piece(&config, src, chunk, role))
+            .collect::<Result<Vec<_>, _>>()?;
+        let chunks = assemble(&pieces);
+        let reparsed = pieces.len();
+        Ok(ChunkFile { config, src: String::from(src), raw, roles, pieces, chunks, reparsed })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.src
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    /// How many chunks the last edit, or the first parse, had to parse.
+    pub fn reparsed(&self) -> usize {
+        self.reparsed
+    }
+
+    /// Apply an edit to the source and parse the chunks it changed. If the
+    /// new source has a syntax error, the error is returned and the file is
+    /// left as it was.
+    pub fn edit(&mut self, edit: &TextEdit) -> Result<(), SyntaxError> {
+        let src = edit.apply(&self.src);
+        let raw = split_chunks(&src);
+        let roles = roles(&raw);
+
+        // Where the chunks outside the edited region start in the new source.
+        let growth = edit.text.len() as isize - edit.range.len() as isize;
+        let old: HashMap<usize, usize> = self.raw.iter().enumerate()
+            .filter_map(|(i, chunk)| {
+                let (start, end) = chunk.offsets;
+                if end < edit.range.start {
+                    Some((start, i))
+                } else if start >= edit.range.end {
+                    Some(((start as isize + growth) as usize, i))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let mut pieces = Vec::with_capacity(raw.len());
+        let mut reparsed = 0;
+        for (&role, chunk) in roles.iter().zip(&raw) {
+            let reuse = old.get(&chunk.offsets.0).copied().filter(|&i| {
+                let before = &self.raw[i];
+                // The starting column only matters if something is on the
+                // first line: a chunk usually starts with the end of the
+                // line its `!` is on.
+                let first_line = chunk.text.split('\n').next().unwrap_or("");
+                self.roles[i] == role && before.text == chunk.text
+                    && (before.start.column == chunk.start.column || first_line.trim().is_empty())
+            });
+            pieces.push(match reuse {
+                Some(i) => {
+                    let mut piece = self.pieces[i].clone();
+                    piece.shift(chunk.start.line as isize - self.raw[i].start.line as isize);
+                    piece
+                }
+                None => {
+                    reparsed += 1;
+                    parse_piece(&self.config, &src, chunk, role)?
+                }
+            });
+        }
+
+        self.chunks = assemble(&pieces);
+        self.src = src;
+        self.raw = raw;
+        self.roles = roles;
+        self.pieces = pieces;
+        self.reparsed = reparsed;
+        Ok(())
+    }
+}
+
+// Move a tree down by a number of lines, keeping the columns.
+trait Shift {
+    fn shift(&mut self, lines: isize);
+}
+
+impl Shift for Position {
+    fn shift(&mut self, lines: isize) {
+        self.line = (self.line as isize + lines) as usize;
+    }
+}
+
+impl Shift for Span {
+    fn shift(&mut self, lines: isize) {
+        self.start.shift(lines);
+        self.end.shift(lines);
+    }
+}
+
+impl Shift for Ident {
+    fn shift(&mut self, lines: isize) {
+        self.1.shift(lines);
+    }
+}
+
+impl<T: Shift> Shift for Vec<T> {
+    fn shift(&mut self, lines: isize) {
+        for t in self {
+            t.shift(lines);
+        }
+    }
+}
+
+impl<T: Shift> Shift for Option<T> {
+    fn shift(&mut self, lines: isize) {
+        if let Some(ref mut t) = *self {
+            t.shift(lines);
+        }
+    }
+}
+
+impl<T: Shift> Shift for Box<T> {
+    fn shift(&mut self, lines: isize) {
+        (**self).shift(lines);
+    }
+}
+
+impl Shift for Piece {
+    fn shift(&mut self, lines: isize) {
+        match *self {
+            Piece::Blank => (),
+            Piece::Header(ref mut class, _, _, ref mut span) => {
+                class.shift(lines);
+                span.shift(lines);
+            }
+            Piece::Method(ref mut method) => method.shift(lines),
+            Piece::Statements(ref mut temps, ref mut stmts) => {
+                temps.shift(lines);
+                stmts.shift(lines);
+            }
+        }
+    }
+}
+
+impl Shift for Method {
+    fn shift(&mut self, lines: isize) {
+        match self.sig {
+            MsgPat::Unary(ref mut id) => id.shift(lines),
+            MsgPat::Bin(ref mut sel, ref mut var) => {
+                sel.shift(lines);
+                var.shift(lines);
+            }
+            MsgPat::Kwargs(ref mut kws) => for kw in kws {
+                kw.keyword.shift(lines);
+                kw.var.shift(lines);
+            },
+        }
+        for pragma in &mut self.pragmas {
+            pragma.args.shift(lines);
+            pragma.span.shift(lines);
+        }
+        self.temps.shift(lines);
+        self.stmts.shift(lines);
+        self.span.shift(lines);
+    }
+}
+
+impl Shift for Statement {
+    fn shift(&mut self, lines: isize) {
+        match *self {
+            Statement::E(ref mut e) => e.shift(lines),
+            Statement::Ret(ref mut e, ref mut span) => {
+                e.shift(lines);
+                span.shift(lines);
+            }
+        }
+    }
+}
+
+impl Shift for Expr {
+    fn shift(&mut self, lines: isize) {
+        match *self {
+            Expr::Id(ref mut id) => id.shift(lines),
+            Expr::Assign(ref mut id, ref mut val) => {
+                id.shift(lines);
+                val.shift(lines);
+            }
+            Expr::Lit(ref mut lit) => lit.shift(lines),
+            Expr::Message { ref mut receiver, ref mut selector, ref mut span } => {
+                receiver.shift(lines);
+                selector.shift(lines);
+                span.shift(lines);
+            }
+            Expr::Cascade { ref mut receiver, ref mut messages, ref mut span } => {
+                receiver.shift(lines);
+                messages.shift(lines);
+                span.shift(lines);
+            }
+            Expr::Block { ref mut args, ref mut temps, ref mut statements, ref mut span } => {
+                args.shift(lines);
+                temps.shift(lines);
+                statements.shift(lines);
+                span.shift(lines);
+            }
+            Expr::Brace { ref mut elements, ref mut span } => {
+                elements.shift(lines);
+                span.shift(lines);
+            }
+            Expr::Pseudo(_, ref mut span) | Expr::Error(ref mut span) => span.shift(lines),
+            Expr::Method(ref mut method) => method.shift(lines),
+        }
+    }
+}
+
+impl Shift for Msg {
+    fn shift(&mut self, lines: isize) {
+        match *self {
+            Msg::Unary(ref mut id) => id.shift(lines),
+            Msg::Binary(_, ref mut arg) => arg.shift(lines),
+            Msg::Kwargs(ref mut kws) => for kw in kws {
+                kw.id.shift(lines);
+                kw.val.shift(lines);
+            },
+        }
+    }
+}
+
+impl Shift for Literal {
+    fn shift(&mut self, lines: isize) {
+        if let Literal::Array(ref mut elements, _) = *self {
+            elements.shift(lines);
+        }
+        let mut span = self.span();
+        span.shift(lines);
+        *self = self.clone().with_span(span);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickcheck::{Arbitrary, Gen, QuickCheck};
+
+    use super::*;
+
+    const SRC: &str = "Object subclass: #Point
+    instanceVariableNames: 'x y'
+    classVariableNames: ''
+    category: 'Graphics-Primitives'!
+
+Point comment: 'A point in the plane.'!
+
+!Point methodsFor: 'accessing'!
+x
+    \"Answer the x coordinate.\"
+    ^x!
+y
+    ^y!
+x: xValue y: yValue
+    | sum |
+    x <- xValue. y <- yValue.
+    sum <- #(1 $a 'two' #three (4)) inject: 0 into: [:a :b | a + b].
+    ^self! !
+
+!Point class methodsFor: 'instance creation'!
+origin
+    ^self new x: 0 y: 0! !
+
+Transcript show: 'done!!'!
+";
+
+    fn same(file: &ChunkFile) {
+        assert_eq!(Ok(file.chunks().to_vec()), parse_chunks(file.source()));
+    }
+
+    fn edit(file: &mut ChunkFile, find: &str, len: usize, text: &str) {
+        let start = file.source().find(find).unwrap();
+        file.edit(&TextEdit::new(start..start + len, text)).unwrap();
+        same(file);
+    }
+
+    #[test]
+    fn test_edit_method_body() {
+        let mut file = ChunkFile::new(ParserConfig::default(), SRC).unwrap();
+        assert_eq!(file.reparsed(), 14);
+        edit(&mut file, "^x!", 2, "^x\n        + 1");
+        assert_eq!(file.reparsed(), 1);
+        edit(&mut file, "    ^y", 0, "\n\n");
+        assert_eq!(file.reparsed(), 1);
+    }
+
+    #[test]
+    fn test_edit_that_changes_chunks() {
+        let mut file = ChunkFile::new(ParserConfig::default(), SRC).unwrap();
+        // A new method.
+        edit(&mut file, "\ny\n    ^y!", 0, "\nz\n    ^0!");
+        assert_eq!(file.reparsed(), 1);
+        // Merging two methods into one.
+        edit(&mut file, "!\ny\n    ^", 9, " + ");
+        assert_eq!(file.reparsed(), 1);
+        // Splitting a section of methods in two.
+        edit(&mut file, "\nx: xValue", 0, " !\n\n!Point methodsFor: 'initialization'!");
+        assert_eq!(file.reparsed(), 3);
+        // A class comment, collected into the class from a later chunk.
+        edit(&mut file, "A point", 1, "The");
+        assert_eq!(file.reparsed(), 1);
+    }
+
+    #[test]
+    fn test_edit_with_error_keeps_file() {
+        let mut file = ChunkFile::new(ParserConfig::default(), SRC).unwrap();
+        let start = SRC.find("^y").unwrap();
+        let err = file.edit(&TextEdit::new(start..start + 2, "^)")).unwrap_err();
+        let src = TextEdit::new(start..start + 2, "^)").apply(SRC);
+        assert_eq!(err, parse_chunks(&src).unwrap_err());
+        assert_eq!(file.source(), SRC);
+        same(&file);
+    }
+
+    // A few edits to `SRC`, as offsets in the text as it is before each.
+    #[derive(Debug, Clone)]
+    struct Edits(Vec<TextEdit>);
+
+    const SNIPPETS: &[&str] = &[
+        "", "!", "!!", "! !", "\n", " ", "    ", "x", "^y", ". ", "'", "\"", "3", "[:a | a]",
+        "foo: 1 bar: 2", "(", ")", "\nfoo\n    ^1",
+    ];
+
+    impl Arbitrary for Edits {
+        fn arbitrary(g: &mut Gen) -> Edits {
+            let mut len = SRC.len();
+            let edits = (0..usize::arbitrary(g) % 4 + 1).map(|_| {
+                let start = usize::arbitrary(g) % (len + 1);
+                let end = (start + usize::arbitrary(g) % 8).min(len);
+                let text = *g.choose(SNIPPETS).unwrap();
+                len = len - (end - start) + text.len();
+                TextEdit::new(start..end, text)
+            }).collect();
+            Edits(edits)
+        }
+
+        fn shrink(&self) -> Box<dyn Iterator<Item = Edits>> {
+            let edits = self.0.clone();
+            Box::new((0..edits.len()).map(move |n| Edits(edits[..n].to_vec())))
+        }
+    }
+
+    #[test]
+    fn prop_edit_same_as_full_parse() {
+        fn prop(edits: Edits) -> bool {
+            let mut file = ChunkFile::new(ParserConfig::default(), SRC).unwrap();
+            for edit in &edits.0 {
+                let src = edit.apply(file.source());
+                let full = parse_chunks(&src);
+                match file.edit(edit) {
+                    Ok(()) => if Ok(file.chunks()) != full.as_ref().map(|c| &c[..]) {
+                        return false;
+                    },
+                    // The file is left as it was, so the later edits no
+                    // longer apply.
+                    Err(err) => return full == Err(err),
+                }
+            }
+            true
+        }
+        QuickCheck::new().tests(1000).quickcheck(prop as fn(Edits) -> bool)
+    }
+}
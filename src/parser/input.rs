@@ -0,0 +1,82 @@
+// The token stream the parsers run over.
+//
+// The parsers are built with `parser!`, which makes the type of every parser
+// public, so this type is public too, in a private module.
+
+use std::cell::RefCell;
+
+use combine::primitives::{Error, ParseError, StreamOnce};
+
+use lexer::{src_char, Lexed, Token, TokenKind};
+use syntax::Position;
+
+use super::{ParserConfig, SyntaxError};
+
+// The input for the parsers: the tokens left to parse, the syntax to accept,
+// and where to put the errors recovered from, when recovering.
+#[derive(Clone)]
+pub struct Input<'a> {
+    pub(super) tokens: &'a [Token],
+    // The end of the last token parsed.
+    pub(super) last: Position,
+    pub(super) lexed: &'a Lexed,
+    pub(super) src: &'a str,
+    pub(super) config: &'a ParserConfig,
+    pub(super) errors: Option<&'a RefCell<Vec<SyntaxError>>>,
+}
+
+impl<'a> Input<'a> {
+    pub(super) fn new(lexed: &'a Lexed, src: &'a str, config: &'a ParserConfig) -> Self {
+        Input { tokens: &lexed.tokens, last: lexed.start, lexed, src, config, errors: None }
+    }
+
+    // Offer a syntax error to the input. An input which is parsing in
+    // error-recovery mode records the error and returns `true`, asking the
+    // parser to skip past it and carry on. Otherwise the error fails the
+    // parse as usual.
+    pub(super) fn recover(&self, err: &ParseError<Self>) -> bool {
+        match self.errors {
+            Some(errors) => {
+                errors.borrow_mut().push(syntax_error(self.lexed, self.src, err));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<'a> StreamOnce for Input<'a> {
+    type Item = &'a Token;
+    type Range = &'a Token;
+    type Position = Position;
+
+    fn uncons(&mut self) -> Result<&'a Token, Error<&'a Token, &'a Token>> {
+        match self.tokens.split_first() {
+            Some((token, rest)) => {
+                self.tokens = rest;
+                self.last = token.span.end;
+                Ok(token)
+            }
+            None => Err(Error::end_of_input()),
+        }
+    }
+
+    fn position(&self) -> Position {
+        self.tokens.first().map_or(self.lexed.end, |t| t.span.start)
+    }
+}
+
+// Turn a parse error into a `SyntaxError`. A parser which fails at text the
+// lexer could not read reports the lexer's error instead, or the character
+// it stopped at if it has none, as `tokenize` does.
+pub(super) fn syntax_error<'a>(lexed: &Lexed, src: &str, err: &ParseError<Input<'a>>) -> SyntaxError {
+    match lexed.tokens.last() {
+        Some(t) if t.kind == TokenKind::Error && t.span.start == err.position => {
+            lexed.error.clone().unwrap_or_else(|| {
+                let c = src_char(src, t.span.start).map(|c| c.to_string());
+                SyntaxError::new(src, t.span.start, c, Vec::new(), Vec::new())
+            })
+        }
+        _ => SyntaxError::from_parse_error(src, err),
+    }
+}
@@ -0,0 +1,2372 @@
+//! Parser for the Smalltalk programming language.
+//!
+//! The source is first split into tokens by the `lexer`, and the parsers here
+//! run over those tokens, so that every node can record the span of source
+//! text it was parsed from without the parsers ever looking at whitespace or
+//! comments. The `parse_*` functions are the entry points for the rest of the
+//! crate: they parse a whole source string and report failures as a
+//! `SyntaxError`.
+//!
+//! The expression parsers follow the railway diagrams at the back of the blue
+//! book.
+
+use std::cell::RefCell;
+use std::marker::PhantomData;
+
+use combine::{many, many1, try, optional};
+use combine::Parser;
+use combine::primitives::{ConsumedResult, Error, FastResult, Info, ParseError};
+use combine::primitives::{SourcePosition, StreamOnce};
+use combine::combinator::*;
+
+use lexer::{lex, lex_collapsed, Lexed, Token, TokenKind};
+use syntax::*;
+use syntax::Position;
+
+use self::input::{syntax_error, Input};
+
+mod chunk;
+mod config;
+mod error;
+mod incremental;
+mod input;
+
+pub use self::chunk::parse_chunks;
+pub use self::config::{Dialect, ParserConfig};
+pub use self::error::SyntaxError;
+pub use self::incremental::{ChunkFile, TextEdit};
+
+/// Parse a single expression, such as `a foo: b + 1`.
+pub fn parse_expr(src: &str) -> Result<Expr, SyntaxError> {
+    ParserConfig::default().parse_expr(src)
+}
+
+/// Parse a sequence of statements separated by `.`.
+pub fn parse_statements(src: &str) -> Result<Vec<Statement>, SyntaxError> {
+    ParserConfig::default().parse_statements(src)
+}
+
+/// Parse a program to run as it is: optional temporaries, then statements.
+///
+/// ```
+/// use smoltok::parser::parse_do_it;
+/// use smoltok::syntax::Syntax;
+///
+/// match parse_do_it("| a b | a <- 3. b <- a + 4. b printString").unwrap() {
+///     Syntax::DoIt { temps, statements } => {
+///         assert_eq!(temps.len(), 2);
+///         assert_eq!(statements.len(), 3);
+///     }
+///     s => panic!("expected a doIt, got {:?}", s),
+/// }
+/// ```
+pub fn parse_do_it(src: &str) -> Result<Syntax, SyntaxError> {
+    ParserConfig::default().parse_do_it(src)
+}
+
+/// Parse a method definition: a message pattern, optional temporaries and the
+/// method body.
+pub fn parse_method(src: &str) -> Result<Method, SyntaxError> {
+    ParserConfig::default().parse_method(src)
+}
+
+/// Parse a literal constant, such as `#(1 $a 'str' #sym)`.
+pub fn parse_literal(src: &str) -> Result<Literal, SyntaxError> {
+    ParserConfig::default().parse_literal(src)
+}
+
+/// Parse a sequence of statements without giving up at the first syntax
+/// error, for editors and the REPL.
+///
+/// A statement which fails to parse, in the sequence itself or in the body of
+/// a block, is recorded as an error and replaced by an `Expr::Error`, and
+/// parsing resumes at the next `.` or `]`. This always produces statements,
+/// along with every error found on the way.
+///
+/// ```
+/// use smoltok::parser::parse_statements_recovering;
+/// use smoltok::syntax::{Expr, Statement};
+///
+/// let (stmts, errors) = parse_statements_recovering("a foo: ). b bar");
+/// assert_eq!(stmts.len(), 2);
+/// match stmts[0] {
+///     Statement::E(Expr::Error(_)) => (),
+///     ref s => panic!("expected an error placeholder, got {:?}", s),
+/// }
+/// assert_eq!(errors.len(), 1);
+/// ```
+pub fn parse_statements_recovering(src: &str) -> (Vec<Statement>, Vec<SyntaxError>) {
+    ParserConfig::default().parse_statements_recovering(src)
+}
+
+const START: Position = Position { line: 1, column: 1 };
+
+impl ParserConfig {
+    /// Parse a single expression in this configuration. See `parse_expr`.
+    pub fn parse_expr(&self, src: &str) -> Result<Expr, SyntaxError> {
+        let lexed = lex(self, src, src, START);
+        parse_all(self, &lexed, src, expr())
+    }
+
+    /// Parse a sequence of statements in this configuration. See
+    /// `parse_statements`.
+    pub fn parse_statements(&self, src: &str) -> Result<Vec<Statement>, SyntaxError> {
+        let lexed = lex(self, src, src, START);
+        parse_all(self, &lexed, src, statements())
+    }
+
+    /// Parse a program to run in this configuration. See `parse_do_it`.
+    pub fn parse_do_it(&self, src: &str) -> Result<Syntax, SyntaxError> {
+        let lexed = lex(self, src, src, START);
+        parse_all(self, &lexed, src, do_it())
+    }
+
+    /// Parse a method definition in this configuration. See `parse_method`.
+    pub fn parse_method(&self, src: &str) -> Result<Method, SyntaxError> {
+        let lexed = lex(self, src, src, START);
+        parse_all(self, &lexed, src, method_p())
+    }
+
+    /// Parse a literal constant in this configuration. See `parse_literal`.
+    pub fn parse_literal(&self, src: &str) -> Result<Literal, SyntaxError> {
+        let lexed = lex(self, src, src, START);
+        parse_all(self, &lexed, src, literal())
+    }
+
+    /// Parse a sequence of statements in this configuration, recovering from
+    /// syntax errors. See `parse_statements_recovering`.
+    pub fn parse_statements_recovering(&self, src: &str) -> (Vec<Statement>, Vec<SyntaxError>) {
+        let lexed = lex(self, src, src, START);
+        let errors = RefCell::new(Vec::new());
+        let mut input = Input::new(&lexed, src, self);
+        input.errors = Some(&errors);
+        let mut stmts = Vec::new();
+        loop {
+            match statements().parse(input.clone()) {
+                Ok((more, rest)) => {
+                    stmts.extend(more);
+                    input = rest;
+                }
+                // Statements are recovered rather than failing in this mode.
+                Err(_) => break,
+            }
+            // Whatever stopped the statements, such as a stray `]` or a statement
+            // after a return, is an error of its own.
+            input = match eof().parse_stream_consumed(input.clone()) {
+                FastResult::ConsumedOk(_) | FastResult::EmptyOk(_) => break,
+                FastResult::ConsumedErr(err) | FastResult::EmptyErr(err) => {
+                    input.recover(&err);
+                    let mut rest = input.clone();
+                    let _ = rest.uncons();
+                    let (rest, _) = skip_to_sync(rest, input.position());
+                    skip_period(rest.clone()).unwrap_or(rest)
+                }
+            };
+        }
+        (stmts, errors.into_inner())
+    }
+}
+
+// Run `p` over all of the tokens in `lexed`, which were read from `src`.
+fn parse_all<'a, P>(config: &'a ParserConfig, lexed: &'a Lexed, src: &'a str, p: P)
+    -> Result<P::Output, SyntaxError>
+    where P: Parser<Input = Input<'a>>
+{
+    (p, eof())
+        .map(|t| t.0)
+        .parse(Input::new(lexed, src, config))
+        .map(|(o, _)| o)
+        .map_err(|err| syntax_error(lexed, src, &err))
+}
+
+fn pos(p: SourcePosition) -> Position {
+    Position { line: p.line as usize, column: p.column as usize }
+}
+
+// Parse a token of the given kind, such as a `)`, returning its span. `name`
+// describes the token in errors.
+parser! {
+    fn punct['a](kind: TokenKind, name: &'static str)(Input<'a>) -> Span
+    {
+        let kind = kind.clone();
+        label(satisfy_map(move |t: &Token| if t.kind == kind { Some(t.span) } else { None }), name)
+    }
+}
+
+// Parse a `|`, which is lexed as a binary selector.
+parser! {
+    fn bar['a]()(Input<'a>) -> Span
+    {
+        punct(TokenKind::Binary(String::from("|")), "`|`")
+    }
+}
+
+parser! {
+    fn period['a]()(Input<'a>) -> Span
+    {
+        punct(TokenKind::Period, "`.`")
+    }
+}
+
+// Like `Parser::expected`, except that a failure to parse without consuming
+// input reports `label` as the *only* thing that was expected, rather than
+// adding it to the tokens each alternative was looking for.
+struct Label<P>(P, &'static str);
+
+fn label<P: Parser>(p: P, label: &'static str) -> Label<P> {
+    Label(p, label)
+}
+
+impl<P: Parser> Parser for Label<P> {
+    type Input = P::Input;
+    type Output = P::Output;
+
+    fn parse_lazy(&mut self, input: P::Input) -> ConsumedResult<P::Output, P::Input> {
+        match self.0.parse_lazy(input) {
+            FastResult::EmptyErr(mut err) => {
+                err.set_expected(Info::Borrowed(self.1));
+                FastResult::EmptyErr(err)
+            }
+            result => result,
+        }
+    }
+
+    fn add_error(&mut self, errors: &mut ParseError<P::Input>) {
+        let start = errors.errors.len();
+        self.0.add_error(errors);
+        let mut i = 0;
+        errors.errors.retain(|e| {
+            i += 1;
+            i <= start || !matches!(*e, Error::Expected(_))
+        });
+        errors.add_error(Error::Expected(Info::Borrowed(self.1)));
+    }
+}
+
+// Run `p` only if the configuration of the input enables it, failing without
+// consuming anything otherwise.
+struct When<P>(fn(&ParserConfig) -> bool, P);
+
+fn when<P: Parser>(enabled: fn(&ParserConfig) -> bool, p: P) -> When<P> {
+    When(enabled, p)
+}
+
+impl<'a, P: Parser<Input = Input<'a>>> Parser for When<P> {
+    type Input = Input<'a>;
+    type Output = P::Output;
+
+    fn parse_lazy(&mut self, input: Input<'a>) -> ConsumedResult<P::Output, Input<'a>> {
+        if (self.0)(input.config) {
+            self.1.parse_lazy(input)
+        } else {
+            FastResult::EmptyErr(ParseError::empty(input.position()))
+        }
+    }
+
+    fn add_error(&mut self, errors: &mut ParseError<Input<'a>>) {
+        self.1.add_error(errors)
+    }
+}
+
+// Build a message send, with a span from the start of the receiver to the end
+// of the last part of the message.
+fn send(receiver: Expr, selector: Msg) -> Expr {
+    let span = receiver.span().to(message_end(&receiver, &selector));
+    Expr::Message { receiver: Box::new(receiver), selector, span }
+}
+
+// The span of the last part of a message sent to `receiver`.
+fn message_end(receiver: &Expr, selector: &Msg) -> Span {
+    match *selector {
+        Msg::Unary(ref id) => id.1,
+        Msg::Binary(_, ref arg) => arg.span(),
+        Msg::Kwargs(ref kws) => match kws.last() {
+            Some(kw) => kw.val.span(),
+            None => receiver.span(),
+        },
+    }
+}
+
+parser! {
+    fn expr['a]()(Input<'a>) -> Expr
+    {
+        let target = pseudo_var().map(Err).or(ident().map(Ok));
+        let assign = try((target, punct(TokenKind::Assign, "`<-`")))
+            .and_then(|(target, _): (Result<Ident, (PseudoVar, Span)>, Span)| match target {
+                Ok(id) => Ok(id),
+                Err((var, _)) => {
+                    let msg = format!("cannot assign to the pseudo-variable `{}`", var.name());
+                    Err(Error::Message(Info::Owned(msg)))
+                }
+            })
+            .then(|id|
+                expr()
+                    .message("assignment needs a value after `<-`")
+                    .map(move |val| Expr::Assign(id.clone(), Box::new(val)))
+            );
+        label(
+            assign
+                .or(cascaded_message_expr())
+                .or(method_p().map(Expr::Method)),
+            "expression"
+        )
+    }
+}
+
+// A primary followed by any number of unary messages.
+parser! {
+    fn unary_object['a]()(Input<'a>) -> Expr
+    {
+        (primary(), many(unary_selector()))
+            .map(|(p, sels): (_, Vec<Msg>)| sels.into_iter().fold(p, send))
+    }
+}
+
+parser! {
+    fn unary_expr['a]()(Input<'a>) -> Expr
+    {
+        (primary(), many1(unary_selector()))
+            .map(|(p, sels): (_, Vec<Msg>)| sels.into_iter().fold(p, send))
+    }
+}
+
+parser! {
+    fn unary_selector['a]()(Input<'a>) -> Msg
+    {
+        ident().map(Msg::Unary)
+    }
+}
+
+// A binary selector and its argument.
+parser! {
+    fn binary_part['a]()(Input<'a>) -> Msg
+    {
+        binary_selector().then(|Ident(sel, _)| {
+            let hint = format!("binary message `{}` needs an argument", sel);
+            unary_object()
+                .message(hint)
+                .map(move |arg| Msg::Binary(Selector(sel.clone()), Box::new(arg)))
+        })
+    }
+}
+
+// A unary object followed by any number of binary messages.
+parser! {
+    fn binary_object['a]()(Input<'a>) -> Expr
+    {
+        (unary_object(), many(binary_part()))
+            .map(|(o, msgs): (_, Vec<Msg>)| msgs.into_iter().fold(o, send))
+    }
+}
+
+parser! {
+    fn binary_expr['a]()(Input<'a>) -> Expr
+    {
+        (unary_object(), many1(binary_part()))
+            .map(|(o, msgs): (_, Vec<Msg>)| msgs.into_iter().fold(o, send))
+    }
+}
+
+// A keyword and its argument.
+parser! {
+    fn keyword_part['a]()(Input<'a>) -> Keyword
+    {
+        keyword_lit().then(|id| {
+            let hint = format!("keyword message argument missing after `{}`", id.0);
+            binary_object()
+                .message(hint)
+                .map(move |val| Keyword { id: id.clone(), val })
+        })
+    }
+}
+
+parser! {
+    fn keyword_expr['a]()(Input<'a>) -> Expr
+    {
+        (binary_object(), many1(keyword_part()))
+            .map(|(o, kws)| send(o, Msg::Kwargs(kws)))
+    }
+}
+
+// A binary object, optionally followed by a keyword message. Unlike the blue
+// book's "message expression" this also accepts a bare primary, which saves
+// the callers from having to backtrack.
+parser! {
+    fn message_expr['a]()(Input<'a>) -> Expr
+    {
+        (binary_object(), many(keyword_part()))
+            .map(|(o, kws): (_, Vec<Keyword>)|
+                if kws.is_empty() { o } else { send(o, Msg::Kwargs(kws)) }
+            )
+    }
+}
+
+// A message expression, optionally followed by more messages for the same
+// receiver, each after a `;`.
+parser! {
+    fn cascaded_message_expr['a]()(Input<'a>) -> Expr
+    {
+        message_expr().then(|first| {
+            many(cascade_part()).and_then(move |rest: Vec<Msg>| {
+                if rest.is_empty() {
+                    return Ok(first.clone());
+                }
+                match first.clone() {
+                    Expr::Message { receiver, selector, .. } => {
+                        let mut messages = vec![selector];
+                        messages.extend(rest);
+                        let end = message_end(&receiver, &messages[messages.len() - 1]);
+                        let span = receiver.span().to(end);
+                        Ok(Expr::Cascade { receiver, messages, span })
+                    }
+                    _ => Err(Error::Message(Info::Borrowed(
+                        "only a message send can be followed by `;`"
+                    ))),
+                }
+            })
+        })
+    }
+}
+
+// A `;` and the next message of a cascade.
+parser! {
+    fn cascade_part['a]()(Input<'a>) -> Msg
+    {
+        let part = label(
+            unary_selector()
+                .or(binary_part())
+                .or(many1(keyword_part()).map(Msg::Kwargs)),
+            "message"
+        ).message("cascade needs a message after `;`");
+        (punct(TokenKind::Semicolon, "`;`"), part, cascade_part_end()).map(|t| t.1)
+    }
+}
+
+// Each part of a cascade is a single message, so another message straight
+// after one is missing the `;` between them.
+parser! {
+    fn cascade_part_end['a]()(Input<'a>) -> ()
+    {
+        let selector = satisfy_map(|t: &Token| match t.kind {
+            TokenKind::Ident(_) | TokenKind::Keyword(_) | TokenKind::Binary(_) => Some(()),
+            _ => None,
+        });
+        look_ahead(optional(selector))
+            .and_then(|next| match next {
+                Some(_) => Err(Error::Message(Info::Borrowed(
+                    "cascaded messages must be separated by `;`"
+                ))),
+                None => Ok(()),
+            })
+    }
+}
+
+parser! {
+    fn keyword_lit['a]()(Input<'a>) -> Ident
+    {
+        satisfy_map(|t: &Token| match t.kind {
+            TokenKind::Keyword(ref k) => Some(Ident(k.clone(), t.span)),
+            _ => None,
+        })
+    }
+}
+
+parser! {
+    fn primary['a]()(Input<'a>) -> Expr
+    {
+        let parens = punct(TokenKind::LParen, "`(`").then(|open: Span| {
+            let hint = format!(
+                "missing `)` to close the `(` at line {}, column {}",
+                open.start.line, open.start.column
+            );
+            (expr(), punct(TokenKind::RParen, "`)`").message(hint)).map(|t| t.0)
+        });
+        label(
+            pseudo_var().map(|(var, span)| Expr::Pseudo(var, span))
+                .or(ident().map(Expr::Id))
+                .or(literal().map(Expr::Lit))
+                .or(negative_number().map(Expr::Lit))
+                .or(block())
+                .or(when(|c| c.brace_arrays, brace()))
+                .or(parens),
+            "expression"
+        )
+    }
+}
+
+// Parse a `-` directly in front of the digits of a number as a negative
+// number. The lexer reads a `-` after an identifier as a binary selector, but
+// where a primary is expected, as at the start of the body of the method
+// `x -1`, it can only be the sign of the number.
+parser! {
+    fn negative_number['a]()(Input<'a>) -> Literal
+    {
+        let minus = punct(TokenKind::Binary(String::from("-")), "`-`");
+        let digits = satisfy_map(|t: &Token| match t.kind {
+            TokenKind::Number(ref n) if !n.negative => Some((n.clone(), t.span)),
+            _ => None,
+        });
+        try((minus, digits).and_then(|(minus, (n, span)): (Span, _)| {
+            if minus.end == span.start {
+                Ok(Literal::Number(Num { negative: true, ..n }, minus.to(span)))
+            } else {
+                Err(Error::Expected(Info::Borrowed("number")))
+            }
+        }))
+    }
+}
+
+// Parse a pseudo-variable, failing without consuming anything on any other
+// identifier. `thisContext` is only a pseudo-variable if the configuration
+// says so.
+parser! {
+    fn pseudo_var['a]()(Input<'a>) -> (PseudoVar, Span)
+    {
+        let named = |this_context: bool| satisfy_map(move |t: &Token| match t.kind {
+            TokenKind::Ident(ref id) => match PseudoVar::from_name(id) {
+                Some(PseudoVar::ThisContext) if !this_context => None,
+                var => var.map(|var| (var, t.span)),
+            },
+            _ => None,
+        });
+        when(|c| c.this_context, named(true)).or(named(false))
+    }
+}
+
+// Parse a brace array: expressions separated by `.`, with an optional `.`
+// after the last one.
+parser! {
+    fn brace['a]()(Input<'a>) -> Expr
+    {
+        (
+            punct(TokenKind::LBrace, "`{`"),
+            optional((expr(), many(try(period().with(expr()))), optional(period()))),
+            punct(TokenKind::RBrace, "`}`").message("missing `}` to close the brace array"),
+        ).map(|(open, elems, close)| {
+            let elements = match elems {
+                Some((first, rest, _)) => {
+                    let rest: Vec<Expr> = rest;
+                    let mut elements = vec![first];
+                    elements.extend(rest);
+                    elements
+                }
+                None => Vec::new(),
+            };
+            Expr::Brace { elements, span: open.to(close) }
+        })
+    }
+}
+
+parser! {
+    fn block['a]()(Input<'a>) -> Expr
+    {
+        (
+            punct(TokenKind::LBracket, "`[`"),
+            optional(block_args()),
+            optional(temporaries()),
+            statements(),
+            punct(TokenKind::RBracket, "`]`").message("missing `]` to close the block"),
+        ).map(|(open, args, temps, statements, close)| Expr::Block {
+            args: args.unwrap_or_default(),
+            temps: temps.unwrap_or_default(),
+            statements,
+            span: open.to(close),
+        })
+    }
+}
+
+// Parse a sequence of statements separated by `.`. A return statement ends
+// the sequence.
+parser! {
+    fn statements['a]()(Input<'a>) -> Vec<Statement>
+    {
+        StatementList(PhantomData)
+    }
+}
+
+parser! {
+    fn statement['a]()(Input<'a>) -> Statement
+    {
+        punct(TokenKind::Return, "`^`")
+            .then(|caret: Span|
+                expr()
+                    .message("return needs an expression after `^`")
+                    .map(move |e| {
+                        let span = Span::new(caret.start, e.span().end);
+                        Statement::Ret(e, span)
+                    })
+            )
+            .or(expr().map(Statement::E))
+    }
+}
+
+// The statement list is written out by hand, rather than with combinators, so
+// that an input in error-recovery mode can replace a statement which fails to
+// parse with `Expr::Error` and carry on with the next one.
+//
+// Recovery records errors as it goes, so it must never run underneath a
+// parser which might backtrack.
+struct StatementList<'a>(PhantomData<fn(Input<'a>) -> Input<'a>>);
+
+impl<'a> Parser for StatementList<'a> {
+    type Input = Input<'a>;
+    type Output = Vec<Statement>;
+
+    fn parse_lazy(&mut self, mut input: Input<'a>) -> ConsumedResult<Vec<Statement>, Input<'a>> {
+        let mut stmts = Vec::new();
+        let mut consumed = false;
+        loop {
+            let start = input.clone();
+            let (stmt, rest) = match statement().parse_stream_consumed(input) {
+                FastResult::ConsumedOk(x) => {
+                    consumed = true;
+                    x
+                }
+                FastResult::EmptyOk(x) => x,
+                // Running out of statements is how the list ends.
+                FastResult::EmptyErr(err) => {
+                    if at_end_of_statements(&start) || !start.recover(&err) {
+                        input = start;
+                        break;
+                    }
+                    consumed = true;
+                    skip_statement(start, &err)
+                }
+                FastResult::ConsumedErr(err) => {
+                    if !start.recover(&err) {
+                        return FastResult::ConsumedErr(err);
+                    }
+                    consumed = true;
+                    skip_statement(start, &err)
+                }
+            };
+            let is_ret = matches!(stmt, Statement::Ret(..));
+            stmts.push(stmt);
+            input = rest;
+
+            if let Some(rest) = skip_period(input.clone()) {
+                consumed = true;
+                input = rest;
+            } else if !is_ret && !at_end_of_statements(&input) {
+                let err = missing_period(&input);
+                if !input.recover(&err) {
+                    break;
+                }
+                let (rest, _) = skip_to_sync(input.clone(), input.position());
+                consumed = true;
+                input = skip_period(rest.clone()).unwrap_or(rest);
+            }
+            if is_ret {
+                break;
+            }
+        }
+        if consumed {
+            FastResult::ConsumedOk((stmts, input))
+        } else {
+            FastResult::EmptyOk((stmts, input))
+        }
+    }
+
+    fn add_error(&mut self, errors: &mut ParseError<Input<'a>>) {
+        statement().add_error(errors)
+    }
+}
+
+fn skip_period(input: Input) -> Option<Input> {
+    period().parse(input).ok().map(|t| t.1)
+}
+
+// A statement list ends at the end of the input or of the enclosing block.
+fn at_end_of_statements(input: &Input) -> bool {
+    input.tokens.first().is_none_or(|t| t.kind == TokenKind::RBracket)
+}
+
+fn missing_period<'a>(input: &Input<'a>) -> ParseError<Input<'a>> {
+    let errors = vec![
+        Error::Expected(Info::Borrowed("`.`")),
+        Error::Message(Info::Borrowed("statements must be separated by `.`")),
+    ];
+    ParseError::from_errors(input.position(), errors)
+}
+
+// Skip the rest of a statement which failed with `err`, returning a
+// placeholder for it and the input after it.
+fn skip_statement<'a>(start: Input<'a>, err: &ParseError<Input<'a>>) -> (Statement, Input<'a>) {
+    let mut input = start.clone();
+    let mut end = start.position();
+    while input.position() < err.position && input.uncons().is_ok() {
+        end = input.last;
+    }
+    let (rest, end) = skip_to_sync(input, end);
+    let span = Span::new(start.position(), end);
+    (Statement::E(Expr::Error(span)), rest)
+}
+
+// Skip tokens up to the next `.` or `]` which ends a statement, stepping over
+// brackets on the way. Returns the remaining input and the end of the last
+// token skipped.
+fn skip_to_sync(mut input: Input, mut end: Position) -> (Input, Position) {
+    let mut depth = 0;
+    while let Some(t) = input.tokens.first() {
+        match t.kind {
+            TokenKind::Period | TokenKind::RBracket if depth == 0 => break,
+            TokenKind::LBracket | TokenKind::LParen | TokenKind::LBrace
+            | TokenKind::ArrayStart | TokenKind::ByteArrayStart => depth += 1,
+            TokenKind::RBracket | TokenKind::RParen | TokenKind::RBrace if depth > 0 =>
+                depth -= 1,
+            _ => (),
+        }
+        let _ = input.uncons();
+        end = input.last;
+    }
+    (input, end)
+}
+
+// Parse the arguments of a block and the `|` after them.
+parser! {
+    fn block_args['a]()(Input<'a>) -> Vec<Ident>
+    {
+        (
+            many1((punct(TokenKind::Colon, "`:`"), ident()).map(|t| t.1)),
+            bar().message("missing `|` after the block arguments")
+        ).map(|t| t.0)
+    }
+}
+
+// Parse an identifier.
+parser! {
+    fn ident['a]()(Input<'a>) -> Ident
+    {
+        label(satisfy_map(|t: &Token| match t.kind {
+            TokenKind::Ident(ref id) => Some(Ident(id.clone(), t.span)),
+            _ => None,
+        }), "identifier")
+    }
+}
+
+parser! {
+    fn array['a]()(Input<'a>) -> Literal
+    {
+        (
+            punct(TokenKind::ArrayStart, "`#(`"),
+            many(array_element()),
+            punct(TokenKind::RParen, "`)`").message("missing `)` to close the literal array"),
+        ).map(|(open, elems, close)| Literal::Array(elems, open.to(close)))
+    }
+}
+
+// Parse an element of a literal array. Identifiers, keywords and binary
+// selectors stand for symbols, and an array inside another needs no `#`.
+parser! {
+    fn array_element['a]()(Input<'a>) -> Literal
+    {
+        let nested = (
+            punct(TokenKind::LParen, "`(`"),
+            many(array_element()),
+            punct(TokenKind::RParen, "`)`").message("missing `)` to close the literal array"),
+        ).map(|(open, elems, close)| Literal::Array(elems, open.to(close)));
+        let symbol = satisfy_map(|t: &Token| match t.kind {
+            TokenKind::Ident(ref s) | TokenKind::Keyword(ref s) | TokenKind::Binary(ref s) =>
+                Some(Literal::Symbol(Selector(s.clone()), t.span)),
+            _ => None,
+        });
+        when(|c| c.literal_constants, literal_constant())
+            .or(literal())
+            .or(symbol)
+            .or(nested)
+    }
+}
+
+// Parse `true`, `false` or `nil` inside a literal array, failing without
+// consuming anything on any other identifier.
+parser! {
+    fn literal_constant['a]()(Input<'a>) -> Literal
+    {
+        satisfy_map(|t: &Token| match t.kind {
+            TokenKind::Ident(ref id) => match &id[..] {
+                "true" => Some(Literal::Bool(true, t.span)),
+                "false" => Some(Literal::Bool(false, t.span)),
+                "nil" => Some(Literal::Nil(t.span)),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+}
+
+parser! {
+    fn byte_array['a]()(Input<'a>) -> Literal
+    {
+        let byte = label(satisfy_map(|t: &Token| match t.kind {
+            TokenKind::Number(ref n) => Some((n.clone(), t.to_string())),
+            _ => None,
+        }), "byte").and_then(|(n, text)| match whole_number(&n) {
+            Some(b) if b < 256 => Ok(b as u8),
+            _ => {
+                let msg = format!("a byte must be between 0 and 255, not {}", text);
+                Err(Error::Message(Info::Owned(msg)))
+            }
+        });
+        (
+            punct(TokenKind::ByteArrayStart, "`#[`"),
+            many(byte),
+            punct(TokenKind::RBracket, "`]`").message("missing `]` to close the byte array"),
+        ).map(|(open, bytes, close)| Literal::ByteArray(bytes, open.to(close)))
+    }
+}
+
+// Parse a binary selector. The lexer has already decided whether a `-` is a
+// selector or the sign of a number.
+parser! {
+    fn binary_selector['a]()(Input<'a>) -> Ident
+    {
+        satisfy_map(|t: &Token| match t.kind {
+            TokenKind::Binary(ref s) => Some(Ident(s.clone(), t.span)),
+            _ => None,
+        })
+    }
+}
+
+parser! {
+    fn message_pattern['a]()(Input<'a>) -> MsgPat
+    {
+        commented_message_pattern().map(|t| t.0)
+    }
+}
+
+// A message pattern, along with the comments directly after it.
+parser! {
+    fn commented_message_pattern['a]()(Input<'a>) -> (MsgPat, Vec<String>)
+    {
+        let kwargs = many1(
+            keyword_lit().then(|keyword| {
+                let hint = format!("argument name missing after `{}`", keyword.0);
+                pattern_ident()
+                    .message(hint)
+                    .map(move |(var, cs)| (KeyPat { keyword: keyword.clone(), var }, cs))
+            })
+        ).map(|parts: Vec<(KeyPat, Vec<String>)>| {
+            let mut comments = Vec::new();
+            let parts = parts.into_iter().map(|(part, cs)| { comments = cs; part }).collect();
+            (MsgPat::Kwargs(parts), comments)
+        });
+
+        let bin = binary_selector().then(|sel| {
+            let hint = format!("argument name missing after `{}`", sel.0);
+            pattern_ident()
+                .message(hint)
+                .map(move |(var, cs)| (MsgPat::Bin(sel.clone(), var), cs))
+        });
+        let unary = pattern_ident().map(|(id, cs)| (MsgPat::Unary(id), cs));
+        label(kwargs.or(bin).or(unary), "message pattern")
+    }
+}
+
+// An identifier ending a message pattern, along with any comments after it.
+parser! {
+    fn pattern_ident['a]()(Input<'a>) -> (Ident, Vec<String>)
+    {
+        (ident(), Comments(PhantomData))
+    }
+}
+
+// The text of the comments between the last token parsed and the next one.
+struct Comments<'a>(PhantomData<fn(Input<'a>) -> Input<'a>>);
+
+impl<'a> Parser for Comments<'a> {
+    type Input = Input<'a>;
+    type Output = Vec<String>;
+
+    fn parse_lazy(&mut self, input: Input<'a>) -> ConsumedResult<Vec<String>, Input<'a>> {
+        let comments = &input.lexed.comments;
+        let first = comments.partition_point(|c| c.1.start < input.last);
+        let next = input.position();
+        let text = comments[first..].iter()
+            .take_while(|c| c.1.end <= next)
+            .map(|c| c.0.clone())
+            .collect();
+        FastResult::EmptyOk((text, input))
+    }
+}
+
+// Parse a pragma at the start of a method. `<primitive: N>` is part of the
+// blue book syntax, but any other pragma needs the configuration to accept
+// pragmas.
+parser! {
+    fn method_pragma['a]()(Input<'a>) -> Pragma
+    {
+        when(|c| c.pragmas, pragma()).or(primitive_pragma())
+    }
+}
+
+// The `<` and `>` around a pragma, which are lexed as binary selectors.
+parser! {
+    fn open_angle['a]()(Input<'a>) -> Span
+    {
+        punct(TokenKind::Binary(String::from("<")), "`<`")
+    }
+}
+
+parser! {
+    fn close_angle['a]()(Input<'a>) -> Span
+    {
+        punct(TokenKind::Binary(String::from(">")), "`>`")
+            .message("missing `>` to close the pragma")
+    }
+}
+
+parser! {
+    fn primitive_pragma['a]()(Input<'a>) -> Pragma
+    {
+        let keyword = satisfy_map(|t: &Token| match t.kind {
+            TokenKind::Keyword(ref k) if k == "primitive:" => Some(()),
+            _ => None,
+        });
+        let index = satisfy_map(|t: &Token| match t.kind {
+            TokenKind::Number(ref n) => Some((n.clone(), t.span)),
+            _ => None,
+        }).and_then(|(n, span)| match whole_number(&n) {
+            Some(_) => Ok(Literal::Number(n, span)),
+            None => Err(Error::Message(
+                Info::Borrowed("a primitive index must be a whole number"))),
+        });
+        (try((open_angle(), keyword)), index, close_angle()).map(|((open, _), arg, close)| {
+            Pragma { selector: Selector::new("primitive:"), args: vec![arg], span: open.to(close) }
+        })
+    }
+}
+
+// The index of the primitive named by a `<primitive: N>` pragma.
+fn primitive_index(p: &Pragma) -> Option<u32> {
+    match (p.selector.as_str(), &p.args[..]) {
+        ("primitive:", [Literal::Number(n, _)]) => whole_number(n),
+        _ => None,
+    }
+}
+
+// The value of a number written as plain decimal digits.
+fn whole_number(n: &Num) -> Option<u32> {
+    let plain = !n.negative && n.radix.is_none() && n.mantissa.is_none()
+        && n.exponent.is_none() && n.scale.is_none();
+    if plain { n.integer.parse().ok() } else { None }
+}
+
+// Parse a pragma: a unary selector, or keywords each followed by a literal,
+// between `<` and `>`.
+parser! {
+    fn pragma['a]()(Input<'a>) -> Pragma
+    {
+        let keywords = many1((keyword_lit(), literal()))
+            .map(|parts: Vec<(Ident, Literal)>| {
+                let selector = Selector(parts.iter().map(|p| &p.0 .0[..]).collect());
+                let args = parts.into_iter().map(|p| p.1).collect();
+                (selector, args)
+            });
+        let unary = ident().map(|id| (Selector(id.0), Vec::new()));
+        (open_angle(), label(keywords.or(unary), "pragma"), close_angle())
+            .map(|(open, (selector, args), close)| Pragma { selector, args, span: open.to(close) })
+    }
+}
+
+parser! {
+    fn temporaries['a]()(Input<'a>) -> Vec<Ident>
+    {
+        (bar(), many1(ident()), bar().message("missing `|` to close the temporaries"))
+            .map(|t| t.1)
+    }
+}
+
+// Parse a program to run as it is: optional temporaries, then statements.
+parser! {
+    fn do_it['a]()(Input<'a>) -> Syntax
+    {
+        (optional(temporaries()), statements()).map(|(temps, statements)| Syntax::DoIt {
+            temps: temps.unwrap_or_default(),
+            statements,
+        })
+    }
+}
+
+// Parse a method: its message pattern, comment, temporaries and body.
+parser! {
+    fn method_p['a]()(Input<'a>) -> Method
+    {
+        ( commented_message_pattern(),
+          many(method_pragma()),
+          optional(temporaries()),
+          optional(statements())
+        ).map(|((sig, comments), mut pragmas, temps, stmts): (_, Vec<Pragma>, _, _)| {
+            let last_stmt = stmts.as_ref().and_then(|s| s.last()).map(Statement::span);
+            let last_temp = temps.as_ref().and_then(|t| t.last()).map(|t| t.1);
+            let last_pragma = pragmas.last().map(|p| p.span);
+            let end = last_stmt.or(last_temp).or(last_pragma).unwrap_or_else(|| sig.span());
+            let span = sig.span().to(end);
+            let comment = comments.into_iter().next();
+            let primitive = pragmas.iter().position(|p| primitive_index(p).is_some())
+                .and_then(|i| primitive_index(&pragmas.remove(i)));
+            Method { sig, comment, primitive, pragmas, temps, stmts, span }
+        })
+    }
+}
+
+// Parse any kind of Smalltalk literal. Don't worry. Just throw whatever you
+// got at it.
+parser! {
+    fn literal['a]()(Input<'a>) -> Literal
+    {
+        satisfy_map(|t: &Token| match t.kind {
+            TokenKind::Number(ref n) => Some(Literal::Number(n.clone(), t.span)),
+            TokenKind::Char(c) => Some(Literal::Char(c, t.span)),
+            TokenKind::Str(ref s) => Some(Literal::Str(s.clone(), t.span)),
+            TokenKind::Symbol(ref s) => Some(Literal::Symbol(Selector(s.clone()), t.span)),
+            _ => None,
+        })
+            .or(array())
+            .or(byte_array())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_err<T, E>(x : Result<T, E>) -> bool {
+        x.is_err()
+    }
+
+    // The byte offset of `position` in `src`.
+    fn offset(src: &str, position: Position) -> usize {
+        let line: usize = src.split_inclusive('\n').take(position.line - 1).map(str::len).sum();
+        line + src[line..].chars().take(position.column - 1).map(char::len_utf8).sum::<usize>()
+    }
+
+    /// Run a parser over the tokens of a string, handing back the unparsed
+    /// remainder as a plain `&str`.
+    macro_rules! run {
+        ($p:expr, $s:expr) => {{
+            let s: &str = $s;
+            let lexed = lex(&ParserConfig::BLUE_BOOK, s, s, START);
+            let result = $p.parse(Input::new(&lexed, s, &ParserConfig::BLUE_BOOK))
+                .map(|(o, rest)| (o, &s[offset(s, rest.position())..]))
+                .map_err(|err| syntax_error(&lexed, s, &err));
+            result
+        }};
+    }
+
+    // Parse a number literal.
+    fn number(s: &str) -> Result<(Num, &str), SyntaxError> {
+        run!(literal(), s).map(|(lit, rest)| match lit {
+            Literal::Number(n, _) => (n, rest),
+            lit => panic!("expected a number, got {:?}", lit),
+        })
+    }
+
+    #[test]
+    fn test_bare_number() {
+        let res = number("10");
+        let ans = Num::int_from_str("10");
+        assert_eq!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_exponent() {
+        let res = number("10e3");
+        let ans = Num {
+            negative: false,
+            integer: String::from("10"),
+            exponent: Some(3),
+            mantissa: None,
+            radix: None,
+            scale: None,
+        };
+        assert_eq!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_full_number() {
+        let res = number("10r10.5e3");
+        let ans = Num {
+            negative: false,
+            integer: String::from("10"),
+            exponent: Some(3),
+            mantissa: Some(String::from("5")),
+            radix: Some(10),
+            scale: None,
+        };
+        assert_eq!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_float() {
+        let res = number("123.456");
+        let ans = Num {
+            negative: false,
+            integer: String::from("123"),
+            exponent: None,
+            mantissa: Some(String::from("456")),
+            radix: None,
+            scale: None,
+        };
+        assert_eq!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_radix() {
+        let res = number("16rAC.DCe10");
+        let ans = Num {
+            negative: false,
+            integer: String::from("AC"),
+            exponent: Some(10),
+            mantissa: Some(String::from("DC")),
+            radix: Some(16),
+            scale: None,
+        };
+        assert_eq!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_char() {
+        let res = run!(literal(), "$a");
+        let ans = Literal::Char('a', Span::default());
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_string() {
+        let res = run!(literal(), "'hello world'");
+        let ans = Literal::Str(String::from("hello world"), Span::default());
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_string_quotes() {
+        let res = run!(literal(), "'hello ''world'''");
+        let ans = Literal::Str(String::from("hello 'world'"), Span::default());
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_symbol() {
+        let res = run!(literal(), "#foobar123");
+        let ans = Literal::Symbol(Selector::new("foobar123"), Span::default());
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_keyword_symbols() {
+        let sym = |s| Literal::Symbol(Selector::new(s), Span::default());
+        assert_eq_ignoring_spans!(parse_literal("#foo:"), Ok(sym("foo:")));
+        assert_eq_ignoring_spans!(parse_literal("#at:put:"), Ok(sym("at:put:")));
+        assert_eq_ignoring_spans!(parse_literal("#Value:value:"), Ok(sym("Value:value:")));
+        let array = Literal::Array(vec![sym("at:put:"), sym("foo"), sym("==>")], Span::default());
+        assert_eq_ignoring_spans!(parse_literal("#(at:put: foo ==>)"), Ok(array));
+        assert!(parse_literal("#at: put:").is_err());
+        let span = parse_literal("#at:put:").unwrap().span();
+        assert_eq!((span.start, span.end), (at(1, 1), at(1, 9)));
+    }
+
+    #[test]
+    fn test_binary_selectors() {
+        for sel in &[",", "->", "==>", "~~", "<=", "\\\\", "-", "-->", "--", "+-"] {
+            for src in &[format!("a {} b", sel), format!("a{}b", sel)] {
+                match parse_expr(src) {
+                    Ok(Expr::Message { selector: Msg::Binary(ref s, _), .. }) =>
+                        assert_eq!(s.as_str(), *sel, "{}", src),
+                    ref e => panic!("expected a binary message for {}, got {:?}", src, e),
+                }
+            }
+        }
+        assert_eq_ignoring_spans!(parse_expr("3--4"), parse_expr("3 - -4"));
+        assert_eq_ignoring_spans!(parse_expr("3+-4"), parse_expr("3 + -4"));
+        assert_eq_ignoring_spans!(parse_expr("a-->-4"), parse_expr("a --> -4"));
+        let m = parse_method("==> other ^self , other").unwrap();
+        assert_eq!(m.sig.selector(), Selector::new("==>"));
+    }
+
+    #[test]
+    fn test_selectors() {
+        let kinds = [
+            ("size", SelectorKind::Unary, 0),
+            ("+", SelectorKind::Binary, 1),
+            ("->", SelectorKind::Binary, 1),
+            ("at:", SelectorKind::Keyword, 1),
+            ("value:value:value:value:", SelectorKind::Keyword, 4),
+        ];
+        for &(name, kind, arity) in &kinds {
+            let sel = Selector::new(name);
+            assert_eq!((sel.kind(), sel.arity()), (kind, arity), "{}", name);
+        }
+        let send = parse_expr("a at: 1 put: 2").unwrap();
+        match send {
+            Expr::Message { ref selector, .. } =>
+                assert_eq!(selector.selector(), Selector::new("at:put:")),
+            ref e => panic!("expected a message, got {:?}", e),
+        }
+        let m = parse_method("at: i put: x ^x").unwrap();
+        assert_eq!(m.sig.selector().parts(), vec!["at:", "put:"]);
+    }
+
+    #[test]
+    fn test_literal() {
+        let res = run!(literal(), "#('hello' 123 world)");
+        let ans = Literal::Array(vec![
+            Literal::Str(String::from("hello"), Span::default()),
+            Literal::Number(Num::int_from_str("123"), Span::default()),
+            Literal::Symbol(Selector::new("world"), Span::default()),
+        ], Span::default());
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_ident() {
+        let res = run!(ident(), "index");
+        let ans = mk_ident("index");
+        assert_eq_ignoring_spans!(res, Ok((ans, "")))
+    }
+
+    #[test]
+    fn test_single_assignment() {
+        let res = run!(expr(), "foo <- bar");
+        let ans = Expr::Assign(mk_ident("foo"), Box::new(mk_ident_expr("bar")));
+        assert_eq_ignoring_spans!(res, Ok((ans, "")))
+    }
+
+    #[test]
+    fn test_expr_assigment() {
+        let res = run!(expr(), "foo <- 'hello world'");
+        let ans = Expr::Assign(
+            mk_ident("foo"),
+            Box::new(Expr::Lit(Literal::Str(String::from("hello world"), Span::default()))),
+        );
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_assign_number() {
+        let res = run!(expr(), "foo <- 3r2e3");
+        let ans = Expr::Assign(
+            mk_ident("foo"),
+            Box::new(Expr::Lit(Literal::Number(Num {
+                negative: false,
+                radix: Some(3),
+                integer: String::from("2"),
+                mantissa: None,
+                exponent: Some(3),
+                scale: None,
+            }, Span::default()))),
+        );
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_multiple_assignment() {
+        let res = run!(expr(), "foo <- bar <- 'hello world'");
+        let ans = Expr::Assign(
+            mk_ident("foo"),
+            Box::new(Expr::Assign(
+                mk_ident("bar"),
+                Box::new(
+                    Expr::Lit(Literal::Str(String::from("hello world"), Span::default())),
+                ),
+            )),
+        );
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_unary_message_expr() {
+        let res = run!(expr(), "theta sin");
+        let ans = Expr::Message {
+            receiver: Box::new(mk_ident_expr("theta")),
+            selector: Msg::Unary(mk_ident("sin")),
+            span: Span::default(),
+        };
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_binary_expr_num() {
+        let res = run!(binary_expr(), "3 + 2");
+        let ans = Expr::Message {
+            receiver: Box::new(Expr::Lit(Literal::Number(Num::int_from_str("3"), Span::default()))),
+            selector: Msg::Binary(Selector::new("+"), Box::new(Expr::Lit(Literal::Number(Num::int_from_str("2"), Span::default())))),
+            span: Span::default(),
+        };
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_binary_expr() {
+        let res = run!(expr(), "foo + 2");
+        let ans = Expr::Message {
+            receiver: Box::new(mk_ident_expr("foo")),
+            selector: Msg::Binary(Selector::new("+"), Box::new(Expr::Lit(Literal::Number(Num::int_from_str("2"), Span::default())))),
+            span: Span::default(),
+        };
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_keyword_message() {
+        let res = run!(expr(), "a b: 2");
+        let ans = Expr::Message {
+            receiver: Box::new(mk_ident_expr("a")),
+            selector: Msg::Kwargs(vec![
+                Keyword {
+                    id: mk_ident("b:"),
+                    val: Expr::Lit(Literal::Number(Num::int_from_str("2"), Span::default()))
+                },
+            ]),
+            span: Span::default(),
+        };
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_keyword_messages() {
+        let res = run!(expr(), "a b: 2 c: 3");
+        let ans = Expr::Message {
+            receiver: Box::new(mk_ident_expr("a")),
+            selector: Msg::Kwargs(vec![
+                Keyword {
+                    id: mk_ident("b:"),
+                    val: Expr::Lit(Literal::Number(Num::int_from_str("2"), Span::default()))
+                },
+                Keyword {
+                    id: mk_ident("c:"),
+                    val: Expr::Lit(Literal::Number(Num::int_from_str("3"), Span::default()))
+                },
+            ]),
+            span: Span::default(),
+        };
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_many_unary_messages() {
+        let res = run!(expr(), "theta sin round");
+        let ans = Expr::Message {
+            receiver: Box::new(Expr::Message {
+                receiver: Box::new(mk_ident_expr("theta")),
+                selector: Msg::Unary(mk_ident("sin")),
+                span: Span::default(),
+            }),
+            selector: Msg::Unary(mk_ident("round")),
+            span: Span::default(),
+        };
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_cascade() {
+        let res = run!(expr(), "OrderedCollection new add: 1; add: 2; yourself");
+        let num = |n| Expr::Lit(Literal::Number(Num::int_from_str(n), Span::default()));
+        let add = |n| Msg::Kwargs(vec![Keyword { id: mk_ident("add:"), val: num(n) }]);
+        let ans = Expr::Cascade {
+            receiver: Box::new(Expr::Message {
+                receiver: Box::new(mk_ident_expr("OrderedCollection")),
+                selector: Msg::Unary(mk_ident("new")),
+                span: Span::default(),
+            }),
+            messages: vec![add("1"), add("2"), Msg::Unary(mk_ident("yourself"))],
+            span: Span::default(),
+        };
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_cascade_binary_receiver() {
+        // The receiver of the cascade is the receiver of the last message
+        // before the first `;`.
+        let res = run!(expr(), "a + b + c; - d");
+        match res {
+            Ok((Expr::Cascade { receiver, messages, span }, "")) => {
+                assert_eq!(receiver.to_string(), "a + b");
+                assert_eq!(messages.len(), 2);
+                assert_eq!((span.start, span.end), (at(1, 1), at(1, 15)));
+            }
+            other => panic!("expected a cascade, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_error_cascade_needs_separator() {
+        let err = parse_expr("Transcript show: 'a'; cr tab").unwrap_err();
+        assert_eq!(err.position, at(1, 26));
+        assert_eq!(
+            err.hints,
+            vec![String::from("cascaded messages must be separated by `;`")]
+        );
+    }
+
+    #[test]
+    fn test_error_cascade_without_message() {
+        let err = parse_expr("3; foo").unwrap_err();
+        assert_eq!(
+            err.hints,
+            vec![String::from("only a message send can be followed by `;`")]
+        );
+        let err = parse_expr("a foo;").unwrap_err();
+        assert_eq!(err.hints, vec![String::from("cascade needs a message after `;`")]);
+    }
+
+    #[test]
+    fn test_empty_statements() {
+        let res = run!(statements(), "");
+        let ans = vec![];
+        assert_eq!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_empty_expr() {
+        let res = run!(expr(), "");
+        assert!(is_err(res));
+    }
+
+    #[test]
+    fn test_empty_ident() {
+        assert!(is_err(run!(ident(), "")));
+    }
+
+    #[test]
+    fn test_empty_primary() {
+        assert!(is_err(run!(primary(), "")));
+    }
+
+    #[test]
+    fn test_empty_message_expr() {
+        assert!(is_err(run!(message_expr(), "")));
+    }
+
+    #[test]
+    fn test_empty_keyword_expr() {
+        assert!(is_err(run!(keyword_expr(), "")));
+    }
+
+    #[test]
+    fn test_empty_binary_object() {
+        assert!(is_err(run!(binary_object(), "")));
+    }
+
+    #[test]
+    fn test_empty_unary_object() {
+        assert!(is_err(run!(unary_object(), "")));
+    }
+
+    #[test]
+    fn test_empty_cascaded_message_expr() {
+        assert!(is_err(run!(cascaded_message_expr(), "")));
+    }
+
+    #[test]
+    fn test_expr_statement() {
+        let res = run!(statements(), "what");
+        let ans = vec![Statement::E(mk_ident_expr("what"))];
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+
+    #[test]
+    fn test_return_statement() {
+        let res = run!(statements(), "^ 'foo'");
+        let ans = vec![Statement::Ret(Expr::Lit(Literal::Str(String::from("foo"), Span::default())), Span::default())];
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_many_statements() {
+        let res = run!(statements(), "foo <- bar. ^ foo");
+        let ans = vec![
+            Statement::E(Expr::Assign(
+                mk_ident("foo"),
+                Box::new(mk_ident_expr("bar")),
+            )),
+            Statement::Ret(mk_ident_expr("foo"), Span::default()),
+        ];
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_message_pattern_unary() {
+        let res = run!(message_pattern(), "hello");
+        let ans = MsgPat::Unary(mk_ident("hello"));
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_message_pattern_binary() {
+        let res = run!(message_pattern(), "+ hello");
+        let ans = MsgPat::Bin(mk_ident("+"), mk_ident("hello"));
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_mssage_pattern_kwargs() {
+        let res = run!(message_pattern(), "foo: bar");
+        let ans = MsgPat::Kwargs(vec![
+            KeyPat {
+                keyword: mk_ident("foo:"),
+                var: mk_ident("bar")
+            }
+        ]);
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_method() {
+        let res = run!(method_p(), "foo ^ bar");
+        let ans = Method {
+            sig: MsgPat::Unary(mk_ident("foo")),
+            comment: None,
+            primitive: None,
+            pragmas: Vec::new(),
+            temps: None,
+            stmts: Some(vec![
+                Statement::Ret(mk_ident_expr("bar"), Span::default())
+            ]),
+            span: Span::default(),
+        };
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_method_temps() {
+        let res = run!(method_p(), "foo |asdf| ^ bar");
+        let ans = Method {
+            sig: MsgPat::Unary(mk_ident("foo")),
+            comment: None,
+            primitive: None,
+            pragmas: Vec::new(),
+            temps: Some(vec![mk_ident("asdf")]),
+            stmts: Some(vec![
+                Statement::Ret(mk_ident_expr("bar"), Span::default())
+            ]),
+            span: Span::default(),
+        };
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_method_bare_ret_kwargs() {
+        let res = run!(method_p(), "foo: asdf bar");
+        let ans = Method {
+            sig: MsgPat::Kwargs(vec![
+                KeyPat {
+                    keyword: mk_ident("foo:"),
+                    var: mk_ident("asdf"),
+                }
+            ]),
+            comment: None,
+            primitive: None,
+            pragmas: Vec::new(),
+            temps: None,
+            stmts: Some(vec![
+                Statement::E(mk_ident_expr("bar"))
+            ]),
+            span: Span::default(),
+        };
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_method_bare_ret() {
+        let res = run!(method_p(), "foo bar");
+        let ans = Method {
+            sig: MsgPat::Unary(mk_ident("foo")),
+            comment: None,
+            primitive: None,
+            pragmas: Vec::new(),
+            temps: None,
+            stmts: Some(vec![
+                Statement::E(mk_ident_expr("bar"))
+            ]),
+            span: Span::default(),
+        };
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_method_kwargs() {
+        let res = run!(method_p(), "foo: asdf ^ bar");
+        let ans = Method {
+            sig: MsgPat::Kwargs(vec![
+                KeyPat {
+                    keyword: mk_ident("foo:"),
+                    var: mk_ident("asdf"),
+                }
+            ]),
+            comment: None,
+            primitive: None,
+            pragmas: Vec::new(),
+            temps: None,
+            stmts: Some(vec![
+                Statement::Ret(mk_ident_expr("bar"), Span::default())
+            ]),
+            span: Span::default(),
+        };
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    #[test]
+    fn test_temporaries_empty() {
+        let res = run!(temporaries(), "");
+        assert!(is_err(res));
+    }
+
+    #[test]
+    fn test_temporaries() {
+        let res = run!(temporaries(), "| foo |");
+        let ans = vec![mk_ident("foo")];
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+
+    #[test]
+    fn test_any_whitespace() {
+        assert!(is_err(run!(any().map(|_| ()), "   ")));
+    }
+
+    #[test]
+    fn test_rectangle_constructor() {
+        let res = run!(expr(), "Rectangle
+    origin: (Point x:0 y:10)
+    extent: (Point x:5 y:15)");
+        let ans = Expr::Message {
+            receiver: Box::new(
+                mk_ident_expr("Rectangle")
+            ),
+            selector: Msg::Kwargs(vec![
+                Keyword {
+                    id: mk_ident("origin:"),
+                    val: Expr::Message {
+                        receiver: Box::new(mk_ident_expr("Point")),
+                        selector: Msg::Kwargs(vec![
+                            Keyword {
+                                id: mk_ident("x:"),
+                                val: Num::int_from_str("0").to_expr(),
+                            },
+                            Keyword {
+                                id: mk_ident("y:"),
+                                val: Num::int_from_str("10").to_expr(),
+                            }
+                        ]),
+                        span: Span::default(),
+                    }
+                },
+                Keyword {
+                    id: mk_ident("extent:"),
+                    val: Expr::Message {
+                        receiver: Box::new(mk_ident_expr("Point")),
+                        selector: Msg::Kwargs(vec![
+                            Keyword {
+                                id: mk_ident("x:"),
+                                val: Num::int_from_str("5").to_expr(),
+                            },
+                            Keyword {
+                                id: mk_ident("y:"),
+                                val: Num::int_from_str("15").to_expr()
+                            }
+                        ]),
+                        span: Span::default(),
+                    }
+                },
+            ]),
+            span: Span::default(),
+        };
+
+        assert_eq_ignoring_spans!(res, Ok((ans, "")));
+    }
+
+    fn at(line: usize, column: usize) -> Position {
+        Position { line, column }
+    }
+
+    #[test]
+    fn test_ident_span_excludes_whitespace() {
+        let (id, _) = run!(ident(), "index   ").unwrap();
+        assert_eq!((id.1.start, id.1.end), (at(1, 1), at(1, 6)));
+    }
+
+    #[test]
+    fn test_symbol_span_includes_hash() {
+        let lit = parse_literal("  #foo").unwrap();
+        let span = lit.span();
+        assert_eq!((span.start, span.end), (at(1, 3), at(1, 7)));
+    }
+
+    #[test]
+    fn test_message_span() {
+        let (e, _) = run!(expr(), "a b: 2 c: 3").unwrap();
+        let span = e.span();
+        assert_eq!((span.start, span.end), (at(1, 1), at(1, 12)));
+    }
+
+    #[test]
+    fn test_multiline_spans() {
+        let (stmts, _) = run!(statements(), "foo <- bar.\n^ foo").unwrap();
+        let first = stmts[0].span();
+        let second = stmts[1].span();
+        assert_eq!((first.start, first.end), (at(1, 1), at(1, 11)));
+        assert_eq!((second.start, second.end), (at(2, 1), at(2, 6)));
+    }
+
+    #[test]
+    fn test_method_span() {
+        let (m, _) = run!(method_p(), "foo: asdf ^ bar").unwrap();
+        assert_eq!((m.span.start, m.span.end), (at(1, 1), at(1, 16)));
+        assert_eq!(m.sig.span().end, at(1, 10));
+    }
+
+    #[test]
+    fn test_spans_take_part_in_eq() {
+        let (a, _) = run!(expr(), "x foo").unwrap();
+        let (b, _) = run!(expr(), "x    foo").unwrap();
+        assert!(a != b);
+        assert_eq_ignoring_spans!(a, b);
+        assert!(a.span().end != b.span().end);
+    }
+
+    #[test]
+    fn test_parse_expr() {
+        let res = parse_expr("  a foo: b + 1  ");
+        let ans = Expr::Message {
+            receiver: Box::new(mk_ident_expr("a")),
+            selector: Msg::Kwargs(vec![
+                Keyword {
+                    id: mk_ident("foo:"),
+                    val: Expr::Message {
+                        receiver: Box::new(mk_ident_expr("b")),
+                        selector: Msg::Binary(
+                            Selector::new("+"),
+                            Box::new(Num::int_from_str("1").to_expr()),
+                        ),
+                        span: Span::default(),
+                    },
+                },
+            ]),
+            span: Span::default(),
+        };
+        assert_eq_ignoring_spans!(res, Ok(ans));
+    }
+
+    #[test]
+    fn test_error_keyword_argument_missing() {
+        let err = parse_expr("a at: )").unwrap_err();
+        assert_eq!(err.position, at(1, 7));
+        assert_eq!(err.unexpected, Some(String::from(")")));
+        assert_eq!(err.expected, vec![String::from("expression")]);
+        assert_eq!(
+            err.hints,
+            vec![String::from("keyword message argument missing after `at:`")]
+        );
+    }
+
+    #[test]
+    fn test_error_binary_argument_missing() {
+        let err = parse_statements("3 + + 4").unwrap_err();
+        assert_eq!(err.position, at(1, 5));
+        assert_eq!(
+            err.hints,
+            vec![String::from("binary message `+` needs an argument")]
+        );
+    }
+
+    #[test]
+    fn test_error_unclosed_paren() {
+        let err = parse_expr("(a foo").unwrap_err();
+        assert_eq!(err.position, at(1, 7));
+        assert_eq!(err.unexpected, None);
+        assert_eq!(err.message(), "unexpected end of input, expected `)`");
+        assert_eq!(
+            err.hints,
+            vec![String::from("missing `)` to close the `(` at line 1, column 1")]
+        );
+    }
+
+    #[test]
+    fn test_error_unterminated_string() {
+        let err = parse_literal("'abc").unwrap_err();
+        assert_eq!(err.hints, vec![String::from("unterminated string literal")]);
+    }
+
+    #[test]
+    fn test_error_unmatched_paren() {
+        let err = parse_statements("a b c ) d").unwrap_err();
+        assert_eq!(err.position, at(1, 7));
+        assert_eq!(err.hints, vec![String::from("unmatched `)`")]);
+    }
+
+    #[test]
+    fn test_error_unreadable_character() {
+        let err = Dialect::BlueBook.config().parse_expr("my_var foo").unwrap_err();
+        assert_eq!(err.position, at(1, 3));
+        assert_eq!(err.unexpected, Some(String::from("_")));
+        let err = parse_expr("#:").unwrap_err();
+        assert_eq!(err.position, at(1, 1));
+        assert_eq!(err.unexpected, Some(String::from("#")));
+        assert_eq!(err.expected, vec![String::from("symbol"), String::from("`(`")]);
+    }
+
+    #[test]
+    fn test_assignment_spellings() {
+        let ans = parse_statements("x <- y <- 3. ^x").unwrap();
+        for src in &["x := y := 3. ^x", "x _ y _ 3. ^x", "x ← y ← 3. ↑x", "x_y<-3. ^x"] {
+            let res = ParserConfig::ALL_SPELLINGS.parse_statements(src);
+            assert_eq_ignoring_spans!(res.as_ref().ok(), Some(&ans), "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_assignment_spellings_are_separate() {
+        let colon_equals = ParserConfig { colon_equals: true, ..ParserConfig::default() };
+        assert!(colon_equals.parse_statements("x := 3").is_ok());
+        assert!(colon_equals.parse_statements("x _ 3").is_err());
+        assert!(colon_equals.parse_statements("x ← 3").is_err());
+        let underscore = ParserConfig { underscore_assignment: true, ..ParserConfig::default() };
+        assert!(underscore.parse_statements("x _ 3").is_ok());
+        assert!(underscore.parse_statements("↑x").is_err());
+        let arrows = ParserConfig { arrow_glyphs: true, ..ParserConfig::default() };
+        assert!(arrows.parse_statements("x ← 3. ↑x").is_ok());
+        assert!(arrows.parse_statements("x := 3").is_err());
+    }
+
+    #[test]
+    fn test_blue_book_rejects_other_spellings() {
+        for src in &["x _ 3", "x ← 3", "↑x"] {
+            assert!(parse_statements(src).is_err(), "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_chunks_in_dialect() {
+        let src = "!Point methodsFor: 'accessing'!\nx: n\n    x := n! !";
+        assert!(parse_chunks(src).is_err());
+        let squeak = ParserConfig { colon_equals: true, ..ParserConfig::default() };
+        assert_eq!(squeak.parse_chunks(src).map(|c| c.len()), Ok(1));
+    }
+
+    fn int(digits: &str) -> Literal {
+        Literal::Number(num(false, None, digits, None, None, None), Span::default())
+    }
+
+    fn only<F: Fn(&mut ParserConfig)>(enable: F) -> ParserConfig {
+        let mut config = ParserConfig::default();
+        enable(&mut config);
+        config
+    }
+
+    #[test]
+    fn test_byte_arrays() {
+        let config = only(|c| c.byte_arrays = true);
+        let bytes = |b: &[u8]| Literal::ByteArray(b.to_vec(), Span::default());
+        assert_eq_ignoring_spans!(config.parse_literal("#[1 2 255]"), Ok(bytes(&[1, 2, 255])));
+        assert_eq_ignoring_spans!(config.parse_literal("#[ ]"), Ok(bytes(&[])));
+        assert_eq_ignoring_spans!(
+            config.parse_literal("#(1 #[2])"),
+            Ok(Literal::Array(vec![int("1"), bytes(&[2])], Span::default()))
+        );
+        let err = config.parse_literal("#[1 256]").unwrap_err();
+        assert_eq!(err.position, at(1, 5));
+        assert_eq!(err.hints, vec![String::from("a byte must be between 0 and 255, not 256")]);
+        assert!(parse_literal("#[1 2]").is_err());
+    }
+
+    #[test]
+    fn test_brace_arrays() {
+        let config = only(|c| c.brace_arrays = true);
+        let brace = |elements| Expr::Brace { elements, span: Span::default() };
+        assert_eq_ignoring_spans!(config.parse_expr("{}"), Ok(brace(vec![])));
+        assert_eq_ignoring_spans!(
+            config.parse_expr("{a. b + 1.}"),
+            Ok(brace(vec![mk_ident_expr("a"), parse_expr("b + 1").unwrap()]))
+        );
+        assert_eq_ignoring_spans!(
+            config.parse_expr("{a} size"),
+            Ok(Expr::Message {
+                receiver: Box::new(brace(vec![mk_ident_expr("a")])),
+                selector: Msg::Unary(mk_ident("size")),
+                span: Span::default(),
+            })
+        );
+        let err = config.parse_expr("{a. b").unwrap_err();
+        assert_eq!(err.hints, vec![String::from("missing `}` to close the brace array")]);
+        assert!(parse_expr("{a. b}").is_err());
+    }
+
+    #[test]
+    fn test_literal_constants() {
+        let config = only(|c| c.literal_constants = true);
+        let span = Span::default();
+        assert_eq_ignoring_spans!(
+            config.parse_literal("#(true false nil #true foo)"),
+            Ok(Literal::Array(vec![
+                Literal::Bool(true, span),
+                Literal::Bool(false, span),
+                Literal::Nil(span),
+                Literal::Symbol(Selector::new("true"), span),
+                Literal::Symbol(Selector::new("foo"), span),
+            ], span))
+        );
+        assert_eq_ignoring_spans!(
+            parse_literal("#(true nil)"),
+            Ok(Literal::Array(vec![
+                Literal::Symbol(Selector::new("true"), span),
+                Literal::Symbol(Selector::new("nil"), span),
+            ], span))
+        );
+    }
+
+    #[test]
+    fn test_pragmas() {
+        let config = only(|c| c.pragmas = true);
+        let src = "+ x <primitive: 1> <foo: 2 bar: #baz> <inline> ^self";
+        let m = config.parse_method(src).unwrap();
+        let baz = Literal::Symbol(Selector::new("baz"), Span::default());
+        let pragma = |selector: &str, args| Pragma {
+            selector: Selector::new(selector),
+            args,
+            span: Span::default(),
+        };
+        assert_eq!(m.primitive, Some(1));
+        assert_eq_ignoring_spans!(m.pragmas, vec![
+            pragma("foo:bar:", vec![int("2"), baz]),
+            pragma("inline", vec![]),
+        ]);
+        assert_eq!(m.stmts.map(|s| s.len()), Some(1));
+        let m = config.parse_method("foo <primitive: 'prim' module: 'Plugin'>").unwrap();
+        assert_eq!(m.primitive, None);
+        assert_eq!(m.pragmas.len(), 1);
+        let err = config.parse_method("foo <bar: 60 ^self").unwrap_err();
+        assert_eq!(err.hints, vec![String::from("missing `>` to close the pragma")]);
+        assert!(parse_method("foo <bar: 60> ^self").is_err());
+    }
+
+    #[test]
+    fn test_primitive() {
+        let m = parse_method("+ aNumber <primitive: 1> ^super + aNumber").unwrap();
+        assert_eq!(m.primitive, Some(1));
+        assert!(m.pragmas.is_empty());
+        assert_eq!(m.stmts.map(|s| s.len()), Some(1));
+        let m = parse_method("at: i \"Index.\" <primitive: 60> | t | ^self error").unwrap();
+        assert_eq!(m.comment, Some(String::from("Index.")));
+        assert_eq!(m.primitive, Some(60));
+        assert_eq!(m.temps.map(|t| t.len()), Some(1));
+        let m = parse_method("foo <primitive: 60>").unwrap();
+        assert_eq!((m.span.start, m.span.end), (at(1, 1), at(1, 20)));
+        assert_eq!(parse_method("foo ^1").unwrap().primitive, None);
+    }
+
+    #[test]
+    fn test_error_primitive() {
+        let err = parse_method("foo <primitive: 1.5> ^self").unwrap_err();
+        assert_eq!(err.position, at(1, 17));
+        assert_eq!(err.hints, vec![String::from("a primitive index must be a whole number")]);
+        let err = parse_method("foo <primitive: 60 ^self").unwrap_err();
+        assert_eq!(err.hints, vec![String::from("missing `>` to close the pragma")]);
+    }
+
+    #[test]
+    fn test_this_context() {
+        let config = only(|c| c.this_context = true);
+        let this_context = Expr::Pseudo(PseudoVar::ThisContext, Span::default());
+        assert_eq_ignoring_spans!(config.parse_expr("thisContext"), Ok(this_context));
+        assert_eq_ignoring_spans!(config.parse_expr("thisContextual"), Ok(mk_ident_expr("thisContextual")));
+        assert_eq_ignoring_spans!(parse_expr("thisContext"), Ok(mk_ident_expr("thisContext")));
+    }
+
+    #[test]
+    fn test_pseudo_variables() {
+        let pseudo = |var| Expr::Pseudo(var, Span::default());
+        for &var in &[PseudoVar::Self_, PseudoVar::Super, PseudoVar::True,
+                      PseudoVar::False, PseudoVar::Nil] {
+            assert_eq_ignoring_spans!(parse_expr(var.name()), Ok(pseudo(var)));
+        }
+        assert_eq_ignoring_spans!(parse_expr("selfish"), Ok(mk_ident_expr("selfish")));
+        assert_eq_ignoring_spans!(parse_expr("nil2"), Ok(mk_ident_expr("nil2")));
+        assert_eq_ignoring_spans!(
+            parse_expr("x <- super foo: true"),
+            Ok(Expr::Assign(mk_ident("x"), Box::new(Expr::Message {
+                receiver: Box::new(pseudo(PseudoVar::Super)),
+                selector: Msg::Kwargs(vec![Keyword {
+                    id: mk_ident("foo:"),
+                    val: pseudo(PseudoVar::True),
+                }]),
+                span: Span::default(),
+            })))
+        );
+        let span = parse_expr("  nil").unwrap().span();
+        assert_eq!((span.start, span.end), (at(1, 3), at(1, 6)));
+    }
+
+    #[test]
+    fn test_error_assign_to_pseudo_variable() {
+        let err = parse_statements("a <- 1. self <- 3").unwrap_err();
+        assert_eq!(err.position, at(1, 9));
+        assert_eq!(err.hints, vec![String::from("cannot assign to the pseudo-variable `self`")]);
+        let err = parse_expr("x <- nil <- 3").unwrap_err();
+        assert_eq!(err.position, at(1, 6));
+        assert_eq!(err.hints[0], "cannot assign to the pseudo-variable `nil`");
+        let config = only(|c| c.this_context = true);
+        assert!(config.parse_expr("thisContext <- 3").is_err());
+        assert!(parse_expr("thisContext <- 3").is_ok());
+    }
+
+    #[test]
+    fn test_dialects() {
+        let src = "x := {#[1 2]. 1.5s2. #(true)}. ^thisContext";
+        assert!(Dialect::Pharo.config().parse_statements(src).is_ok());
+        assert!(Dialect::Squeak.config().parse_statements(src).is_ok());
+        assert!(Dialect::Squeak.config().parse_statements("x _ 3").is_ok());
+        assert!(Dialect::Pharo.config().parse_statements("x _ 3").is_err());
+        assert!(Dialect::Pharo.config().parse_statements("my_var := self at_end: _x").is_ok());
+        assert!(Dialect::Ansi.config().parse_expr("my_var foo").is_ok());
+        assert!(Dialect::BlueBook.config().parse_expr("my_var foo").is_err());
+        assert!(Dialect::Ansi.config().parse_statements("x := #[1 2]. ^1.5s2").is_ok());
+        assert!(Dialect::Ansi.config().parse_statements("^{1}").is_err());
+        assert_eq!(Dialect::default().config(), ParserConfig::default());
+    }
+
+    #[test]
+    fn test_left_arrow_is_a_send_in_modern_dialects() {
+        for dialect in &[Dialect::Squeak, Dialect::Pharo, Dialect::Ansi] {
+            let send = dialect.config().parse_expr("a<-1");
+            assert_eq_ignoring_spans!(send, parse_expr("a < -1"), "{:?}", dialect);
+        }
+        let arrow = Expr::Message {
+            receiver: Box::new(mk_ident_expr("a")),
+            selector: Msg::Binary(Selector::new("<-"), Box::new(mk_ident_expr("b"))),
+            span: Span::default(),
+        };
+        assert_eq_ignoring_spans!(Dialect::Pharo.config().parse_expr("a<-b"), Ok(arrow));
+        assert!(matches!(parse_expr("a<-1"), Ok(Expr::Assign(..))));
+    }
+
+    fn block(args: &[&str], temps: &[&str], statements: Vec<Statement>) -> Expr {
+        Expr::Block {
+            args: args.iter().map(|a| mk_ident(a)).collect(),
+            temps: temps.iter().map(|t| mk_ident(t)).collect(),
+            statements,
+            span: Span::default(),
+        }
+    }
+
+    #[test]
+    fn test_block_forms() {
+        let foo = || Statement::E(parse_expr("x foo").unwrap());
+        let nil = Expr::Pseudo(PseudoVar::Nil, Span::default());
+        let ret_nil = || Statement::Ret(nil.clone(), Span::default());
+        let cases = vec![
+            ("[]", block(&[], &[], vec![])),
+            ("[ ]", block(&[], &[], vec![])),
+            ("[x foo]", block(&[], &[], vec![foo()])),
+            ("[^nil]", block(&[], &[], vec![ret_nil()])),
+            ("[:x | ]", block(&["x"], &[], vec![])),
+            ("[:x :y | x foo]", block(&["x", "y"], &[], vec![foo()])),
+            ("[| t | x foo]", block(&[], &["t"], vec![foo()])),
+            ("[:x | | t u | x foo. ^nil]", block(&["x"], &["t", "u"], vec![foo(), ret_nil()])),
+        ];
+        for (src, ans) in cases {
+            assert_eq_ignoring_spans!(parse_expr(src), Ok(ans), "{}", src);
+        }
+        let span = parse_expr("[:x | x]").unwrap().span();
+        assert_eq!((span.start, span.end), (at(1, 1), at(1, 9)));
+    }
+
+    #[test]
+    fn test_error_block_args_need_bar() {
+        let err = parse_expr("[:x x foo]").unwrap_err();
+        assert_eq!(err.position, at(1, 5));
+        assert_eq!(err.hints, vec![String::from("missing `|` after the block arguments")]);
+        let err = parse_expr("[| t x foo]").unwrap_err();
+        assert_eq!(err.hints, vec![String::from("missing `|` to close the temporaries")]);
+    }
+
+    #[test]
+    fn test_do_it() {
+        let do_it = |temps: &[&str], src| Syntax::DoIt {
+            temps: temps.iter().map(|t| mk_ident(t)).collect(),
+            statements: parse_statements(src).unwrap(),
+        };
+        assert_eq_ignoring_spans!(
+            parse_do_it("| a b | a <- 3. b <- a + 4. b printString"),
+            Ok(do_it(&["a", "b"], "a <- 3. b <- a + 4. b printString"))
+        );
+        assert_eq!(parse_do_it("3 + 4"), Ok(do_it(&[], "3 + 4")));
+        assert_eq!(parse_do_it("  "), Ok(do_it(&[], "")));
+        assert_eq_ignoring_spans!(parse_do_it("| t | ^t"), Ok(do_it(&["t"], "^t")));
+        assert_eq_ignoring_spans!(
+            Dialect::Pharo.config().parse_do_it("| a | a := 3"),
+            Ok(do_it(&["a"], "a <- 3"))
+        );
+    }
+
+    #[test]
+    fn test_error_do_it() {
+        let err = parse_do_it("| a b a <- 3").unwrap_err();
+        assert_eq!(err.hints, vec![String::from("missing `|` to close the temporaries")]);
+        let err = parse_do_it("| | a").unwrap_err();
+        assert_eq!(err.position, at(1, 3));
+        assert_eq!(err.expected, vec![String::from("identifier")]);
+    }
+
+    #[test]
+    fn test_error_colon_equals() {
+        let err = parse_statements("x := 3").unwrap_err();
+        assert_eq!(err.position, at(1, 3));
+        assert_eq!(
+            err.hints,
+            vec![String::from("blue book assignment is written `<-`, not `:=`")]
+        );
+    }
+
+    #[test]
+    fn test_error_method_pattern() {
+        let err = parse_method("foo: ").unwrap_err();
+        assert_eq!(err.expected, vec![String::from("identifier")]);
+        assert_eq!(
+            err.hints,
+            vec![String::from("argument name missing after `foo:`")]
+        );
+    }
+
+    #[test]
+    fn test_error_rendering_multiline() {
+        let err = parse_statements("a\n  foo: 3 bar: \n    ]").unwrap_err();
+        assert_eq!(err.position, at(3, 5));
+        assert_eq!(err.snippet, "    ]");
+        assert_eq!(
+            err.to_string(),
+            "error: unexpected `]`, expected expression\n \
+             --> line 3, column 5\n  \
+             |\n\
+             3 |     ]\n  \
+             |     ^\n  \
+             = hint: keyword message argument missing after `bar:`"
+        );
+    }
+
+    #[test]
+    fn test_error_underlines_identifier() {
+        let err = parse_method("+ 42abc").unwrap_err();
+        assert_eq!(err.position, at(1, 3));
+        assert_eq!(err.underline(), "  ^^^^^");
+    }
+
+    fn is_error(stmt: &Statement) -> bool {
+        matches!(*stmt, Statement::E(Expr::Error(_)))
+    }
+
+    #[test]
+    fn test_recover_middle_statement() {
+        let (stmts, errors) = parse_statements_recovering("a foo. b at: ). c bar");
+        assert_eq!(stmts.len(), 3);
+        assert!(!is_error(&stmts[0]));
+        assert!(is_error(&stmts[1]));
+        assert_eq_ignoring_spans!(stmts[2], parse_statements("c bar").unwrap()[0]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].position, at(1, 14));
+        match stmts[1] {
+            Statement::E(Expr::Error(span)) => {
+                assert_eq!((span.start, span.end), (at(1, 8), at(1, 15)));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_recover_inside_block() {
+        let (stmts, errors) = parse_statements_recovering("x <- [:a | a + . a]. y");
+        assert_eq!(stmts.len(), 2);
+        assert_eq!(errors.len(), 1);
+        match stmts[0] {
+            Statement::E(Expr::Assign(_, ref val)) => match **val {
+                Expr::Block { ref statements, .. } => {
+                    assert_eq!(statements.len(), 2);
+                    assert!(is_error(&statements[0]));
+                    assert!(!is_error(&statements[1]));
+                }
+                ref e => panic!("expected a block, got {:?}", e),
+            },
+            ref s => panic!("expected an assignment, got {:?}", s),
+        }
+    }
+
+    #[test]
+    fn test_recover_unmatched_bracket() {
+        let (stmts, errors) = parse_statements_recovering("a foo ]. b");
+        assert_eq!(stmts.len(), 2);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].hints, vec![String::from("unmatched `]`")]);
+    }
+
+    #[test]
+    fn test_recover_missing_period() {
+        let (stmts, errors) = parse_statements_recovering("a foo\nb bar: 1)\nc");
+        assert_eq!(stmts.len(), 1);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].unexpected, Some(String::from(")")));
+    }
+
+    #[test]
+    fn test_recover_skips_literals() {
+        let (stmts, errors) = parse_statements_recovering("a + ']. x'. 3.5 foo: . $.. b");
+        assert_eq!(stmts.len(), 4);
+        assert!(!is_error(&stmts[0]));
+        assert!(is_error(&stmts[1]));
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn test_recover_valid_input() {
+        let src = "x <- 3 + 4. [:y | y foo] value: x. ^x";
+        let (stmts, errors) = parse_statements_recovering(src);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(stmts, parse_statements(src).unwrap());
+    }
+
+    #[test]
+    fn test_comments_as_whitespace() {
+        let src = "\"first\" x <- \"the sum\" 3 \"three\" + 4. \"done\" ^ x \"bye\"";
+        assert_eq_ignoring_spans!(parse_statements(src), parse_statements("x <- 3 + 4. ^x"));
+        let src = "[:a \"arg\" | \"body\" a foo: \"kw\" 1] value: 2";
+        assert_eq_ignoring_spans!(parse_expr(src), parse_expr("[:a | a foo: 1] value: 2"));
+    }
+
+    #[test]
+    fn test_comment_span_excluded() {
+        let e = parse_expr("foo \"a comment\" bar").unwrap();
+        let span = e.span();
+        assert_eq!((span.start, span.end), (at(1, 1), at(1, 20)));
+        match e {
+            Expr::Message { receiver, .. } => {
+                let span = receiver.span();
+                assert_eq!((span.start, span.end), (at(1, 1), at(1, 4)));
+            }
+            e => panic!("expected a message, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_method_comment() {
+        let m = parse_method("at: i put: x\n    \"Store x at i.\" \"more\"\n    | t |\n    ^x").unwrap();
+        assert_eq!(m.comment, Some(String::from("Store x at i.")));
+        assert_eq_ignoring_spans!(m.temps, Some(vec![mk_ident("t")]));
+        let m = parse_method("foo ^self").unwrap();
+        assert_eq!(m.comment, None);
+    }
+
+    #[test]
+    fn test_error_unterminated_comment() {
+        let err = parse_statements("a foo \"oops").unwrap_err();
+        assert_eq!(err.unexpected, None);
+        assert!(err.hints.contains(&String::from("unterminated comment")));
+    }
+
+    #[test]
+    fn test_recover_skips_comments() {
+        let (stmts, errors) = parse_statements_recovering("a foo: \"x. y\" ). b");
+        assert_eq!(stmts.len(), 2);
+        assert_eq!(errors.len(), 1);
+    }
+
+    fn num(negative: bool, radix: Option<u8>, integer: &str, mantissa: Option<&str>,
+           exponent: Option<i32>, scale: Option<u32>) -> Num {
+        Num {
+            negative,
+            radix,
+            integer: String::from(integer),
+            mantissa: mantissa.map(String::from),
+            exponent,
+            scale,
+        }
+    }
+
+    #[test]
+    fn test_negative_numbers() {
+        assert_eq!(number("-3"), Ok((num(true, None, "3", None, None, None), "")));
+        assert_eq!(number("-16rFF"), Ok((num(true, Some(16), "FF", None, None, None), "")));
+        assert_eq!(number("16r-FF"), Ok((num(true, Some(16), "FF", None, None, None), "")));
+        assert_eq!(number("1.5e-3"), Ok((num(false, None, "1", Some("5"), Some(-3), None), "")));
+        assert_eq!(number("-2r1e10"), Ok((num(true, Some(2), "1", None, Some(10), None), "")));
+        assert!(is_err(number("-16r-FF")));
+    }
+
+    #[test]
+    fn test_scaled_decimals() {
+        let ansi = Dialect::Ansi.config();
+        let lit = |n| Ok(Literal::Number(n, Span::default()));
+        let scaled = |mantissa, scale| num(false, None, "1", Some(mantissa), None, Some(scale));
+        assert_eq_ignoring_spans!(ansi.parse_literal("1.25s2"), lit(scaled("25", 2)));
+        assert_eq_ignoring_spans!(ansi.parse_literal("1.250s"), lit(scaled("250", 3)));
+        assert_eq_ignoring_spans!(ansi.parse_literal("3s"), lit(num(false, None, "3", None, None, Some(0))));
+        assert_eq_ignoring_spans!(ansi.parse_expr("3sqrt"), parse_expr("3 sqrt"));
+    }
+
+    #[test]
+    fn test_scaled_decimals_need_config() {
+        let res = number("1.25s2");
+        assert_eq!(res, Ok((num(false, None, "1", Some("25"), None, None), "s2")));
+    }
+
+    #[test]
+    fn test_number_stops_before_message() {
+        assert_eq!(number("3e"), Ok((num(false, None, "3", None, None, None), "e")));
+        assert_eq!(number("3. Foo"), Ok((num(false, None, "3", None, None, None), ". Foo")));
+        assert_eq!(number("16r1.x"), Ok((num(false, Some(16), "1", None, None, None), ".x")));
+    }
+
+    #[test]
+    fn test_negative_literal_in_expressions() {
+        let minus_four = Expr::Message {
+            receiver: Box::new(num(false, None, "3", None, None, None).to_expr()),
+            selector: Msg::Binary(
+                Selector::new("-"),
+                Box::new(num(true, None, "4", None, None, None).to_expr()),
+            ),
+            span: Span::default(),
+        };
+        assert_eq_ignoring_spans!(parse_expr("3 - -4"), Ok(minus_four.clone()));
+        assert_eq_ignoring_spans!(parse_expr("3--4"), Ok(minus_four));
+        let sub = parse_expr("3-4").unwrap();
+        match sub {
+            Expr::Message { selector: Msg::Binary(ref sel, _), .. } => assert_eq!(sel.as_str(), "-"),
+            ref e => panic!("expected a subtraction, got {:?}", e),
+        }
+        let m = parse_method("x -4").unwrap();
+        assert_eq_ignoring_spans!(m.stmts, Some(vec![Statement::E(num(true, None, "4", None, None, None).to_expr())]));
+        let arr = parse_literal("#(-1 - foo)").unwrap();
+        assert_eq_ignoring_spans!(arr, Literal::Array(vec![
+            Literal::Number(num(true, None, "1", None, None, None), Span::default()),
+            Literal::Symbol(Selector::new("-"), Span::default()),
+            Literal::Symbol(Selector::new("foo"), Span::default()),
+        ], Span::default()));
+    }
+
+    #[test]
+    fn test_error_digit_invalid_for_radix() {
+        let err = parse_expr("x <- 2r1013").unwrap_err();
+        assert_eq!(err.position, at(1, 11));
+        assert_eq!(err.unexpected, Some(String::from("3")));
+        assert_eq!(err.hints[0], "`3` is not a digit in base 2");
+        assert!(parse_expr("2r3").is_err());
+        assert!(parse_expr("12A").is_err());
+        assert!(parse_expr("2r1.2").is_err());
+        let err = parse_expr("37r1").unwrap_err();
+        assert_eq!(err.hints, vec![String::from("radix must be between 2 and 36, not 37")]);
+        let err = parse_expr("-16r-3").unwrap_err();
+        assert_eq!(err.position, at(1, 5));
+        assert_eq!(err.hints[0], "a number can only have one `-`");
+    }
+
+    #[test]
+    fn test_error_number_too_large() {
+        let err = parse_expr("1e99999999999").unwrap_err();
+        assert_eq!(err.position, at(1, 3));
+        assert_eq!(err.hints, vec![String::from("exponent 99999999999 is too large")]);
+        let config = Dialect::Ansi.config();
+        let err = config.parse_expr("1.5s99999999999").unwrap_err();
+        assert_eq!(err.position, at(1, 5));
+        assert_eq!(err.hints, vec![String::from("scale 99999999999 is too large")]);
+        assert!(config.parse_expr("1.5s4294967295").is_ok());
+    }
+}
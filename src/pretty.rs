@@ -0,0 +1,641 @@
+//! Printing syntax trees back out as Smalltalk source.
+//!
+//! The printer produces one canonical layout for a tree, whatever layout it
+//! was parsed from: statements go on lines of their own, and an expression
+//! which doesn't fit in the line width is broken up, one keyword part per line
+//! and one block statement per line. Parentheses are only added where the
+//! unary > binary > keyword precedence of Smalltalk demands them, and
+//! comments other than method comments are not kept.
+//!
+//! Printing round-trips: parsing the output gives back the tree that was
+//! printed. The exception is a tree from the error-recovering parser, whose
+//! `Expr::Error` nodes print as `` `error` `` so that parsing fails again.
+//!
+//! ```
+//! use smoltok::parser::parse_method;
+//! use smoltok::pretty::{print_method, Config};
+//! use smoltok::syntax::SpanlessEq;
+//!
+//! let m = parse_method("max: a  ^(self > a) ifTrue: [:x | self] ifFalse: [:x | a]").unwrap();
+//! let narrow = Config { width: 30, ..Config::default() };
+//! assert_eq!(
+//!     print_method(&m, &narrow),
+//!     "max: a
+//!     ^self > a
+//!         ifTrue: [:x | self]
+//!         ifFalse: [:x | a]"
+//! );
+//! assert!(parse_method(&print_method(&m, &narrow)).spanless_eq(&Ok(m)));
+//! ```
+
+use std::fmt::{self, Write};
+
+use syntax::*;
+
+// What an `Expr::Error` is printed as.
+const ERROR: &str = "`error`";
+
+/// How to lay out the printed source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// The number of spaces to indent by for each level of nesting.
+    pub indent: usize,
+    /// The column to try to keep lines within. Expressions are only broken
+    /// across lines where Smalltalk allows, so a line can still overflow.
+    pub width: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config { indent: 4, width: 80 }
+    }
+}
+
+/// Print an expression, as if it started at the beginning of a line.
+pub fn print_expr(e: &Expr, config: &Config) -> String {
+    Printer { config }.expr(e, 0, 0)
+}
+
+/// Print a sequence of statements, one to a line.
+pub fn print_statements(stmts: &[Statement], config: &Config) -> String {
+    Printer { config }.statements(stmts, 0)
+}
+
+/// Print a method: its message pattern on the first line, then its comment,
+/// temporaries and statements indented underneath.
+pub fn print_method(method: &Method, config: &Config) -> String {
+    Printer { config }.method(method, 0)
+}
+
+/// Print a literal, with the `#` that introduces symbols and arrays.
+pub fn print_literal(lit: &Literal) -> String {
+    let mut out = String::new();
+    literal(&mut out, lit, true);
+    out
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&print_expr(self, &Config::default()))
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&print_statements(::std::slice::from_ref(self), &Config::default()))
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&print_method(self, &Config::default()))
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&print_literal(self))
+    }
+}
+
+// How tightly an expression binds. An expression needs parentheses when it is
+// used where only something binding at least as tightly is allowed.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum Prec {
+    Primary,
+    Unary,
+    Binary,
+    Keyword,
+    Cascade,
+    Assign,
+}
+
+fn prec(e: &Expr) -> Prec {
+    match *e {
+        Expr::Message { selector: Msg::Unary(_), .. } => Prec::Unary,
+        Expr::Message { selector: Msg::Binary(..), .. } => Prec::Binary,
+        Expr::Message { selector: Msg::Kwargs(_), .. } => Prec::Keyword,
+        Expr::Cascade { .. } => Prec::Cascade,
+        Expr::Assign(..) | Expr::Method(_) => Prec::Assign,
+        _ => Prec::Primary,
+    }
+}
+
+struct Printer<'a> {
+    config: &'a Config,
+}
+
+impl<'a> Printer<'a> {
+    fn indent(&self, depth: usize) -> String {
+        " ".repeat(depth * self.config.indent)
+    }
+
+    fn newline(&self, depth: usize) -> String {
+        format!("\n{}", self.indent(depth))
+    }
+
+    fn method(&self, m: &Method, depth: usize) -> String {
+        let mut out = match m.sig {
+            MsgPat::Unary(ref id) => id.0.clone(),
+            MsgPat::Bin(ref sel, ref var) => format!("{} {}", sel.0, var.0),
+            MsgPat::Kwargs(ref kws) => {
+                let parts: Vec<_> = kws.iter()
+                    .map(|kw| format!("{} {}", kw.keyword.0, kw.var.0))
+                    .collect();
+                parts.join(" ")
+            }
+        };
+        let body = self.newline(depth + 1);
+        if let Some(ref comment) = m.comment {
+            let _ = write!(out, "{}\"{}\"", body, comment);
+        }
+        if let Some(index) = m.primitive {
+            let _ = write!(out, "{}<primitive: {}>", body, index);
+        }
+        for p in &m.pragmas {
+            out.push_str(&body);
+            pragma(&mut out, p);
+        }
+        if let Some(ref temps) = m.temps {
+            let names: Vec<_> = temps.iter().map(|t| &t.0[..]).collect();
+            let _ = write!(out, "{}| {} |", body, names.join(" "));
+        }
+        if let Some(ref stmts) = m.stmts {
+            if !stmts.is_empty() {
+                out.push_str(&body);
+                out.push_str(&self.statements(stmts, depth + 1));
+            }
+        }
+        out
+    }
+
+    // Statements at the start of a line indented to `depth`.
+    fn statements(&self, stmts: &[Statement], depth: usize) -> String {
+        let column = depth * self.config.indent;
+        let lines: Vec<_> = stmts.iter().map(|stmt| match *stmt {
+            Statement::E(ref e) => self.expr(e, column, depth),
+            Statement::Ret(ref e, _) => format!("^{}", self.expr(e, column + 1, depth)),
+        }).collect();
+        lines.join(&format!(".{}", self.newline(depth)))
+    }
+
+    // Print `e` starting at `column`, on a line indented to `depth`.
+    fn expr(&self, e: &Expr, column: usize, depth: usize) -> String {
+        let flat = flat_expr(e);
+        if column + flat.len() <= self.config.width && !flat.contains('\n') {
+            return flat;
+        }
+        match *e {
+            Expr::Assign(ref id, ref val) => {
+                let lhs = format!("{} <- ", id.0);
+                let rhs = self.expr(val, column + lhs.len(), depth);
+                lhs + &rhs
+            }
+            Expr::Message { ref receiver, ref selector, .. } => match *selector {
+                Msg::Unary(ref sel) => {
+                    let recv = self.operand(receiver, Prec::Unary, column, depth);
+                    format!("{} {}", recv, sel.0)
+                }
+                Msg::Binary(ref sel, ref arg) => {
+                    let recv = self.operand(receiver, Prec::Binary, column, depth);
+                    let start = last_line_len(&recv, column) + sel.0.len() + 2;
+                    let arg = self.operand(arg, Prec::Unary, start, depth);
+                    format!("{} {} {}", recv, sel, arg)
+                }
+                Msg::Kwargs(ref kws) => {
+                    let mut out = self.operand(receiver, Prec::Binary, column, depth);
+                    let inner = depth + 1;
+                    for kw in kws {
+                        out.push_str(&self.newline(inner));
+                        out.push_str(&kw.id.0);
+                        out.push(' ');
+                        let start = inner * self.config.indent + kw.id.0.len() + 1;
+                        out.push_str(&self.operand(&kw.val, Prec::Binary, start, inner));
+                    }
+                    out
+                }
+            },
+            Expr::Cascade { ref receiver, ref messages, .. } => {
+                let max = messages.first().map_or(Prec::Primary, receiver_prec);
+                let mut out = self.operand(receiver, max, column, depth);
+                let inner = depth + 1;
+                for (i, msg) in messages.iter().enumerate() {
+                    if i > 0 {
+                        out.push(';');
+                    }
+                    out.push_str(&self.newline(inner));
+                    out.push_str(&self.message(msg, inner * self.config.indent, inner + 1));
+                }
+                out
+            }
+            Expr::Block { ref args, ref temps, ref statements, .. } => {
+                let mut out = String::from("[");
+                out.push_str(&block_header(args, temps));
+                if !statements.is_empty() {
+                    out.push_str(&self.newline(depth + 1));
+                    out.push_str(&self.statements(statements, depth + 1));
+                }
+                out.push(']');
+                out
+            }
+            Expr::Brace { ref elements, .. } => {
+                let inner = self.newline(depth + 1);
+                let elems: Vec<_> = elements.iter()
+                    .map(|e| self.expr(e, (depth + 1) * self.config.indent, depth + 1))
+                    .collect();
+                format!("{{{}{}}}", inner, elems.join(&format!(".{}", inner)))
+            }
+            Expr::Method(ref m) => self.method(m, depth),
+            Expr::Id(_) | Expr::Lit(_) | Expr::Pseudo(..) | Expr::Error(_) => flat,
+        }
+    }
+
+    // Print a message of a cascade, without its receiver, starting at
+    // `column`. Keywords which don't fit go on lines indented to `depth`.
+    fn message(&self, msg: &Msg, column: usize, depth: usize) -> String {
+        let mut flat = String::new();
+        flat_message(&mut flat, msg);
+        if column + flat.len() <= self.config.width && !flat.contains('\n') {
+            return flat;
+        }
+        match *msg {
+            Msg::Unary(ref sel) => sel.0.clone(),
+            Msg::Binary(ref sel, ref arg) => {
+                let arg = self.operand(arg, Prec::Unary, column + sel.0.len() + 1, depth);
+                format!("{} {}", sel, arg)
+            }
+            Msg::Kwargs(ref kws) => {
+                let mut out = String::new();
+                let mut start = column;
+                for (i, kw) in kws.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(&self.newline(depth));
+                        start = depth * self.config.indent;
+                    }
+                    out.push_str(&kw.id.0);
+                    out.push(' ');
+                    let start = start + kw.id.0.len() + 1;
+                    out.push_str(&self.operand(&kw.val, Prec::Binary, start, depth));
+                }
+                out
+            }
+        }
+    }
+
+    // Print `e`, in parentheses if it binds more loosely than `max`.
+    fn operand(&self, e: &Expr, max: Prec, column: usize, depth: usize) -> String {
+        if prec(e) > max {
+            format!("({})", self.expr(e, column + 1, depth))
+        } else {
+            self.expr(e, column, depth)
+        }
+    }
+}
+
+// The column reached after printing `s` from `column`.
+fn last_line_len(s: &str, column: usize) -> usize {
+    match s.rfind('\n') {
+        Some(i) => s.len() - i - 1,
+        None => column + s.len(),
+    }
+}
+
+// The block arguments and the bar after them, then the temporaries.
+fn block_header(args: &[Ident], temps: &[Ident]) -> String {
+    let mut parts = Vec::new();
+    if !args.is_empty() {
+        let args: Vec<_> = args.iter().map(|a| format!(":{}", a.0)).collect();
+        parts.push(format!("{} |", args.join(" ")));
+    }
+    if !temps.is_empty() {
+        let temps: Vec<_> = temps.iter().map(|t| &t.0[..]).collect();
+        parts.push(format!("| {} |", temps.join(" ")));
+    }
+    parts.join(" ")
+}
+
+// Print `e` on a single line.
+fn flat_expr(e: &Expr) -> String {
+    let mut out = String::new();
+    flat(&mut out, e);
+    out
+}
+
+fn flat(out: &mut String, e: &Expr) {
+    match *e {
+        Expr::Id(ref id) => out.push_str(&id.0),
+        Expr::Assign(ref id, ref val) => {
+            out.push_str(&id.0);
+            out.push_str(" <- ");
+            flat(out, val);
+        }
+        Expr::Lit(ref lit) => literal(out, lit, true),
+        Expr::Message { ref receiver, ref selector, .. } => {
+            flat_operand(out, receiver, receiver_prec(selector));
+            out.push(' ');
+            flat_message(out, selector);
+        }
+        Expr::Cascade { ref receiver, ref messages, .. } => {
+            flat_operand(out, receiver, messages.first().map_or(Prec::Primary, receiver_prec));
+            for (i, msg) in messages.iter().enumerate() {
+                out.push_str(if i > 0 { "; " } else { " " });
+                flat_message(out, msg);
+            }
+        }
+        Expr::Block { ref args, ref temps, ref statements, .. } => {
+            let header = block_header(args, temps);
+            out.push('[');
+            out.push_str(&header);
+            for (i, stmt) in statements.iter().enumerate() {
+                out.push_str(if i > 0 { ". " } else if header.is_empty() { "" } else { " " });
+                if let Statement::Ret(..) = *stmt {
+                    out.push('^');
+                }
+                match *stmt {
+                    Statement::E(ref e) | Statement::Ret(ref e, _) => flat(out, e),
+                }
+            }
+            out.push(']');
+        }
+        Expr::Brace { ref elements, .. } => {
+            out.push('{');
+            for (i, elem) in elements.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(". ");
+                }
+                flat(out, elem);
+            }
+            out.push('}');
+        }
+        Expr::Pseudo(var, _) => out.push_str(var.name()),
+        Expr::Method(ref m) => out.push_str(&print_method(m, &Config::default())),
+        // There is no source text for a statement which failed to parse, so
+        // it is printed as something no dialect reads, and parsing the output
+        // reports the error again instead of giving a different program.
+        Expr::Error(_) => out.push_str(ERROR),
+    }
+}
+
+// A message, without its receiver, on a single line.
+fn flat_message(out: &mut String, msg: &Msg) {
+    match *msg {
+        Msg::Unary(ref sel) => out.push_str(&sel.0),
+        Msg::Binary(ref sel, ref arg) => {
+            out.push_str(&sel.0);
+            out.push(' ');
+            flat_operand(out, arg, Prec::Unary);
+        }
+        Msg::Kwargs(ref kws) => {
+            for (i, kw) in kws.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                out.push_str(&kw.id.0);
+                out.push(' ');
+                flat_operand(out, &kw.val, Prec::Binary);
+            }
+        }
+    }
+}
+
+// The loosest binding receiver `msg` can be sent to without parentheses.
+// Binary messages are left associative, so `a + b + c` needs none.
+fn receiver_prec(msg: &Msg) -> Prec {
+    match *msg {
+        Msg::Unary(_) => Prec::Unary,
+        Msg::Binary(..) | Msg::Kwargs(_) => Prec::Binary,
+    }
+}
+
+fn flat_operand(out: &mut String, e: &Expr, max: Prec) {
+    if prec(e) > max {
+        out.push('(');
+        flat(out, e);
+        out.push(')');
+    } else {
+        flat(out, e);
+    }
+}
+
+fn pragma(out: &mut String, p: &Pragma) {
+    out.push('<');
+    if p.args.is_empty() {
+        out.push_str(&p.selector.0);
+    }
+    for (i, (keyword, arg)) in p.selector.parts().into_iter().zip(&p.args).enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        let _ = write!(out, "{} ", keyword);
+        literal(out, arg, true);
+    }
+    // Keep a binary selector symbol, such as `#+`, from running into the `>`.
+    if let Some(Literal::Symbol(s, _)) = p.args.last() {
+        if s.kind() == SelectorKind::Binary {
+            out.push(' ');
+        }
+    }
+    out.push('>');
+}
+
+// Literals inside a literal array leave out the `#` of symbols and arrays,
+// except for the symbols which would read as constants without it.
+fn literal(out: &mut String, lit: &Literal, top: bool) {
+    match *lit {
+        Literal::Number(ref n, _) => number(out, n),
+        Literal::Char(c, _) => {
+            out.push('$');
+            out.push(c);
+        }
+        Literal::Str(ref s, _) => string(out, s),
+        Literal::Symbol(ref s, _) => {
+            if top || matches!(s.as_str(), "true" | "false" | "nil") {
+                out.push('#');
+            }
+            out.push_str(&s.0);
+        }
+        Literal::Array(ref elems, _) => {
+            if top {
+                out.push('#');
+            }
+            out.push('(');
+            for (i, elem) in elems.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                literal(out, elem, false);
+            }
+            out.push(')');
+        }
+        Literal::ByteArray(ref bytes, _) => {
+            let bytes: Vec<_> = bytes.iter().map(u8::to_string).collect();
+            let _ = write!(out, "#[{}]", bytes.join(" "));
+        }
+        Literal::Bool(b, _) => out.push_str(if b { "true" } else { "false" }),
+        Literal::Nil(_) => out.push_str("nil"),
+    }
+}
+
+fn number(out: &mut String, n: &Num) {
+    if n.negative {
+        out.push('-');
+    }
+    if let Some(radix) = n.radix {
+        let _ = write!(out, "{}r", radix);
+    }
+    out.push_str(&n.integer);
+    if let Some(ref mantissa) = n.mantissa {
+        out.push('.');
+        out.push_str(mantissa);
+    }
+    if let Some(exponent) = n.exponent {
+        let _ = write!(out, "e{}", exponent);
+    }
+    if let Some(scale) = n.scale {
+        let _ = write!(out, "s{}", scale);
+    }
+}
+
+// Print `s` as a string literal, with embedded quotes doubled.
+pub(crate) fn string(out: &mut String, s: &str) {
+    out.push('\'');
+    out.push_str(&s.replace('\'', "''"));
+    out.push('\'');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::{parse_expr, parse_statements, parse_statements_recovering, Dialect, ParserConfig};
+
+    const METHODS: &[&str] = &[
+        "foo ^self",
+        "+ aNumber ^self plus: aNumber",
+        "at: i put: x \"Store x.\" | old | old <- self at: i. self basicAt: i put: x. ^old",
+        "test ^(a + b) c: (d e: f) g: h i + (j - k)",
+        "test ^a - (b - c) - d",
+        "test ^((a foo: b) bar) baz: (c qux: d)",
+        "test x <- y <- 3 + 4. ^x",
+        "test ^#(1 -2 $a 'it''s' foo + (3 4.5e-2) 16rFF 1.25s2)",
+        "test ^[:a :b | a < b. ^a] value: 1 value: [:c | c]",
+        "test ^3 - -4 abs negated max: -2r101",
+        "test ^(OrderedCollection new) add: 1 + 2; add: (a foo; bar); yourself",
+        "test x <- Transcript show: 'a' , 'b'; cr; + 3; tab. ^(x foo; bar) baz",
+        "+ x <primitive: 1> <category: #+ > ^#(true #true nil #[0 255]) , {x. thisContext}",
+        "-> x ^(self ==> x) ~~ #(at:put: #at:put: ->) -> #value:value:",
+        "test \"Make one.\" <foo> | a | ^{}",
+        "test ^[] value + [x foo] value + [:a | ] value + [| t | t <- 3. t] value",
+        "test ^#(1 2) inject: 0 into: [:sum :each | | t | t <- sum + each. ^t]",
+    ];
+
+    // The corpus uses syntax from later dialects, too, but assigns with `<-`
+    // as the printer does.
+    fn parse_method(src: &str) -> Result<Method, ::parser::SyntaxError> {
+        ParserConfig { left_arrow_assignment: true, ..Dialect::Pharo.config() }.parse_method(src)
+    }
+
+    fn round_trips(src: &str, config: &Config) {
+        let m = parse_method(src).unwrap();
+        let printed = print_method(&m, config);
+        assert_eq_ignoring_spans!(parse_method(&printed), Ok(m.clone()), "printed as:\n{}", printed);
+        let reprinted = print_method(&parse_method(&printed).unwrap(), config);
+        assert_eq!(reprinted, printed);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for src in METHODS{
+            round_trips(src, &Config::default());
+        }
+    }
+
+    #[test]
+    fn test_round_trip_narrow() {
+        for width in &[0, 10, 30] {
+            let config = Config { indent: 2, width: *width };
+            for src in METHODS{
+                round_trips(src, &config);
+            }
+        }
+    }
+
+    #[test]
+    fn test_minimal_parentheses() {
+        let cases = [
+            ("(a foo) bar", "a foo bar"),
+            ("(a + b) + c", "a + b + c"),
+            ("a + (b + c)", "a + (b + c)"),
+            ("(a + b) foo", "(a + b) foo"),
+            ("(a foo: b) bar: c", "(a foo: b) bar: c"),
+            ("a foo: (b bar) baz: (c + d)", "a foo: b bar baz: c + d"),
+            ("(x <- 3) + 4", "(x <- 3) + 4"),
+            ("(a foo) bar; baz", "a foo bar; baz"),
+            ("(a + b) + c; - d", "a + b + c; - d"),
+            ("(a foo: b) bar; baz", "(a foo: b) bar; baz"),
+            ("a foo: (b bar; baz)", "a foo: (b bar; baz)"),
+        ];
+        for &(src, printed) in &cases {
+            assert_eq!(print_expr(&parse_expr(src).unwrap(), &Config::default()), printed);
+        }
+    }
+
+    #[test]
+    fn test_breaks_long_lines() {
+        let src = "aCollection inject: 0 into: [:sum :each | sum + each size. sum * 2]";
+        let config = Config { indent: 2, width: 50 };
+        assert_eq!(
+            print_expr(&parse_expr(src).unwrap(), &config),
+            "aCollection
+  inject: 0
+  into: [:sum :each | sum + each size. sum * 2]"
+        );
+        let config = Config { indent: 2, width: 30 };
+        assert_eq!(
+            print_expr(&parse_expr(src).unwrap(), &config),
+            "aCollection
+  inject: 0
+  into: [:sum :each |
+    sum + each size.
+    sum * 2]"
+        );
+    }
+
+    #[test]
+    fn test_breaks_long_cascades() {
+        let src = "Transcript show: 'hello'; cr; show: 'world' with: 42; yourself";
+        let config = Config { indent: 2, width: 30 };
+        assert_eq!(
+            print_expr(&parse_expr(src).unwrap(), &config),
+            "Transcript
+  show: 'hello';
+  cr;
+  show: 'world' with: 42;
+  yourself"
+        );
+    }
+
+    #[test]
+    fn test_statements_and_display() {
+        let stmts = parse_statements("x <- 3.   y <- x + 1.  ^y").unwrap();
+        assert_eq!(print_statements(&stmts, &Config::default()), "x <- 3.\ny <- x + 1.\n^y");
+        assert_eq!(stmts[2].to_string(), "^y");
+        assert_eq!(parse_expr("a   foo:  #(1 $b)").unwrap().to_string(), "a foo: #(1 $b)");
+        assert_eq!(parse_method("foo ^1").unwrap().to_string(), "foo\n    ^1");
+    }
+
+    #[test]
+    fn test_errors_stay_errors() {
+        let (stmts, errors) = parse_statements_recovering("a foo. b + . ^d");
+        assert_eq!(errors.len(), 1);
+        let printed = print_statements(&stmts, &Config::default());
+        assert_eq!(printed, "a foo.\n`error`.\n^d");
+        let err = parse_statements(&printed).unwrap_err();
+        assert_eq!(err.unexpected, Some(String::from("`")));
+    }
+}
@@ -2,8 +2,60 @@
 
 /// The datatype representing valid syntax in Smoltok. Currently, we don't have
 /// a type for declarations.
+#[derive(Debug, PartialEq, Clone)]
 pub enum Syntax {
-    Expr(Expr)
+    Expr(Expr),
+    /// A program to run as it is, such as a workspace snippet or a line of
+    /// REPL input: temporaries, then statements, like a method body without
+    /// a message pattern.
+    DoIt { temps: Vec<Ident>, statements: Vec<Statement> },
+}
+
+/// A line and column in the source text. Both start counting at 1; the
+/// `Default` position of `0:0` marks a node that was not parsed from source.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default, Hash)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// The region of source text that produced a node. `start` is the position of
+/// the first character and `end` is the position just past the last one.
+///
+/// Spans take part in equality like any other field, so two trees are only
+/// `==` when they came from the same place. Use `SpanlessEq` to compare just
+/// their shape, as tests which build expected trees with `Span::default()`
+/// do.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Span { start, end }
+    }
+
+    /// The span running from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span { start: self.start, end: other.end }
+    }
+}
+
+/// Equality which treats every span as equal, for comparing syntax trees by
+/// their shape alone.
+///
+/// ```
+/// use smoltok::syntax::{mk_ident, Ident, Position, Span, SpanlessEq};
+///
+/// let at = |column| Position { line: 1, column };
+/// let parsed = Ident(String::from("hey"), Span::new(at(3), at(6)));
+/// assert!(parsed != mk_ident("hey"));
+/// assert!(parsed.spanless_eq(&mk_ident("hey")));
+/// ```
+pub trait SpanlessEq {
+    fn spanless_eq(&self, other: &Self) -> bool;
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -11,9 +63,142 @@ pub enum Expr {
     Id(Ident),
     Assign(Ident, Box<Expr>),
     Lit(Literal),
-    Message { receiver: Box<Expr>, selector: Msg },
-    Block { vars: Vec<Ident>, statements: Vec<Statement>},
+    Message { receiver: Box<Expr>, selector: Msg, span: Span },
+    /// Several messages sent to the same receiver, as in `a foo; bar`. The
+    /// receiver is evaluated once and each message is sent to it in turn.
+    Cascade { receiver: Box<Expr>, messages: Vec<Msg>, span: Span },
+    /// A block, as in `[:x | | t | t <- x. t]`. Both the arguments and the
+    /// temporaries may be empty.
+    Block { args: Vec<Ident>, temps: Vec<Ident>, statements: Vec<Statement>, span: Span },
+    /// An array built from the values of expressions, as in `{a. b + 1}`.
+    /// Only parsed when `ParserConfig::brace_arrays` is set.
+    Brace { elements: Vec<Expr>, span: Span },
+    /// A pseudo-variable, such as `self` or `nil`.
+    Pseudo(PseudoVar, Span),
     Method(Method),
+    /// Stands in for source text which failed to parse. Only the
+    /// error-recovering parser produces it.
+    Error(Span),
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match *self {
+            Expr::Id(ref id) => id.1,
+            Expr::Assign(ref id, ref val) => id.1.to(val.span()),
+            Expr::Lit(ref lit) => lit.span(),
+            Expr::Message { span, .. } => span,
+            Expr::Cascade { span, .. } => span,
+            Expr::Block { span, .. } => span,
+            Expr::Brace { span, .. } => span,
+            Expr::Pseudo(_, span) => span,
+            Expr::Method(ref m) => m.span,
+            Expr::Error(span) => span,
+        }
+    }
+}
+
+/// The names which the parser reads as values of their own rather than as
+/// variables. They cannot be assigned to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum PseudoVar {
+    /// `self`, the receiver of the current message.
+    Self_,
+    /// `super`, the receiver, with message lookup starting in the superclass
+    /// of the class the method is defined in.
+    Super,
+    True,
+    False,
+    Nil,
+    /// `thisContext`, the active context. Only a pseudo-variable when
+    /// `ParserConfig::this_context` is set; otherwise it names a variable.
+    ThisContext,
+}
+
+impl PseudoVar {
+    pub const ALL: [PseudoVar; 6] = [
+        PseudoVar::Self_,
+        PseudoVar::Super,
+        PseudoVar::True,
+        PseudoVar::False,
+        PseudoVar::Nil,
+        PseudoVar::ThisContext,
+    ];
+
+    /// The name the pseudo-variable is written with.
+    pub fn name(self) -> &'static str {
+        match self {
+            PseudoVar::Self_ => "self",
+            PseudoVar::Super => "super",
+            PseudoVar::True => "true",
+            PseudoVar::False => "false",
+            PseudoVar::Nil => "nil",
+            PseudoVar::ThisContext => "thisContext",
+        }
+    }
+
+    /// The pseudo-variable written as `name`, if any.
+    pub fn from_name(name: &str) -> Option<PseudoVar> {
+        PseudoVar::ALL.iter().cloned().find(|v| v.name() == name)
+    }
+}
+
+/// The name of a message, such as `size`, `+` or `at:put:`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+pub struct Selector(pub String);
+
+/// The three shapes of selector, which differ in how many arguments they
+/// take and how tightly they bind.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SelectorKind {
+    Unary,
+    Binary,
+    Keyword,
+}
+
+impl Selector {
+    pub fn new(name: &str) -> Self {
+        Selector(String::from(name))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Keyword selectors end with a `:`, unary selectors are identifiers,
+    /// and binary selectors are made of special characters.
+    pub fn kind(&self) -> SelectorKind {
+        match self.0.chars().next() {
+            Some(c) if c.is_alphabetic() && self.0.ends_with(':') => SelectorKind::Keyword,
+            Some(c) if c.is_alphabetic() => SelectorKind::Unary,
+            _ => SelectorKind::Binary,
+        }
+    }
+
+    /// The number of arguments a message with this selector takes.
+    pub fn arity(&self) -> usize {
+        match self.kind() {
+            SelectorKind::Unary => 0,
+            SelectorKind::Binary => 1,
+            SelectorKind::Keyword => self.0.matches(':').count(),
+        }
+    }
+
+    /// The keywords of a keyword selector, each with its colon, as in
+    /// `["at:", "put:"]`. Other selectors are a single part.
+    ///
+    /// ```
+    /// use smoltok::syntax::Selector;
+    ///
+    /// assert_eq!(Selector::new("at:put:").parts(), vec!["at:", "put:"]);
+    /// assert_eq!(Selector::new("->").parts(), vec!["->"]);
+    /// ```
+    pub fn parts(&self) -> Vec<&str> {
+        match self.kind() {
+            SelectorKind::Keyword => self.0.split_inclusive(':').collect(),
+            _ => vec![&self.0[..]],
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -23,6 +208,26 @@ pub enum MsgPat {
     Kwargs(Vec<KeyPat>)
 }
 
+impl MsgPat {
+    pub fn span(&self) -> Span {
+        match *self {
+            MsgPat::Unary(ref id) => id.1,
+            MsgPat::Bin(ref sel, ref var) => sel.1.to(var.1),
+            MsgPat::Kwargs(ref kws) => match (kws.first(), kws.last()) {
+                (Some(first), Some(last)) => first.keyword.1.to(last.var.1),
+                _ => Span::default(),
+            },
+        }
+    }
+
+    pub fn selector(&self) -> Selector {
+        match *self {
+            MsgPat::Unary(ref id) | MsgPat::Bin(ref id, _) => Selector(id.0.clone()),
+            MsgPat::Kwargs(ref kws) => Selector(kws.iter().map(|kw| &kw.keyword.0[..]).collect()),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct KeyPat {
     pub keyword: Ident,
@@ -32,16 +237,36 @@ pub struct KeyPat {
 #[derive(Debug, PartialEq, Clone)]
 pub enum Statement {
     E(Expr),
-    Ret(Expr)
+    /// A return. The span runs from the `^` to the end of the expression.
+    Ret(Expr, Span)
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match *self {
+            Statement::E(ref e) => e.span(),
+            Statement::Ret(_, span) => span,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Msg {
     Unary(Ident),
-    Binary(String, Box<Expr>),
+    Binary(Selector, Box<Expr>),
     Kwargs(Vec<Keyword>),
 }
 
+impl Msg {
+    pub fn selector(&self) -> Selector {
+        match *self {
+            Msg::Unary(ref id) => Selector(id.0.clone()),
+            Msg::Binary(ref sel, _) => sel.clone(),
+            Msg::Kwargs(ref kws) => Selector(kws.iter().map(|kw| &kw.id.0[..]).collect()),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Keyword {
     pub id: Ident,
@@ -50,22 +275,154 @@ pub struct Keyword {
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Literal {
-    Number(Num),
-    Char(char),
-    Str(String),
-    Symbol(String),
-    Array(Vec<Literal>),
+    Number(Num, Span),
+    Char(char, Span),
+    Str(String, Span),
+    Symbol(Selector, Span),
+    Array(Vec<Literal>, Span),
+    /// A byte array, as in `#[1 2 255]`.
+    ByteArray(Vec<u8>, Span),
+    /// `true` or `false` inside a literal array. The blue book reads these as
+    /// symbols; see `ParserConfig::literal_constants`.
+    Bool(bool, Span),
+    /// `nil` inside a literal array.
+    Nil(Span),
+}
+
+impl Literal {
+    pub fn span(&self) -> Span {
+        match *self {
+            Literal::Number(_, span) => span,
+            Literal::Char(_, span) => span,
+            Literal::Str(_, span) => span,
+            Literal::Symbol(_, span) => span,
+            Literal::Array(_, span) => span,
+            Literal::ByteArray(_, span) => span,
+            Literal::Bool(_, span) => span,
+            Literal::Nil(span) => span,
+        }
+    }
+
+    /// Replace the span of the literal, keeping its value.
+    pub fn with_span(self, span: Span) -> Literal {
+        match self {
+            Literal::Number(n, _) => Literal::Number(n, span),
+            Literal::Char(c, _) => Literal::Char(c, span),
+            Literal::Str(s, _) => Literal::Str(s, span),
+            Literal::Symbol(s, _) => Literal::Symbol(s, span),
+            Literal::Array(a, _) => Literal::Array(a, span),
+            Literal::ByteArray(b, _) => Literal::ByteArray(b, span),
+            Literal::Bool(b, _) => Literal::Bool(b, span),
+            Literal::Nil(_) => Literal::Nil(span),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Method {
     pub sig: MsgPat,
+    /// The comment directly after the message pattern, which by convention
+    /// says what the method does. Other comments are not kept.
+    pub comment: Option<String>,
+    /// The index of the primitive the method runs, from `<primitive: N>`.
+    /// The statements only run if the primitive fails.
+    pub primitive: Option<u32>,
+    /// Pragmas other than the primitive. Only parsed when
+    /// `ParserConfig::pragmas` is set.
+    pub pragmas: Vec<Pragma>,
     pub temps: Option<Vec<Ident>>,
     pub stmts: Option<Vec<Statement>>,
+    pub span: Span,
+}
+
+/// An annotation at the start of a method body, such as
+/// `<category: 'accessing'>`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Pragma {
+    /// The whole selector, such as `category:` or `primitive:module:`.
+    pub selector: Selector,
+    /// One literal for each keyword of the selector.
+    pub args: Vec<Literal>,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Clone)]
-pub struct Ident(pub String);
+pub struct Ident(pub String, pub Span);
+
+/// A chunk of a source file in the `!`-delimited fileIn format.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Chunk {
+    /// A class definition, along with the comment and methods given for the
+    /// class later in the same file.
+    Class(ClassDef),
+    /// A `!Foo methodsFor: 'category'!` section for a class which is not
+    /// defined in the same file.
+    Methods(MethodCategory),
+    /// Any other statements, and the temporaries they declare, which are
+    /// evaluated as the file is read in.
+    DoIt { temps: Vec<Ident>, statements: Vec<Statement>, span: Span },
+}
+
+/// A class definition message, such as
+/// `Object subclass: #Point instanceVariableNames: 'x y' ...`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ClassDef {
+    pub name: Ident,
+    /// `None` for a class whose superclass is `nil`.
+    pub superclass: Option<Ident>,
+    pub kind: ClassKind,
+    pub instance_vars: Vec<String>,
+    pub class_vars: Vec<String>,
+    /// Only the Blue Book form of the definition message lists pool
+    /// dictionaries; `None` when the message leaves them out.
+    pub pool_dictionaries: Option<Vec<String>>,
+    pub category: String,
+    /// From `Foo comment: '...'`.
+    pub comment: Option<String>,
+    /// From `Foo class instanceVariableNames: '...'`.
+    pub class_instance_vars: Vec<String>,
+    /// Instance and class side methods, in the order they were read.
+    pub categories: Vec<MethodCategory>,
+    pub span: Span,
+}
+
+/// The layout of the instances of a class, chosen by the definition message.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ClassKind {
+    /// `subclass:`
+    Fixed,
+    /// `variableSubclass:`, with indexed pointer fields.
+    Pointers,
+    /// `variableByteSubclass:`
+    Bytes,
+    /// `variableWordSubclass:`
+    Words,
+}
+
+impl ClassKind {
+    /// The keyword which starts the definition message.
+    pub fn keyword(self) -> &'static str {
+        match self {
+            ClassKind::Fixed => "subclass:",
+            ClassKind::Pointers => "variableSubclass:",
+            ClassKind::Bytes => "variableByteSubclass:",
+            ClassKind::Words => "variableWordSubclass:",
+        }
+    }
+}
+
+/// The methods of a `!Foo methodsFor: 'category'!` section.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MethodCategory {
+    pub class: Ident,
+    /// Whether the methods are for the metaclass, as in
+    /// `!Foo class methodsFor: 'instance creation'!`.
+    pub meta: bool,
+    pub name: String,
+    pub methods: Vec<Method>,
+    /// The span of the section header.
+    pub span: Span,
+}
 
 /// Create an Expr from a string.
 ///
@@ -75,7 +432,7 @@ pub struct Ident(pub String);
 /// use smoltok::syntax::*;
 /// assert_eq!(
 ///     mk_ident_expr("hey"),
-///     Expr::Id(Ident(String::from("hey")))
+///     Expr::Id(Ident(String::from("hey"), Span::default()))
 /// );
 /// ```
 pub fn mk_ident_expr(s: &str) -> Expr {
@@ -83,11 +440,14 @@ pub fn mk_ident_expr(s: &str) -> Expr {
 }
 
 pub fn mk_ident(s: &str) -> Ident {
-    Ident(String::from(s))
+    Ident(String::from(s), Span::default())
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Num {
+    /// Whether the number was written with a `-`, either in front of it as in
+    /// `-16rFF` or after the radix as in `16r-FF`.
+    pub negative: bool,
     /// Smalltalk numbers can include an optional radix to specify the base of
     /// the number. This is given as as `Nr` where `N` is the base.
     pub radix: Option<u8>,
@@ -97,23 +457,1026 @@ pub struct Num {
     /// For floating point numbers, the mantissa may be represented as `.N`,
     /// where `N` is some number permitted by the given base.
     pub mantissa: Option<String>,
-    /// Finally, the exponent is available as `eN` where `N` is some number
-    /// permitted by the given base.
-    pub exponent: Option<u32>,
+    /// The exponent is available as `eN` or `e-N`, where `N` is written in
+    /// decimal. It scales the number by a power of the radix.
+    pub exponent: Option<i32>,
+    /// Finally, a ScaledDecimal is written with a trailing `sN`, where `N` is
+    /// the number of decimal places to keep. A bare `s` keeps as many places
+    /// as the mantissa has digits.
+    pub scale: Option<u32>,
 }
 
 impl Num {
     /// Convenient alias for creating a base 10 integral number from a string.
     pub fn int_from_str(s: &str) -> Self {
         Num {
+            negative: false,
             integer: String::from(s),
             radix: None,
             mantissa: None,
-            exponent: None
+            exponent: None,
+            scale: None,
         }
     }
 
     pub fn to_expr(self) -> Expr {
-        Expr::Lit(Literal::Number(self))
+        Expr::Lit(Literal::Number(self, Span::default()))
+    }
+}
+
+impl SpanlessEq for Span {
+    fn spanless_eq(&self, _: &Span) -> bool {
+        true
+    }
+}
+
+// Values without spans in them compare as they always do.
+macro_rules! spanless_eq_by_eq {
+    ($($t:ty),*) => {
+        $(impl SpanlessEq for $t {
+            fn spanless_eq(&self, other: &Self) -> bool {
+                self == other
+            }
+        })*
+    };
+}
+
+spanless_eq_by_eq!(str);
+
+impl<T: SpanlessEq + ?Sized> SpanlessEq for &T {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        (**self).spanless_eq(*other)
+    }
+}
+
+impl<T: SpanlessEq + ?Sized> SpanlessEq for Box<T> {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        (**self).spanless_eq(&**other)
+    }
+}
+
+impl<T: SpanlessEq> SpanlessEq for [T] {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().zip(other).all(|(a, b)| a.spanless_eq(b))
+    }
+}
+
+impl<T: SpanlessEq> SpanlessEq for Vec<T> {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        self[..].spanless_eq(&other[..])
+    }
+}
+
+impl<T: SpanlessEq> SpanlessEq for Option<T> {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Some(a), Some(b)) => a.spanless_eq(b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+// Errors carry positions rather than spans, so they compare with `==`.
+impl<T: SpanlessEq, E: PartialEq> SpanlessEq for Result<T, E> {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Ok(a), Ok(b)) => a.spanless_eq(b),
+            (Err(a), Err(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl<A: SpanlessEq, B: SpanlessEq> SpanlessEq for (A, B) {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        self.0.spanless_eq(&other.0) && self.1.spanless_eq(&other.1)
+    }
+}
+
+impl SpanlessEq for Syntax {
+    fn spanless_eq(&self, other: &Syntax) -> bool {
+        match (self, other) {
+            (Syntax::Expr(a), Syntax::Expr(b)) => a.spanless_eq(b),
+            (
+                Syntax::DoIt { temps: t1, statements: s1 },
+                Syntax::DoIt { temps: t2, statements: s2 },
+            ) => t1.spanless_eq(t2) && s1.spanless_eq(s2),
+            _ => false,
+        }
+    }
+}
+
+impl SpanlessEq for Expr {
+    fn spanless_eq(&self, other: &Expr) -> bool {
+        match (self, other) {
+            (Expr::Id(a), Expr::Id(b)) => a.spanless_eq(b),
+            (Expr::Assign(v1, e1), Expr::Assign(v2, e2)) => {
+                v1.spanless_eq(v2) && e1.spanless_eq(e2)
+            }
+            (Expr::Lit(a), Expr::Lit(b)) => a.spanless_eq(b),
+            (
+                Expr::Message { receiver: r1, selector: m1, span: _ },
+                Expr::Message { receiver: r2, selector: m2, span: _ },
+            ) => r1.spanless_eq(r2) && m1.spanless_eq(m2),
+            (
+                Expr::Cascade { receiver: r1, messages: m1, span: _ },
+                Expr::Cascade { receiver: r2, messages: m2, span: _ },
+            ) => r1.spanless_eq(r2) && m1.spanless_eq(m2),
+            (
+                Expr::Block { args: a1, temps: t1, statements: s1, span: _ },
+                Expr::Block { args: a2, temps: t2, statements: s2, span: _ },
+            ) => a1.spanless_eq(a2) && t1.spanless_eq(t2) && s1.spanless_eq(s2),
+            (Expr::Brace { elements: e1, span: _ }, Expr::Brace { elements: e2, span: _ }) => {
+                e1.spanless_eq(e2)
+            }
+            (Expr::Pseudo(a, _), Expr::Pseudo(b, _)) => a == b,
+            (Expr::Method(a), Expr::Method(b)) => a.spanless_eq(b),
+            (Expr::Error(_), Expr::Error(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+impl SpanlessEq for MsgPat {
+    fn spanless_eq(&self, other: &MsgPat) -> bool {
+        match (self, other) {
+            (MsgPat::Unary(a), MsgPat::Unary(b)) => a.spanless_eq(b),
+            (MsgPat::Bin(s1, v1), MsgPat::Bin(s2, v2)) => s1.spanless_eq(s2) && v1.spanless_eq(v2),
+            (MsgPat::Kwargs(a), MsgPat::Kwargs(b)) => a.spanless_eq(b),
+            _ => false,
+        }
+    }
+}
+
+impl SpanlessEq for KeyPat {
+    fn spanless_eq(&self, other: &KeyPat) -> bool {
+        let KeyPat { ref keyword, ref var } = *self;
+        keyword.spanless_eq(&other.keyword) && var.spanless_eq(&other.var)
+    }
+}
+
+impl SpanlessEq for Statement {
+    fn spanless_eq(&self, other: &Statement) -> bool {
+        match (self, other) {
+            (Statement::E(a), Statement::E(b)) => a.spanless_eq(b),
+            (Statement::Ret(a, _), Statement::Ret(b, _)) => a.spanless_eq(b),
+            _ => false,
+        }
+    }
+}
+
+impl SpanlessEq for Msg {
+    fn spanless_eq(&self, other: &Msg) -> bool {
+        match (self, other) {
+            (Msg::Unary(a), Msg::Unary(b)) => a.spanless_eq(b),
+            (Msg::Binary(s1, e1), Msg::Binary(s2, e2)) => s1 == s2 && e1.spanless_eq(e2),
+            (Msg::Kwargs(a), Msg::Kwargs(b)) => a.spanless_eq(b),
+            _ => false,
+        }
+    }
+}
+
+impl SpanlessEq for Keyword {
+    fn spanless_eq(&self, other: &Keyword) -> bool {
+        let Keyword { ref id, ref val } = *self;
+        id.spanless_eq(&other.id) && val.spanless_eq(&other.val)
+    }
+}
+
+impl SpanlessEq for Literal {
+    fn spanless_eq(&self, other: &Literal) -> bool {
+        match (self, other) {
+            (Literal::Number(a, _), Literal::Number(b, _)) => a == b,
+            (Literal::Char(a, _), Literal::Char(b, _)) => a == b,
+            (Literal::Str(a, _), Literal::Str(b, _)) => a == b,
+            (Literal::Symbol(a, _), Literal::Symbol(b, _)) => a == b,
+            (Literal::Array(a, _), Literal::Array(b, _)) => a.spanless_eq(b),
+            (Literal::ByteArray(a, _), Literal::ByteArray(b, _)) => a == b,
+            (Literal::Bool(a, _), Literal::Bool(b, _)) => a == b,
+            (Literal::Nil(_), Literal::Nil(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+impl SpanlessEq for Method {
+    fn spanless_eq(&self, other: &Method) -> bool {
+        let Method {
+            ref sig,
+            ref comment,
+            primitive,
+            ref pragmas,
+            ref temps,
+            ref stmts,
+            span: _,
+        } = *self;
+        sig.spanless_eq(&other.sig)
+            && *comment == other.comment
+            && primitive == other.primitive
+            && pragmas.spanless_eq(&other.pragmas)
+            && temps.spanless_eq(&other.temps)
+            && stmts.spanless_eq(&other.stmts)
+    }
+}
+
+impl SpanlessEq for Pragma {
+    fn spanless_eq(&self, other: &Pragma) -> bool {
+        let Pragma { ref selector, ref args, span: _ } = *self;
+        *selector == other.selector && args.spanless_eq(&other.args)
+    }
+}
+
+impl SpanlessEq for Ident {
+    fn spanless_eq(&self, other: &Ident) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl SpanlessEq for Chunk {
+    fn spanless_eq(&self, other: &Chunk) -> bool {
+        match (self, other) {
+            (Chunk::Class(a), Chunk::Class(b)) => a.spanless_eq(b),
+            (Chunk::Methods(a), Chunk::Methods(b)) => a.spanless_eq(b),
+            (
+                Chunk::DoIt { temps: t1, statements: s1, span: _ },
+                Chunk::DoIt { temps: t2, statements: s2, span: _ },
+            ) => t1.spanless_eq(t2) && s1.spanless_eq(s2),
+            _ => false,
+        }
+    }
+}
+
+impl SpanlessEq for ClassDef {
+    fn spanless_eq(&self, other: &ClassDef) -> bool {
+        let ClassDef {
+            ref name,
+            ref superclass,
+            kind,
+            ref instance_vars,
+            ref class_vars,
+            ref pool_dictionaries,
+            ref category,
+            ref comment,
+            ref class_instance_vars,
+            ref categories,
+            span: _,
+        } = *self;
+        name.spanless_eq(&other.name)
+            && superclass.spanless_eq(&other.superclass)
+            && kind == other.kind
+            && *instance_vars == other.instance_vars
+            && *class_vars == other.class_vars
+            && *pool_dictionaries == other.pool_dictionaries
+            && *category == other.category
+            && *comment == other.comment
+            && *class_instance_vars == other.class_instance_vars
+            && categories.spanless_eq(&other.categories)
+    }
+}
+
+impl SpanlessEq for MethodCategory {
+    fn spanless_eq(&self, other: &MethodCategory) -> bool {
+        let MethodCategory { ref class, meta, ref name, ref methods, span: _ } = *self;
+        class.spanless_eq(&other.class)
+            && meta == other.meta
+            && *name == other.name
+            && methods.spanless_eq(&other.methods)
+    }
+}
+
+/// A read-only traversal of the syntax tree.
+///
+/// Each `visit_` method has a default which walks into the children of the
+/// node by calling the `walk_` function of the same name, so an
+/// implementation only overrides the nodes it cares about. An overriding
+/// method which still wants the children visited calls the `walk_` function
+/// itself, before or after its own work.
+///
+/// `visit_ident` sees every identifier naming a variable: references,
+/// assignment targets, and the arguments and temporaries which declare them.
+/// The identifiers naming selectors are part of `Msg`, `MsgPat` and
+/// `Keyword` instead.
+///
+/// ```
+/// use smoltok::parser::parse_expr;
+/// use smoltok::syntax::{walk_expr, Expr, Visitor};
+///
+/// struct Sends(usize);
+///
+/// impl Visitor for Sends {
+///     fn visit_expr(&mut self, e: &Expr) {
+///         if let Expr::Message { .. } = *e {
+///             self.0 += 1;
+///         }
+///         walk_expr(self, e);
+///     }
+/// }
+///
+/// let mut sends = Sends(0);
+/// sends.visit_expr(&parse_expr("(a foo: b size) + 1").unwrap());
+/// assert_eq!(sends.0, 3);
+/// ```
+pub trait Visitor {
+    fn visit_syntax(&mut self, s: &Syntax) {
+        walk_syntax(self, s)
+    }
+
+    fn visit_chunk(&mut self, c: &Chunk) {
+        walk_chunk(self, c)
+    }
+
+    fn visit_class_def(&mut self, c: &ClassDef) {
+        walk_class_def(self, c)
+    }
+
+    fn visit_method_category(&mut self, c: &MethodCategory) {
+        walk_method_category(self, c)
+    }
+
+    fn visit_method(&mut self, m: &Method) {
+        walk_method(self, m)
+    }
+
+    fn visit_msg_pat(&mut self, p: &MsgPat) {
+        walk_msg_pat(self, p)
+    }
+
+    fn visit_key_pat(&mut self, p: &KeyPat) {
+        walk_key_pat(self, p)
+    }
+
+    fn visit_pragma(&mut self, p: &Pragma) {
+        walk_pragma(self, p)
+    }
+
+    fn visit_statement(&mut self, s: &Statement) {
+        walk_statement(self, s)
+    }
+
+    fn visit_expr(&mut self, e: &Expr) {
+        walk_expr(self, e)
+    }
+
+    fn visit_msg(&mut self, m: &Msg) {
+        walk_msg(self, m)
+    }
+
+    fn visit_keyword(&mut self, k: &Keyword) {
+        walk_keyword(self, k)
+    }
+
+    fn visit_literal(&mut self, l: &Literal) {
+        walk_literal(self, l)
+    }
+
+    fn visit_ident(&mut self, _: &Ident) {}
+}
+
+pub fn walk_syntax<V: Visitor + ?Sized>(v: &mut V, s: &Syntax) {
+    match *s {
+        Syntax::Expr(ref e) => v.visit_expr(e),
+        Syntax::DoIt { ref temps, ref statements } => {
+            for t in temps {
+                v.visit_ident(t);
+            }
+            for s in statements {
+                v.visit_statement(s);
+            }
+        }
+    }
+}
+
+pub fn walk_chunk<V: Visitor + ?Sized>(v: &mut V, c: &Chunk) {
+    match *c {
+        Chunk::Class(ref class) => v.visit_class_def(class),
+        Chunk::Methods(ref category) => v.visit_method_category(category),
+        Chunk::DoIt { ref temps, ref statements, .. } => {
+            for t in temps {
+                v.visit_ident(t);
+            }
+            for s in statements {
+                v.visit_statement(s);
+            }
+        }
+    }
+}
+
+pub fn walk_class_def<V: Visitor + ?Sized>(v: &mut V, c: &ClassDef) {
+    for category in &c.categories {
+        v.visit_method_category(category);
+    }
+}
+
+pub fn walk_method_category<V: Visitor + ?Sized>(v: &mut V, c: &MethodCategory) {
+    for m in &c.methods {
+        v.visit_method(m);
+    }
+}
+
+pub fn walk_method<V: Visitor + ?Sized>(v: &mut V, m: &Method) {
+    v.visit_msg_pat(&m.sig);
+    for p in &m.pragmas {
+        v.visit_pragma(p);
+    }
+    for t in m.temps.iter().flatten() {
+        v.visit_ident(t);
+    }
+    for s in m.stmts.iter().flatten() {
+        v.visit_statement(s);
+    }
+}
+
+pub fn walk_msg_pat<V: Visitor + ?Sized>(v: &mut V, p: &MsgPat) {
+    match *p {
+        MsgPat::Unary(_) => (),
+        MsgPat::Bin(_, ref var) => v.visit_ident(var),
+        MsgPat::Kwargs(ref kws) => for kw in kws {
+            v.visit_key_pat(kw);
+        },
+    }
+}
+
+pub fn walk_key_pat<V: Visitor + ?Sized>(v: &mut V, p: &KeyPat) {
+    v.visit_ident(&p.var)
+}
+
+pub fn walk_pragma<V: Visitor + ?Sized>(v: &mut V, p: &Pragma) {
+    for arg in &p.args {
+        v.visit_literal(arg);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(v: &mut V, s: &Statement) {
+    match *s {
+        Statement::E(ref e) | Statement::Ret(ref e, _) => v.visit_expr(e),
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(v: &mut V, e: &Expr) {
+    match *e {
+        Expr::Id(ref id) => v.visit_ident(id),
+        Expr::Assign(ref id, ref val) => {
+            v.visit_ident(id);
+            v.visit_expr(val);
+        }
+        Expr::Lit(ref lit) => v.visit_literal(lit),
+        Expr::Message { ref receiver, ref selector, .. } => {
+            v.visit_expr(receiver);
+            v.visit_msg(selector);
+        }
+        Expr::Cascade { ref receiver, ref messages, .. } => {
+            v.visit_expr(receiver);
+            for m in messages {
+                v.visit_msg(m);
+            }
+        }
+        Expr::Block { ref args, ref temps, ref statements, .. } => {
+            for id in args.iter().chain(temps) {
+                v.visit_ident(id);
+            }
+            for s in statements {
+                v.visit_statement(s);
+            }
+        }
+        Expr::Brace { ref elements, .. } => for e in elements {
+            v.visit_expr(e);
+        },
+        Expr::Method(ref m) => v.visit_method(m),
+        Expr::Pseudo(..) | Expr::Error(_) => (),
+    }
+}
+
+pub fn walk_msg<V: Visitor + ?Sized>(v: &mut V, m: &Msg) {
+    match *m {
+        Msg::Unary(_) => (),
+        Msg::Binary(_, ref arg) => v.visit_expr(arg),
+        Msg::Kwargs(ref kws) => for kw in kws {
+            v.visit_keyword(kw);
+        },
+    }
+}
+
+pub fn walk_keyword<V: Visitor + ?Sized>(v: &mut V, k: &Keyword) {
+    v.visit_expr(&k.val)
+}
+
+pub fn walk_literal<V: Visitor + ?Sized>(v: &mut V, l: &Literal) {
+    if let Literal::Array(ref elems, _) = *l {
+        for lit in elems {
+            v.visit_literal(lit);
+        }
+    }
+}
+
+/// Like `Visitor`, but with mutable access to the nodes, so that they can be
+/// changed in place. The `walk_*_mut` functions walk into the children.
+///
+/// ```
+/// use smoltok::parser::parse_statements;
+/// use smoltok::syntax::{Ident, SpanlessEq, Statement, VisitorMut};
+///
+/// struct Rename;
+///
+/// impl VisitorMut for Rename {
+///     fn visit_ident_mut(&mut self, id: &mut Ident) {
+///         if id.0 == "x" {
+///             id.0 = String::from("count");
+///         }
+///     }
+/// }
+///
+/// let mut stmts = parse_statements("x <- x + 1. [:y | x + y]").unwrap();
+/// for s in &mut stmts {
+///     Rename.visit_statement_mut(s);
+/// }
+/// assert!(stmts.spanless_eq(&parse_statements("count <- count + 1. [:y | count + y]").unwrap()));
+/// ```
+pub trait VisitorMut {
+    fn visit_syntax_mut(&mut self, s: &mut Syntax) {
+        walk_syntax_mut(self, s)
+    }
+
+    fn visit_chunk_mut(&mut self, c: &mut Chunk) {
+        walk_chunk_mut(self, c)
+    }
+
+    fn visit_class_def_mut(&mut self, c: &mut ClassDef) {
+        walk_class_def_mut(self, c)
+    }
+
+    fn visit_method_category_mut(&mut self, c: &mut MethodCategory) {
+        walk_method_category_mut(self, c)
+    }
+
+    fn visit_method_mut(&mut self, m: &mut Method) {
+        walk_method_mut(self, m)
+    }
+
+    fn visit_msg_pat_mut(&mut self, p: &mut MsgPat) {
+        walk_msg_pat_mut(self, p)
+    }
+
+    fn visit_key_pat_mut(&mut self, p: &mut KeyPat) {
+        walk_key_pat_mut(self, p)
+    }
+
+    fn visit_pragma_mut(&mut self, p: &mut Pragma) {
+        walk_pragma_mut(self, p)
+    }
+
+    fn visit_statement_mut(&mut self, s: &mut Statement) {
+        walk_statement_mut(self, s)
+    }
+
+    fn visit_expr_mut(&mut self, e: &mut Expr) {
+        walk_expr_mut(self, e)
+    }
+
+    fn visit_msg_mut(&mut self, m: &mut Msg) {
+        walk_msg_mut(self, m)
+    }
+
+    fn visit_keyword_mut(&mut self, k: &mut Keyword) {
+        walk_keyword_mut(self, k)
+    }
+
+    fn visit_literal_mut(&mut self, l: &mut Literal) {
+        walk_literal_mut(self, l)
+    }
+
+    fn visit_ident_mut(&mut self, _: &mut Ident) {}
+}
+
+pub fn walk_syntax_mut<V: VisitorMut + ?Sized>(v: &mut V, s: &mut Syntax) {
+    match *s {
+        Syntax::Expr(ref mut e) => v.visit_expr_mut(e),
+        Syntax::DoIt { ref mut temps, ref mut statements } => {
+            for t in temps {
+                v.visit_ident_mut(t);
+            }
+            for s in statements {
+                v.visit_statement_mut(s);
+            }
+        }
+    }
+}
+
+pub fn walk_chunk_mut<V: VisitorMut + ?Sized>(v: &mut V, c: &mut Chunk) {
+    match *c {
+        Chunk::Class(ref mut class) => v.visit_class_def_mut(class),
+        Chunk::Methods(ref mut category) => v.visit_method_category_mut(category),
+        Chunk::DoIt { ref mut temps, ref mut statements, .. } => {
+            for t in temps {
+                v.visit_ident_mut(t);
+            }
+            for s in statements {
+                v.visit_statement_mut(s);
+            }
+        }
+    }
+}
+
+pub fn walk_class_def_mut<V: VisitorMut + ?Sized>(v: &mut V, c: &mut ClassDef) {
+    for category in &mut c.categories {
+        v.visit_method_category_mut(category);
+    }
+}
+
+pub fn walk_method_category_mut<V: VisitorMut + ?Sized>(v: &mut V, c: &mut MethodCategory) {
+    for m in &mut c.methods {
+        v.visit_method_mut(m);
+    }
+}
+
+pub fn walk_method_mut<V: VisitorMut + ?Sized>(v: &mut V, m: &mut Method) {
+    v.visit_msg_pat_mut(&mut m.sig);
+    for p in &mut m.pragmas {
+        v.visit_pragma_mut(p);
+    }
+    for t in m.temps.iter_mut().flatten() {
+        v.visit_ident_mut(t);
+    }
+    for s in m.stmts.iter_mut().flatten() {
+        v.visit_statement_mut(s);
+    }
+}
+
+pub fn walk_msg_pat_mut<V: VisitorMut + ?Sized>(v: &mut V, p: &mut MsgPat) {
+    match *p {
+        MsgPat::Unary(_) => (),
+        MsgPat::Bin(_, ref mut var) => v.visit_ident_mut(var),
+        MsgPat::Kwargs(ref mut kws) => for kw in kws {
+            v.visit_key_pat_mut(kw);
+        },
+    }
+}
+
+pub fn walk_key_pat_mut<V: VisitorMut + ?Sized>(v: &mut V, p: &mut KeyPat) {
+    v.visit_ident_mut(&mut p.var)
+}
+
+pub fn walk_pragma_mut<V: VisitorMut + ?Sized>(v: &mut V, p: &mut Pragma) {
+    for arg in &mut p.args {
+        v.visit_literal_mut(arg);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(v: &mut V, s: &mut Statement) {
+    match *s {
+        Statement::E(ref mut e) | Statement::Ret(ref mut e, _) => v.visit_expr_mut(e),
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(v: &mut V, e: &mut Expr) {
+    match *e {
+        Expr::Id(ref mut id) => v.visit_ident_mut(id),
+        Expr::Assign(ref mut id, ref mut val) => {
+            v.visit_ident_mut(id);
+            v.visit_expr_mut(val);
+        }
+        Expr::Lit(ref mut lit) => v.visit_literal_mut(lit),
+        Expr::Message { ref mut receiver, ref mut selector, .. } => {
+            v.visit_expr_mut(receiver);
+            v.visit_msg_mut(selector);
+        }
+        Expr::Cascade { ref mut receiver, ref mut messages, .. } => {
+            v.visit_expr_mut(receiver);
+            for m in messages {
+                v.visit_msg_mut(m);
+            }
+        }
+        Expr::Block { ref mut args, ref mut temps, ref mut statements, .. } => {
+            for id in args.iter_mut().chain(temps) {
+                v.visit_ident_mut(id);
+            }
+            for s in statements {
+                v.visit_statement_mut(s);
+            }
+        }
+        Expr::Brace { ref mut elements, .. } => for e in elements {
+            v.visit_expr_mut(e);
+        },
+        Expr::Method(ref mut m) => v.visit_method_mut(m),
+        Expr::Pseudo(..) | Expr::Error(_) => (),
+    }
+}
+
+pub fn walk_msg_mut<V: VisitorMut + ?Sized>(v: &mut V, m: &mut Msg) {
+    match *m {
+        Msg::Unary(_) => (),
+        Msg::Binary(_, ref mut arg) => v.visit_expr_mut(arg),
+        Msg::Kwargs(ref mut kws) => for kw in kws {
+            v.visit_keyword_mut(kw);
+        },
+    }
+}
+
+pub fn walk_keyword_mut<V: VisitorMut + ?Sized>(v: &mut V, k: &mut Keyword) {
+    v.visit_expr_mut(&mut k.val)
+}
+
+pub fn walk_literal_mut<V: VisitorMut + ?Sized>(v: &mut V, l: &mut Literal) {
+    if let Literal::Array(ref mut elems, _) = *l {
+        for lit in elems {
+            v.visit_literal_mut(lit);
+        }
+    }
+}
+
+/// A traversal which takes the syntax tree apart and builds a new one, for
+/// rewrites which change the kind of a node, such as replacing a message
+/// send with the literal it evaluates to.
+///
+/// Each `fold_` method defaults to the free function of the same name, which
+/// folds the children and rebuilds the node around them, keeping its span.
+///
+/// ```
+/// use smoltok::parser::parse_expr;
+/// use smoltok::syntax::{fold_expr, Expr, Fold, Literal, Num, Span};
+///
+/// // Replace every variable with the number 0.
+/// struct Zero;
+///
+/// impl Fold for Zero {
+///     fn fold_expr(&mut self, e: Expr) -> Expr {
+///         match e {
+///             Expr::Id(id) => Expr::Lit(Literal::Number(Num::int_from_str("0"), id.1)),
+///             e => fold_expr(self, e),
+///         }
+///     }
+/// }
+///
+/// let e = Zero.fold_expr(parse_expr("a + (b max: 3)").unwrap());
+/// assert_eq!(e, parse_expr("0 + (0 max: 3)").unwrap());
+/// ```
+pub trait Fold {
+    fn fold_syntax(&mut self, s: Syntax) -> Syntax {
+        fold_syntax(self, s)
+    }
+
+    fn fold_chunk(&mut self, c: Chunk) -> Chunk {
+        fold_chunk(self, c)
+    }
+
+    fn fold_class_def(&mut self, c: ClassDef) -> ClassDef {
+        fold_class_def(self, c)
+    }
+
+    fn fold_method_category(&mut self, c: MethodCategory) -> MethodCategory {
+        fold_method_category(self, c)
+    }
+
+    fn fold_method(&mut self, m: Method) -> Method {
+        fold_method(self, m)
+    }
+
+    fn fold_msg_pat(&mut self, p: MsgPat) -> MsgPat {
+        fold_msg_pat(self, p)
+    }
+
+    fn fold_key_pat(&mut self, p: KeyPat) -> KeyPat {
+        fold_key_pat(self, p)
+    }
+
+    fn fold_pragma(&mut self, p: Pragma) -> Pragma {
+        fold_pragma(self, p)
+    }
+
+    fn fold_statement(&mut self, s: Statement) -> Statement {
+        fold_statement(self, s)
+    }
+
+    fn fold_expr(&mut self, e: Expr) -> Expr {
+        fold_expr(self, e)
+    }
+
+    fn fold_msg(&mut self, m: Msg) -> Msg {
+        fold_msg(self, m)
+    }
+
+    fn fold_keyword(&mut self, k: Keyword) -> Keyword {
+        fold_keyword(self, k)
+    }
+
+    fn fold_literal(&mut self, l: Literal) -> Literal {
+        fold_literal(self, l)
+    }
+
+    fn fold_ident(&mut self, id: Ident) -> Ident {
+        id
+    }
+}
+
+fn fold_idents<F: Fold + ?Sized>(f: &mut F, ids: Vec<Ident>) -> Vec<Ident> {
+    ids.into_iter().map(|id| f.fold_ident(id)).collect()
+}
+
+fn fold_statements<F: Fold + ?Sized>(f: &mut F, stmts: Vec<Statement>) -> Vec<Statement> {
+    stmts.into_iter().map(|s| f.fold_statement(s)).collect()
+}
+
+pub fn fold_syntax<F: Fold + ?Sized>(f: &mut F, s: Syntax) -> Syntax {
+    match s {
+        Syntax::Expr(e) => Syntax::Expr(f.fold_expr(e)),
+        Syntax::DoIt { temps, statements } => Syntax::DoIt {
+            temps: fold_idents(f, temps),
+            statements: fold_statements(f, statements),
+        },
+    }
+}
+
+pub fn fold_chunk<F: Fold + ?Sized>(f: &mut F, c: Chunk) -> Chunk {
+    match c {
+        Chunk::Class(class) => Chunk::Class(f.fold_class_def(class)),
+        Chunk::Methods(category) => Chunk::Methods(f.fold_method_category(category)),
+        Chunk::DoIt { temps, statements, span } => Chunk::DoIt {
+            temps: fold_idents(f, temps),
+            statements: fold_statements(f, statements),
+            span,
+        },
+    }
+}
+
+pub fn fold_class_def<F: Fold + ?Sized>(f: &mut F, c: ClassDef) -> ClassDef {
+    let categories = c.categories.into_iter().map(|c| f.fold_method_category(c)).collect();
+    ClassDef { categories, ..c }
+}
+
+pub fn fold_method_category<F: Fold + ?Sized>(f: &mut F, c: MethodCategory) -> MethodCategory {
+    let methods = c.methods.into_iter().map(|m| f.fold_method(m)).collect();
+    MethodCategory { methods, ..c }
+}
+
+pub fn fold_method<F: Fold + ?Sized>(f: &mut F, m: Method) -> Method {
+    Method {
+        sig: f.fold_msg_pat(m.sig),
+        comment: m.comment,
+        primitive: m.primitive,
+        pragmas: m.pragmas.into_iter().map(|p| f.fold_pragma(p)).collect(),
+        temps: m.temps.map(|t| fold_idents(f, t)),
+        stmts: m.stmts.map(|s| fold_statements(f, s)),
+        span: m.span,
+    }
+}
+
+pub fn fold_msg_pat<F: Fold + ?Sized>(f: &mut F, p: MsgPat) -> MsgPat {
+    match p {
+        MsgPat::Unary(sel) => MsgPat::Unary(sel),
+        MsgPat::Bin(sel, var) => MsgPat::Bin(sel, f.fold_ident(var)),
+        MsgPat::Kwargs(kws) => MsgPat::Kwargs(kws.into_iter().map(|kw| f.fold_key_pat(kw)).collect()),
+    }
+}
+
+pub fn fold_key_pat<F: Fold + ?Sized>(f: &mut F, p: KeyPat) -> KeyPat {
+    KeyPat { keyword: p.keyword, var: f.fold_ident(p.var) }
+}
+
+pub fn fold_pragma<F: Fold + ?Sized>(f: &mut F, p: Pragma) -> Pragma {
+    let args = p.args.into_iter().map(|a| f.fold_literal(a)).collect();
+    Pragma { args, ..p }
+}
+
+pub fn fold_statement<F: Fold + ?Sized>(f: &mut F, s: Statement) -> Statement {
+    match s {
+        Statement::E(e) => Statement::E(f.fold_expr(e)),
+        Statement::Ret(e, span) => Statement::Ret(f.fold_expr(e), span),
+    }
+}
+
+pub fn fold_expr<F: Fold + ?Sized>(f: &mut F, e: Expr) -> Expr {
+    match e {
+        Expr::Id(id) => Expr::Id(f.fold_ident(id)),
+        Expr::Assign(id, val) => Expr::Assign(f.fold_ident(id), Box::new(f.fold_expr(*val))),
+        Expr::Lit(lit) => Expr::Lit(f.fold_literal(lit)),
+        Expr::Message { receiver, selector, span } => Expr::Message {
+            receiver: Box::new(f.fold_expr(*receiver)),
+            selector: f.fold_msg(selector),
+            span,
+        },
+        Expr::Cascade { receiver, messages, span } => Expr::Cascade {
+            receiver: Box::new(f.fold_expr(*receiver)),
+            messages: messages.into_iter().map(|m| f.fold_msg(m)).collect(),
+            span,
+        },
+        Expr::Block { args, temps, statements, span } => Expr::Block {
+            args: fold_idents(f, args),
+            temps: fold_idents(f, temps),
+            statements: fold_statements(f, statements),
+            span,
+        },
+        Expr::Brace { elements, span } => Expr::Brace {
+            elements: elements.into_iter().map(|e| f.fold_expr(e)).collect(),
+            span,
+        },
+        Expr::Method(m) => Expr::Method(f.fold_method(m)),
+        e @ Expr::Pseudo(..) | e @ Expr::Error(_) => e,
+    }
+}
+
+pub fn fold_msg<F: Fold + ?Sized>(f: &mut F, m: Msg) -> Msg {
+    match m {
+        Msg::Unary(sel) => Msg::Unary(sel),
+        Msg::Binary(sel, arg) => Msg::Binary(sel, Box::new(f.fold_expr(*arg))),
+        Msg::Kwargs(kws) => Msg::Kwargs(kws.into_iter().map(|kw| f.fold_keyword(kw)).collect()),
+    }
+}
+
+pub fn fold_keyword<F: Fold + ?Sized>(f: &mut F, k: Keyword) -> Keyword {
+    Keyword { id: k.id, val: f.fold_expr(k.val) }
+}
+
+pub fn fold_literal<F: Fold + ?Sized>(f: &mut F, l: Literal) -> Literal {
+    match l {
+        Literal::Array(elems, span) =>
+            Literal::Array(elems.into_iter().map(|l| f.fold_literal(l)).collect(), span),
+        l => l,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::{parse_chunks, parse_method};
+
+    // Collects the names of the variables, in the order they are visited.
+    struct Names(Vec<String>);
+
+    impl Visitor for Names {
+        fn visit_ident(&mut self, id: &Ident) {
+            self.0.push(id.0.clone());
+        }
+    }
+
+    #[test]
+    fn test_visit_every_variable() {
+        let m = parse_method("at: i put: x | t | t <- [:a | | b | a + i]. ^#(1 (2)) , x")
+            .unwrap_or_else(|e| panic!("{}", e));
+        let mut names = Names(Vec::new());
+        names.visit_method(&m);
+        assert_eq!(names.0, vec!["i", "x", "t", "t", "a", "b", "a", "i", "x"]);
+    }
+
+    #[test]
+    fn test_visit_chunks() {
+        let src = "Object subclass: #Point
+    instanceVariableNames: 'x y' classVariableNames: '' category: 'Graphics'!
+
+!Point methodsFor: 'arithmetic'!
++ delta
+    ^delta! !
+Transcript show: done!";
+        let mut names = Names(Vec::new());
+        for chunk in &parse_chunks(src).unwrap() {
+            names.visit_chunk(chunk);
+        }
+        assert_eq!(names.0, vec!["delta", "delta", "Transcript", "done"]);
+    }
+
+    // Counts the literals, including the elements of literal arrays.
+    struct Literals(usize);
+
+    impl VisitorMut for Literals {
+        fn visit_literal_mut(&mut self, l: &mut Literal) {
+            self.0 += 1;
+            walk_literal_mut(self, l);
+        }
+    }
+
+    #[test]
+    fn test_visit_mut_walks_literals() {
+        let mut m = parse_method("foo ^#(1 #(2 3) $a) , 'b'").unwrap();
+        let mut literals = Literals(0);
+        literals.visit_method_mut(&mut m);
+        assert_eq!(literals.0, 7);
+    }
+
+    // Replaces `a + b` on two literal integers with their sum.
+    struct AddConstants;
+
+    impl Fold for AddConstants {
+        fn fold_expr(&mut self, e: Expr) -> Expr {
+            match fold_expr(self, e) {
+                Expr::Message {
+                    receiver,
+                    selector: Msg::Binary(ref sel, ref arg),
+                    span,
+                } if sel.as_str() == "+" => match (&*receiver, &**arg) {
+                    (&Expr::Lit(Literal::Number(ref a, _)), &Expr::Lit(Literal::Number(ref b, _))) => {
+                        let sum = a.integer.parse::<i64>().unwrap() + b.integer.parse::<i64>().unwrap();
+                        Expr::Lit(Literal::Number(Num::int_from_str(&sum.to_string()), span))
+                    }
+                    _ => Expr::Message { receiver, selector: Msg::Binary(sel.clone(), arg.clone()), span },
+                },
+                e => e,
+            }
+        }
+    }
+
+    #[test]
+    fn test_fold_rebuilds_tree() {
+        let m = parse_method("foo ^[(1 + 2) + 3] value: x + 4").unwrap();
+        let folded = AddConstants.fold_method(m);
+        assert_eq_ignoring_spans!(folded, parse_method("foo ^[6] value: x + 4").unwrap());
     }
 }